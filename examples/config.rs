@@ -30,7 +30,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("  ID: {}", trojan.id);
                     println!("  Server port: {}", trojan.server_port);
                     println!("  Allow insecure: {}", trojan.allow_insecure);
-                    if let Some(ref name) = trojan.server_name {
+                    if let Some(name) = trojan.primary_server_name() {
                         println!("  Server name: {}", name);
                     }
                 }
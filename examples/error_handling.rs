@@ -46,6 +46,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("   Message: {}", message);
             println!("   URL: {}", url);
         }
+        Err(ApiError::AuthError { status_code, .. }) => {
+            println!(
+                "   Authentication failed (status {}), check the API token",
+                status_code
+            );
+        }
+        Err(ApiError::RateLimited { retry_after, .. }) => {
+            println!("   Rate limited, retry after {:?}", retry_after);
+        }
         Err(ApiError::NetworkError { message, url, .. }) => {
             println!("   Network error!");
             println!("   Message: {}", message);
@@ -2,6 +2,7 @@
 //!
 //! Run with: cargo run --example heartbeat
 
+use futures_util::StreamExt;
 use server_r_client::{ApiClient, Config, NodeType};
 use std::time::Duration;
 
@@ -35,14 +36,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Err(e) => println!("Failed to send heartbeat with IP: {}", e),
     }
 
-    // Periodic heartbeat example
+    // Periodic heartbeat with backoff on repeated failure
     println!("\n--- Periodic heartbeat (3 times) ---");
-    for i in 1..=3 {
-        match client.heartbeat(node_type, register_id).await {
+    let beats = client
+        .heartbeat_loop(node_type, register_id, Duration::from_secs(1))
+        .take(3);
+    tokio::pin!(beats);
+    let mut i = 0;
+    while let Some(result) = beats.next().await {
+        i += 1;
+        match result {
             Ok(()) => println!("Heartbeat #{} sent", i),
             Err(e) => println!("Heartbeat #{} failed: {}", i, e),
         }
-        tokio::time::sleep(Duration::from_secs(1)).await;
     }
 
     Ok(())
@@ -0,0 +1,1398 @@
+//! Synchronous API client
+//!
+//! Available with the `blocking` feature. Mirrors [`crate::ApiClient`] on top of
+//! `reqwest::blocking`, sharing [`Config`], the models and [`ApiError`].
+//!
+//! Like `reqwest::blocking`, this client must not be used from within an async
+//! runtime.
+//!
+//! ```rust,no_run
+//! use server_r_client::blocking::ApiClient;
+//! use server_r_client::{Config, NodeType};
+//!
+//! let client = ApiClient::new(Config::new("https://api.example.com", "your-api-token"))?;
+//! client.heartbeat(NodeType::Trojan, "your-register-id")?;
+//! # Ok::<(), server_r_client::ApiError>(())
+//! ```
+
+use reqwest::blocking::{Client as HttpClient, RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
+
+use crate::client::{
+    apply_path_prefix, apply_token_rotation, body_parse_error, build_proxy, build_url,
+    check_body_len, check_content_length, config_cache_tmp_path, config_parser, date_header_skew,
+    encode_json_lines, export_client_state, gzip, import_client_state, parse_api_response,
+    parse_ip_echo, parse_retry_after, record_clock_skew, sni_target, submit_result,
+    track_registration, unix_time_skew, unless_not_modified, untrack_registration,
+    warm_cache_failures, Config, Encoding, IpFamily, Registrations, SubmitFormat,
+    IDEMPOTENCY_KEY_HEADER, JSON_LINES_CONTENT_TYPE, REQUEST_COMPRESSION_THRESHOLD,
+};
+use crate::endpoints::Endpoints;
+use crate::error::{ApiError, Result};
+use crate::etag_cache::{CacheKey, EtagCache};
+#[cfg(feature = "metrics")]
+use crate::metrics::{LatencyRecorder, LatencyStats};
+use crate::models::*;
+use crate::observer::redact_token;
+use crate::parser::ConfigParser;
+use crate::retry_budget::RetryCredits;
+
+/// Blocking API Client for xflash-panda server
+#[derive(Clone)]
+pub struct ApiClient {
+    config: Config,
+    base_url: String,
+    http_client: HttpClient,
+    etag_cache: Arc<Mutex<EtagCache>>,
+    token: Arc<RwLock<String>>,
+    config_parsers: Arc<HashMap<String, Arc<dyn ConfigParser>>>,
+    sync_tokens: Arc<Mutex<HashMap<CacheKey, String>>>,
+    registrations: Arc<Registrations>,
+    clock_skew: Arc<Mutex<Option<Duration>>>,
+    retry_credits: Option<Arc<RetryCredits>>,
+    #[cfg(feature = "metrics")]
+    latency: Arc<Mutex<LatencyRecorder>>,
+}
+
+impl ApiClient {
+    /// Create a new blocking API client
+    pub fn new(config: Config) -> Result<Self> {
+        config.validate()?;
+
+        let mut builder = HttpClient::builder()
+            .timeout(config.timeout)
+            .local_address(config.ip_family.local_address());
+        if let Some(connect_timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        builder = match &config.proxy {
+            Some(proxy) => builder.proxy(build_proxy(proxy)?),
+            None => builder.no_proxy(),
+        };
+        let mut base_url = config.api_host.trim_end_matches('/').to_string();
+
+        if let Some(sni) = &config.sni_override {
+            let (url, resolver) = sni_target(&config.api_host, sni, config.ip_family)?;
+            builder = builder.dns_resolver(Arc::new(resolver));
+            base_url = url;
+        }
+        let base_url = apply_path_prefix(&base_url, config.path_prefix.as_deref());
+
+        let http_client = builder
+            .build()
+            .map_err(|e| ApiError::config_error(format!("Failed to create HTTP client: {}", e)))?;
+
+        Ok(Self::from_parts(config, base_url, http_client))
+    }
+
+    /// Create a client that sends requests through an existing HTTP client
+    ///
+    /// Transport settings such as the timeouts come from `http_client`, so
+    /// `Config::sni_override`, `Config::proxy` and `Config::connect_timeout`
+    /// must be unset and `Config::ip_family` must be `Both`.
+    pub fn with_http_client(config: Config, http_client: HttpClient) -> Result<Self> {
+        config.validate()?;
+
+        if config.sni_override.is_some()
+            || config.proxy.is_some()
+            || config.connect_timeout.is_some()
+            || config.ip_family != IpFamily::Both
+        {
+            return Err(ApiError::config_error(
+                "sni_override, proxy, connect_timeout and ip_family cannot be used with an injected HTTP client",
+            ));
+        }
+
+        let base_url = apply_path_prefix(
+            config.api_host.trim_end_matches('/'),
+            config.path_prefix.as_deref(),
+        );
+
+        Ok(Self::from_parts(config, base_url, http_client))
+    }
+
+    /// Parse configs for `node_type` with `parser` instead of the built-in parser
+    ///
+    /// `node_type` is matched case-insensitively against the node type name,
+    /// e.g. `"trojan"`; other names are fetched with
+    /// [`config_by_name`](Self::config_by_name). Registering a type again
+    /// replaces the earlier parser.
+    pub fn with_config_parser(
+        mut self,
+        node_type: impl Into<String>,
+        parser: Box<dyn ConfigParser>,
+    ) -> Self {
+        Arc::make_mut(&mut self.config_parsers)
+            .insert(node_type.into().to_ascii_lowercase(), Arc::from(parser));
+        self
+    }
+
+    /// Assemble a client from validated parts
+    fn from_parts(config: Config, base_url: String, http_client: HttpClient) -> Self {
+        let etag_cache = EtagCache::new(config.etag_cache_capacity);
+        let token = Arc::new(RwLock::new(config.token.clone()));
+        let retry_credits = config.retry_budget.map(|b| Arc::new(RetryCredits::new(b)));
+
+        Self {
+            config,
+            base_url,
+            http_client,
+            etag_cache: Arc::new(Mutex::new(etag_cache)),
+            token,
+            config_parsers: Arc::new(HashMap::new()),
+            sync_tokens: Arc::new(Mutex::new(HashMap::new())),
+            registrations: Arc::new(Mutex::new(HashMap::new())),
+            clock_skew: Arc::new(Mutex::new(None)),
+            retry_credits,
+            #[cfg(feature = "metrics")]
+            latency: Arc::new(Mutex::new(LatencyRecorder::default())),
+        }
+    }
+
+    /// Lock the ETag cache, recovering from a poisoned lock
+    fn cache(&self) -> MutexGuard<'_, EtagCache> {
+        self.etag_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Lock the stored user sync tokens, recovering from a poisoned lock
+    fn sync_tokens(&self) -> MutexGuard<'_, HashMap<CacheKey, String>> {
+        self.sync_tokens
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Drop the cached ETag and sync token of a registration
+    fn forget_user_state(&self, cache_key: &CacheKey) {
+        self.cache().remove(cache_key);
+        self.sync_tokens().remove(cache_key);
+    }
+
+    /// Build URL with query parameters
+    fn build_url(&self, path: &str, params: &[(&str, &str)]) -> Result<String> {
+        let token = self.token.read().unwrap_or_else(|p| p.into_inner());
+        build_url(
+            &self.base_url,
+            &token,
+            path,
+            params,
+            self.config.query_encoding,
+        )
+    }
+
+    /// Make a GET request
+    fn get(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Response> {
+        let url = self.build_url(path, params)?;
+
+        if self.config.debug {
+            debug!("GET {}", url);
+        }
+
+        let request = self
+            .http_client
+            .get(&url)
+            .header("Content-Type", "application/json");
+        let response = self.send(node_type, request, "GET", &url)?;
+
+        self.check_panel_response(response, &url)
+    }
+
+    /// Make a GET request with ETag support
+    ///
+    /// With `revalidate` unset the cached ETag is not sent, but a fresh ETag
+    /// is still stored.
+    fn get_with_etag(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        params: &[(&str, &str)],
+        cache_key: &CacheKey,
+        revalidate: bool,
+    ) -> Result<Response> {
+        let url = self.build_url(path, params)?;
+
+        if self.config.debug {
+            debug!("GET (with ETag) {}", url);
+        }
+
+        let etag = if revalidate {
+            self.cache().get(cache_key)
+        } else {
+            None
+        };
+
+        let mut request = self
+            .http_client
+            .get(&url)
+            .header("Content-Type", "application/json");
+
+        if let Some(etag) = &etag {
+            request = request.header("If-None-Match", etag);
+        }
+
+        let response = self.send(node_type, request, "GET", &url)?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            self.rotate_token(response.headers(), &url);
+            return Err(ApiError::not_modified(&url));
+        }
+
+        let response = self.check_panel_response(response, &url)?;
+
+        // Store the new ETag if present; failed responses never get here
+        if let Some(new_etag) = response.headers().get("ETag") {
+            if let Ok(etag_str) = new_etag.to_str() {
+                self.cache().insert(cache_key.clone(), etag_str.to_string());
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Make a POST request with JSON body
+    fn post<T: serde::Serialize>(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        params: &[(&str, &str)],
+        body: &T,
+    ) -> Result<Response> {
+        self.post_encoded(node_type, path, params, body, Encoding::Json, &[])
+    }
+
+    /// Make a traffic submission POST using the configured content encoding
+    fn post_submission<T: serde::Serialize>(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        body: &T,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        self.post_encoded(
+            node_type,
+            path,
+            &[],
+            body,
+            self.config.content_encoding,
+            headers,
+        )
+    }
+
+    /// Submit per-user traffic with an `Idempotency-Key`
+    ///
+    /// Uses the caller's key from `headers` if there is one; otherwise a new
+    /// UUID is generated for this call.
+    fn submit_traffic(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        request: &SubmitRequest,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        if headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(IDEMPOTENCY_KEY_HEADER))
+        {
+            return self.send_traffic(node_type, path, request, headers);
+        }
+
+        let key = uuid::Uuid::new_v4().to_string();
+        let mut with_key = headers.to_vec();
+        with_key.push((IDEMPOTENCY_KEY_HEADER, &key));
+
+        self.send_traffic(node_type, path, request, &with_key)
+    }
+
+    /// Submit per-user traffic using the configured field style and encoding
+    fn send_traffic(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        request: &SubmitRequest,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        if self.config.submit_format == SubmitFormat::JsonLines {
+            let url = self.build_url(path, &[("register_id", &request.register_id)])?;
+            let body = match self.config.traffic_field_style {
+                TrafficFieldStyle::Short => encode_json_lines(&request.data, &url)?,
+                TrafficFieldStyle::Verbose => encode_json_lines(
+                    &request
+                        .map_data(|t| UserTrafficVerbose::from(t.clone()))
+                        .data,
+                    &url,
+                )?,
+            };
+
+            if self.config.debug {
+                debug!("POST {} body: {}", url, String::from_utf8_lossy(&body));
+            }
+
+            return self.post_bytes(node_type, &url, body, JSON_LINES_CONTENT_TYPE, headers);
+        }
+
+        match self.config.traffic_field_style {
+            TrafficFieldStyle::Short => self.post_submission(node_type, path, &request, headers),
+            TrafficFieldStyle::Verbose => {
+                let request = request.map_data(|t| UserTrafficVerbose::from(t.clone()));
+                self.post_submission(node_type, path, &request, headers)
+            }
+        }
+    }
+
+    /// Make a POST request with an encoded body and extra headers
+    fn post_encoded<T: serde::Serialize>(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        params: &[(&str, &str)],
+        body: &T,
+        encoding: Encoding,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        let url = self.build_url(path, params)?;
+
+        let body = encoding.encode(body, &url)?;
+
+        if self.config.debug {
+            if encoding == Encoding::Json {
+                debug!("POST {} body: {}", url, String::from_utf8_lossy(&body));
+            } else {
+                debug!("POST {} ({} byte body)", url, body.len());
+            }
+        }
+
+        self.post_bytes(node_type, &url, body, encoding.content_type(), headers)
+    }
+
+    /// POST an already encoded body to a fully built URL
+    fn post_bytes(
+        &self,
+        node_type: Option<NodeType>,
+        url: &str,
+        mut body: Vec<u8>,
+        content_type: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        let mut request = self
+            .http_client
+            .post(url)
+            .header("Content-Type", content_type);
+
+        if self.config.request_compression && body.len() > REQUEST_COMPRESSION_THRESHOLD {
+            body = gzip(&body, url)?;
+            request = request.header("Content-Encoding", "gzip");
+        }
+
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let response = self.send(node_type, request.body(body), "POST", url)?;
+
+        self.check_panel_response(response, url)
+    }
+
+    /// Send a request, notifying the configured observer
+    ///
+    /// `node_type` selects the timeout override from `Config::timeout_overrides`.
+    fn send(
+        &self,
+        node_type: Option<NodeType>,
+        request: RequestBuilder,
+        method: &str,
+        url: &str,
+    ) -> Result<Response> {
+        let request = match node_type.and_then(|nt| self.config.timeout_overrides.get(&nt)) {
+            Some(timeout) => request.timeout(*timeout),
+            None => request,
+        };
+        let observer = self.config.observer.as_deref();
+        if let Some(observer) = observer {
+            observer.on_request(method, &redact_token(url));
+        }
+        if let Some(credits) = &self.retry_credits {
+            credits.deposit();
+        }
+
+        let started = Instant::now();
+        let response = request
+            .send()
+            .map_err(|e| ApiError::network_error(e.to_string(), url, Some(e)))?;
+
+        let elapsed = started.elapsed();
+
+        #[cfg(feature = "metrics")]
+        self.latency
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .record(url, elapsed);
+
+        if let Some(observer) = observer {
+            observer.on_response(
+                response.status().as_u16(),
+                elapsed,
+                response.content_length(),
+            );
+        }
+
+        Ok(response)
+    }
+
+    /// Switch to a panel-issued token, if the response carries one
+    fn rotate_token(&self, headers: &HeaderMap, url: &str) {
+        apply_token_rotation(
+            headers,
+            url,
+            &self.base_url,
+            &self.token,
+            self.config.observer.as_deref(),
+        );
+    }
+
+    /// Check a panel response, applying token rotation and tracking clock skew
+    fn check_panel_response(&self, response: Response, url: &str) -> Result<Response> {
+        self.rotate_token(response.headers(), url);
+        let response = self.check_response(response, url)?;
+        if let Some(skew) = date_header_skew(response.headers()) {
+            record_clock_skew(&self.clock_skew, skew, &self.config);
+        }
+        Ok(response)
+    }
+
+    /// Check response status and handle errors
+    fn check_response(&self, response: Response, url: &str) -> Result<Response> {
+        let status = response.status();
+
+        if status.is_success() {
+            Ok(response)
+        } else if status == StatusCode::NOT_MODIFIED {
+            Err(ApiError::not_modified(url))
+        } else if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = parse_retry_after(response.headers());
+            error!("API rate limited: retry after {:?} - {}", retry_after, url);
+            Err(ApiError::RateLimited {
+                retry_after,
+                url: url.to_string(),
+            })
+        } else {
+            let status_code = status.as_u16();
+            let message = match self.read_body(response, url) {
+                Ok((_, body)) => String::from_utf8_lossy(&body).into_owned(),
+                Err(e @ ApiError::ResponseTooLarge { .. }) => return Err(e),
+                Err(_) => "Unknown error".to_string(),
+            };
+
+            error!("API error: {} - {} - {}", status_code, message, url);
+            Err(ApiError::from_status_code(status_code, message, url))
+        }
+    }
+
+    /// Read the full response body, enforcing `Config::max_response_bytes`
+    fn read_body(&self, response: Response, path: &str) -> Result<(u16, Vec<u8>)> {
+        let status = response.status().as_u16();
+
+        let Some(limit) = self.config.max_response_bytes else {
+            let bytes = response
+                .bytes()
+                .map_err(|e| ApiError::network_error(e.to_string(), path, Some(e)))?;
+            return Ok((status, bytes.to_vec()));
+        };
+
+        check_content_length(response.content_length(), limit, path)?;
+
+        // Read one byte past the limit to detect oversized bodies
+        let mut body = Vec::new();
+        response
+            .take(limit as u64 + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| ApiError::network_error(e.to_string(), path, None))?;
+        check_body_len(body.len(), limit, path)?;
+
+        Ok((status, body))
+    }
+
+    /// Pass a successful response's `message` to the warning observer
+    fn notify_warning<T>(&self, response: &ApiResponse<T>) {
+        if let Some(observer) = &self.config.warning_observer {
+            observer.notify(response.message.as_deref());
+        }
+    }
+
+    /// Finish a request whose response data is unused
+    ///
+    /// The body is only read when a warning observer wants its `message`.
+    fn discard_body(&self, response: Response, path: &str) -> Result<()> {
+        let Some(observer) = &self.config.warning_observer else {
+            return Ok(());
+        };
+
+        #[derive(serde::Deserialize)]
+        struct Envelope {
+            #[serde(default)]
+            message: Option<String>,
+        }
+
+        let (_, bytes) = self.read_body(response, path)?;
+        if let Ok(envelope) = serde_json::from_slice::<Envelope>(&bytes) {
+            observer.notify(envelope.message.as_deref());
+        }
+        Ok(())
+    }
+
+    /// Read a submission response into its acknowledgement
+    fn read_ack(&self, response: Response, path: &str) -> Result<SubmitAck> {
+        let (status, bytes) = self.read_body(response, path)?;
+        let api_response: ApiResponse<Option<SubmitAck>> =
+            parse_api_response(&bytes, status, path)?;
+        self.notify_warning(&api_response);
+
+        let ack = api_response.data.unwrap_or_default();
+        if let Some(skew) = ack.server_time.and_then(unix_time_skew) {
+            record_clock_skew(&self.clock_skew, skew, &self.config);
+        }
+        Ok(ack)
+    }
+
+    // ==================== Configuration APIs ====================
+
+    /// Get raw node configuration
+    pub fn raw_config(&self, node_type: NodeType, node_id: impl Into<NodeId>) -> Result<Vec<u8>> {
+        let path = Endpoints::config(node_type);
+        let node_id_str = node_id.into().checked()?.to_string();
+        let params = [("node_id", node_id_str.as_str())];
+
+        let response = self.get(Some(node_type), &path, &params)?;
+        let (_, bytes) = self.read_body(response, &path)?;
+
+        Ok(bytes)
+    }
+
+    /// Get parsed node configuration (enhanced)
+    pub fn config(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+    ) -> Result<NodeConfigEnum> {
+        self.config_by_name(node_type.as_str(), node_id)
+    }
+
+    /// Get parsed node configuration for a node type given by name
+    ///
+    /// Blocking counterpart of
+    /// [`ApiClient::config_by_name`](crate::ApiClient::config_by_name).
+    pub fn config_by_name(
+        &self,
+        node_type: &str,
+        node_id: impl Into<NodeId>,
+    ) -> Result<NodeConfigEnum> {
+        let name = node_type.to_ascii_lowercase();
+        let known = name.parse::<NodeType>().ok();
+        let path = match known {
+            Some(node_type) => Endpoints::config(node_type),
+            None => Endpoints::config_by_name(&name)?,
+        };
+        let node_id_str = node_id.into().checked()?.to_string();
+        let params = [("node_id", node_id_str.as_str())];
+
+        let response = self.get(known, &path, &params)?;
+        let (status, bytes) = self.read_body(response, &path)?;
+
+        let api_response: ApiResponse<serde_json::Value> =
+            parse_api_response(&bytes, status, &path)?;
+
+        self.notify_warning(&api_response);
+
+        let config_bytes = config_data_bytes(&api_response.data, &path)?;
+
+        match config_parser(&self.config_parsers, &name) {
+            Some(parser) => parser.parse(&config_bytes),
+            None => parse_config_by_name(&name, &config_bytes),
+        }
+    }
+
+    /// Get the node configuration, falling back to a cache file
+    ///
+    /// Blocking counterpart of
+    /// [`ApiClient::config_or_cached`](crate::ApiClient::config_or_cached).
+    pub fn config_or_cached(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        cache_path: impl AsRef<Path>,
+    ) -> Result<NodeConfigEnum> {
+        let cache_path = cache_path.as_ref();
+
+        match self.config(node_type, node_id) {
+            Ok(config) => {
+                if let Err(e) = write_config_cache(cache_path, &config) {
+                    warn!(
+                        "failed to write config cache {}: {}",
+                        cache_path.display(),
+                        e
+                    );
+                }
+                Ok(config)
+            }
+            Err(e) if e.is_network_error() || e.is_timeout() => {
+                let Ok(cached) = std::fs::read(cache_path) else {
+                    return Err(e);
+                };
+                warn!(
+                    "panel unreachable ({}), using cached config {}",
+                    e,
+                    cache_path.display()
+                );
+                parse_raw_config_response(node_type, &cached)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fetch the node configuration and then the user list
+    ///
+    /// The users half is `None` if the cached list is still current.
+    pub fn bootstrap(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        register_id: &str,
+    ) -> Result<(NodeConfigEnum, Option<Vec<User>>)> {
+        let config = self.config(node_type, node_id)?;
+        let users = unless_not_modified(self.users(node_type, register_id))?;
+        Ok((config, users))
+    }
+
+    // ==================== Node Management APIs ====================
+
+    /// Register a node with the server
+    pub fn register(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        request: RegisterRequest,
+    ) -> Result<String> {
+        let data = self.register_full(node_type, node_id, request)?;
+        Ok(data.register_id)
+    }
+
+    /// Register a node and return the full registration metadata
+    pub fn register_full(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        request: RegisterRequest,
+    ) -> Result<RegisterResponseData> {
+        let path = Endpoints::register(node_type);
+        let node_id_str = node_id.into().checked()?.to_string();
+        let params = [("node_id", node_id_str.as_str())];
+        self.config.check_register_request(&request)?;
+
+        let response = self.post(Some(node_type), &path, &params, &request)?;
+        let (_, bytes) = self.read_body(response, &path)?;
+        let api_response: RegisterResponse =
+            serde_json::from_slice(&bytes).map_err(|e| body_parse_error(e, &path, &bytes))?;
+        self.notify_warning(&api_response);
+
+        // A fresh registration must not resume a recycled id's ETag or sync token
+        self.forget_user_state(&CacheKey::new(node_type, &api_response.data.register_id));
+        track_registration(
+            &self.registrations,
+            node_type,
+            &api_response.data.register_id,
+        );
+
+        Ok(api_response.data)
+    }
+
+    /// Register several nodes in one request using the panel's `node_ids` form
+    ///
+    /// Returns `(node_id, register_id)` pairs in the order the panel lists
+    /// them. Errors carry the batch's node_ids in their message.
+    pub fn register_multi(
+        &self,
+        node_type: NodeType,
+        node_ids: &[i64],
+        request: RegisterRequest,
+    ) -> Result<Vec<(i64, String)>> {
+        if node_ids.is_empty() {
+            return Err(ApiError::config_error("node_ids must not be empty"));
+        }
+        for &node_id in node_ids {
+            NodeId::new(node_id)?;
+        }
+        self.config.check_register_request(&request)?;
+
+        let path = Endpoints::register(node_type);
+        let context = format!("register node_ids {:?}", node_ids);
+        let request = RegisterMultiRequest::new(node_ids.to_vec(), request);
+
+        let send = || {
+            let response = self.post(Some(node_type), &path, &[], &request)?;
+            let (status, bytes) = self.read_body(response, &path)?;
+            parse_api_response::<Vec<RegisterMultiEntry>>(&bytes, status, &path)
+        };
+        let api_response = send().map_err(|e| e.with_context(&context))?;
+        self.notify_warning(&api_response);
+
+        for entry in &api_response.data {
+            self.forget_user_state(&CacheKey::new(node_type, &entry.register_id));
+            track_registration(&self.registrations, node_type, &entry.register_id);
+        }
+
+        Ok(api_response
+            .data
+            .into_iter()
+            .map(|entry| (entry.node_id, entry.register_id))
+            .collect())
+    }
+
+    /// Unregister a node
+    pub fn unregister(&self, node_type: NodeType, register_id: &str) -> Result<()> {
+        let path = Endpoints::unregister(node_type);
+        let params = [("register_id", register_id)];
+
+        // Empty body for unregister
+        let empty: HashMap<String, String> = HashMap::new();
+        let response = self.post(Some(node_type), &path, &params, &empty)?;
+        self.forget_user_state(&CacheKey::new(node_type, register_id));
+        untrack_registration(&self.registrations, node_type, register_id);
+
+        self.discard_body(response, &path)
+    }
+
+    /// Unregister several nodes, attempting every entry
+    pub fn unregister_many(
+        &self,
+        entries: &[(NodeType, &str)],
+    ) -> Vec<(NodeType, String, Result<()>)> {
+        entries
+            .iter()
+            .map(|&(node_type, register_id)| {
+                let result = self.unregister(node_type, register_id);
+                (node_type, register_id.to_string(), result)
+            })
+            .collect()
+    }
+
+    /// Verify if a register_id is valid
+    pub fn verify(&self, node_type: NodeType, register_id: &str) -> Result<bool> {
+        Ok(self.verify_detailed(node_type, register_id)?.valid)
+    }
+
+    /// Verify a register_id, including the panel's reason when it is invalid
+    pub fn verify_detailed(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<VerifyResponseData> {
+        let path = Endpoints::verify(node_type);
+        let request = VerifyRequest::new(register_id);
+
+        let response = self.post(Some(node_type), &path, &[], &request)?;
+        let (_, bytes) = self.read_body(response, &path)?;
+        let api_response: VerifyResponse =
+            serde_json::from_slice(&bytes).map_err(|e| body_parse_error(e, &path, &bytes))?;
+        self.notify_warning(&api_response);
+
+        Ok(api_response.data)
+    }
+
+    /// Verify several register_ids sequentially, in input order
+    pub fn verify_many(
+        &self,
+        node_type: NodeType,
+        register_ids: &[&str],
+    ) -> Vec<(String, Result<bool>)> {
+        register_ids
+            .iter()
+            .map(|&register_id| (register_id.to_string(), self.verify(node_type, register_id)))
+            .collect()
+    }
+
+    /// Make sure a registration is valid, re-registering if needed
+    pub fn ensure_registered(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        register_id: &str,
+        request: RegisterRequest,
+    ) -> Result<String> {
+        if self.verify(node_type, register_id)? {
+            return Ok(register_id.to_string());
+        }
+
+        self.forget_user_state(&CacheKey::new(node_type, register_id));
+        untrack_registration(&self.registrations, node_type, register_id);
+        self.register(node_type, node_id, request)
+    }
+
+    // ==================== User Management APIs ====================
+
+    /// Get raw users data with ETag caching support
+    pub fn raw_users(&self, node_type: NodeType, register_id: &str) -> Result<Vec<u8>> {
+        let path = Endpoints::users(node_type);
+        let params = [("register_id", register_id)];
+        let cache_key = CacheKey::new(node_type, register_id);
+
+        let response = self.get_with_etag(Some(node_type), &path, &params, &cache_key, true)?;
+        let (_, bytes) = self.read_body(response, &path)?;
+
+        Ok(bytes)
+    }
+
+    /// Get parsed user list
+    pub fn users(&self, node_type: NodeType, register_id: &str) -> Result<Vec<User>> {
+        Ok(self.users_with_etag(node_type, register_id)?.data)
+    }
+
+    /// Get the user list, bypassing the cached ETag
+    ///
+    /// Sends no `If-None-Match`, so the panel always returns the full list.
+    /// The fresh ETag replaces the cached one.
+    pub fn users_force(&self, node_type: NodeType, register_id: &str) -> Result<Vec<User>> {
+        Ok(self.fetch_users(node_type, register_id, false)?.data)
+    }
+
+    /// Get users as an iterator that parses entries on demand
+    pub fn users_iter(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<impl Iterator<Item = Result<User>>> {
+        let path = Endpoints::users(node_type);
+        let params = [("register_id", register_id)];
+        let cache_key = CacheKey::new(node_type, register_id);
+
+        let response = self.get_with_etag(Some(node_type), &path, &params, &cache_key, true)?;
+        let (status, bytes) = self.read_body(response, &path)?;
+        let api_response: ApiResponse<Box<RawValue>> = parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+        drop(bytes);
+
+        UserArrayIter::new(api_response.data, path)
+    }
+
+    /// Get users with ETag information
+    pub fn users_with_etag(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<UsersResponse<Vec<User>>> {
+        self.fetch_users(node_type, register_id, true)
+    }
+
+    /// Get users, reporting an unchanged list instead of failing
+    ///
+    /// Distinguishes a fresh list (possibly empty, see
+    /// [`UsersResponse::is_empty`]) from a 304, which yields
+    /// [`UsersResponse::is_not_modified`] with the cached ETag and empty
+    /// `data` that must be ignored.
+    pub fn users_with_status(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<UsersResponse<Vec<User>>> {
+        match self.users_with_etag(node_type, register_id) {
+            Err(ApiError::NotModified { .. }) => {
+                let etag = self.cache().get(&CacheKey::new(node_type, register_id));
+                Ok(UsersResponse::unchanged(Vec::new(), etag))
+            }
+            result => result,
+        }
+    }
+
+    /// Fetch and parse the user list with its ETag
+    fn fetch_users(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        revalidate: bool,
+    ) -> Result<UsersResponse<Vec<User>>> {
+        let path = Endpoints::users(node_type);
+        let params = [("register_id", register_id)];
+        let cache_key = CacheKey::new(node_type, register_id);
+
+        let response =
+            self.get_with_etag(Some(node_type), &path, &params, &cache_key, revalidate)?;
+
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        let (status, bytes) = self.read_body(response, &path)?;
+        let api_response: ApiResponse<Vec<User>> = parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+
+        Ok(UsersResponse::new(api_response.data, etag))
+    }
+
+    /// Fetch the users changed since the previous sync
+    ///
+    /// The panel's `next_token` is remembered per registration and sent as
+    /// `sync_token` on the next call; without a stored token the full list is
+    /// fetched.
+    pub fn users_sync(&self, node_type: NodeType, register_id: &str) -> Result<UserSync> {
+        let path = Endpoints::users(node_type);
+        let cache_key = CacheKey::new(node_type, register_id);
+        let sync_token = self.sync_tokens().get(&cache_key).cloned();
+
+        let mut params = vec![("register_id", register_id)];
+        if let Some(sync_token) = &sync_token {
+            params.push(("sync_token", sync_token));
+        }
+
+        let response = self.get(Some(node_type), &path, &params)?;
+        let (status, bytes) = self.read_body(response, &path)?;
+        let api_response: ApiResponse<UserSync> = parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+        let sync = api_response.data;
+
+        let mut sync_tokens = self.sync_tokens();
+        if sync.next_token.is_empty() {
+            sync_tokens.remove(&cache_key);
+        } else {
+            sync_tokens.insert(cache_key, sync.next_token.clone());
+        }
+
+        Ok(sync)
+    }
+
+    /// Get the user list only if it changed since the last call
+    pub fn users_changed(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<Option<Vec<User>>> {
+        match self.users(node_type, register_id) {
+            Ok(users) => Ok(Some(users)),
+            Err(ApiError::NotModified { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Prefetch user lists to populate the ETag cache
+    ///
+    /// Every entry is attempted; `NotModified` counts as success. Failed
+    /// registrations are returned with their error in input order.
+    pub fn warm_cache(&self, entries: &[(NodeType, &str)]) -> Vec<(NodeType, String, ApiError)> {
+        let results = entries.iter().map(|&(node_type, register_id)| {
+            let result = self.users_with_etag(node_type, register_id);
+            (node_type, register_id, result)
+        });
+
+        warm_cache_failures(results)
+    }
+
+    // ==================== Traffic/Statistics APIs ====================
+
+    /// Submit user traffic data
+    ///
+    /// Carries a generated `Idempotency-Key`, see [`crate::ApiClient::submit`].
+    pub fn submit(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<()> {
+        let path = Endpoints::submit(node_type);
+
+        let response = self.submit_traffic(
+            Some(node_type),
+            &path,
+            &SubmitRequest::new(register_id, data),
+            &[],
+        )?;
+        self.discard_body(response, &path)
+    }
+
+    /// Submit user traffic data and return the panel's acknowledgement
+    pub fn submit_ack(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<SubmitAck> {
+        let path = Endpoints::submit(node_type);
+
+        let response = self.submit_traffic(
+            Some(node_type),
+            &path,
+            &SubmitRequest::new(register_id, data),
+            &[],
+        )?;
+        self.read_ack(response, &path)
+    }
+
+    /// Submit user traffic data and report which users the panel accepted
+    ///
+    /// Panels that reply without per-user detail, e.g. with `"data": null` or
+    /// `"data": true`, yield an empty result.
+    pub fn submit_detailed(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<SubmitResult> {
+        let path = Endpoints::submit(node_type);
+
+        let response = self.submit_traffic(
+            Some(node_type),
+            &path,
+            &SubmitRequest::new(register_id, data),
+            &[],
+        )?;
+        let (status, bytes) = self.read_body(response, &path)?;
+        let api_response: ApiResponse<serde_json::Value> =
+            parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+
+        submit_result(api_response.data, &path, &bytes)
+    }
+
+    /// Submit user traffic data with an `Idempotency-Key` header
+    pub fn submit_idempotent(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+        key: &str,
+    ) -> Result<()> {
+        let path = Endpoints::submit(node_type);
+
+        let response = self.submit_traffic(
+            Some(node_type),
+            &path,
+            &SubmitRequest::new(register_id, data),
+            &[(IDEMPOTENCY_KEY_HEADER, key)],
+        )?;
+        self.discard_body(response, &path)
+    }
+
+    /// Submit traffic data with agent information
+    ///
+    /// Carries a generated `Idempotency-Key`, see [`crate::ApiClient::submit`].
+    pub fn submit_with_agent(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<()> {
+        let path = Endpoints::submit_with_agent(node_type);
+
+        let response = self.submit_traffic(
+            Some(node_type),
+            &path,
+            &SubmitRequest::new(register_id, data),
+            &[],
+        )?;
+        self.discard_body(response, &path)
+    }
+
+    /// Submit traffic data with agent information and return the acknowledgement
+    pub fn submit_with_agent_ack(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<SubmitAck> {
+        let path = Endpoints::submit_with_agent(node_type);
+
+        let response = self.submit_traffic(
+            Some(node_type),
+            &path,
+            &SubmitRequest::new(register_id, data),
+            &[],
+        )?;
+        self.read_ack(response, &path)
+    }
+
+    /// Submit user traffic data together with the currently online user IDs
+    pub fn submit_with_online(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+        online: Vec<i64>,
+    ) -> Result<()> {
+        let path = Endpoints::submit_with_agent(node_type);
+        let request = SubmitRequest::new(register_id, data).with_online_users(online);
+
+        let response = self.submit_traffic(Some(node_type), &path, &request, &[])?;
+        self.discard_body(response, &path)
+    }
+
+    /// Submit aggregated traffic statistics
+    pub fn submit_stats_with_agent(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: TrafficStats,
+    ) -> Result<()> {
+        let path = Endpoints::submit_stats_with_agent(node_type);
+        let request = SubmitStatsRequest::new(register_id, data);
+
+        let response = self.post_submission(Some(node_type), &path, &request, &[])?;
+        self.discard_body(response, &path)
+    }
+
+    /// Submit aggregated traffic statistics and return the acknowledgement
+    pub fn submit_stats_with_agent_ack(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: TrafficStats,
+    ) -> Result<SubmitAck> {
+        let path = Endpoints::submit_stats_with_agent(node_type);
+        let request = SubmitStatsRequest::new(register_id, data);
+
+        let response = self.post_submission(Some(node_type), &path, &request, &[])?;
+        self.read_ack(response, &path)
+    }
+
+    /// Submit per-user traffic and the statistics derived from it
+    ///
+    /// Posts `data` via `submit_with_agent`, then the matching
+    /// `TrafficStats::from_traffic` via `submit_stats_with_agent`. The stats
+    /// are not sent if the traffic submission fails.
+    pub fn submit_traffic_and_stats(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<()> {
+        let stats = TrafficStats::from_traffic(&data);
+
+        self.submit_with_agent(node_type, register_id, data)?;
+        self.submit_stats_with_agent(node_type, register_id, stats)
+    }
+
+    // ==================== Health Monitoring APIs ====================
+
+    /// Send heartbeat to server
+    pub fn heartbeat(&self, node_type: NodeType, register_id: &str) -> Result<()> {
+        let path = Endpoints::heartbeat(node_type);
+        let request = HeartbeatRequest::new(register_id);
+
+        let response = self.post(Some(node_type), &path, &[], &request)?;
+        self.discard_body(response, &path)
+    }
+
+    /// Send heartbeat with node IP
+    pub fn heartbeat_with_ip(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        node_ip: &str,
+    ) -> Result<()> {
+        let path = Endpoints::heartbeat(node_type);
+        let request = HeartbeatRequest::new(register_id).with_node_ip(node_ip);
+
+        let response = self.post(Some(node_type), &path, &[], &request)?;
+        self.discard_body(response, &path)
+    }
+
+    /// Send heartbeat with the node's current resource usage
+    pub fn heartbeat_with_load(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        load: NodeLoad,
+    ) -> Result<()> {
+        let path = Endpoints::heartbeat(node_type);
+        let request = HeartbeatRequest::new(register_id).with_load(load);
+
+        let response = self.post(Some(node_type), &path, &[], &request)?;
+        self.discard_body(response, &path)
+    }
+
+    // ==================== Utility Methods ====================
+
+    /// Get the token currently used for requests
+    ///
+    /// Starts as `Config::token` and changes when the panel rotates it.
+    pub fn token(&self) -> String {
+        self.token.read().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// Build the URL a request to `path` would use, without sending it
+    ///
+    /// Parameters are encoded exactly as for a real request; the token value
+    /// is replaced with `REDACTED`. A `path` that does not start with `/` or
+    /// carries a query or fragment is a `ConfigError`.
+    pub fn preview_url(&self, path: &str, params: &[(&str, &str)]) -> Result<String> {
+        build_url(
+            &self.base_url,
+            "REDACTED",
+            path,
+            params,
+            self.config.query_encoding,
+        )
+    }
+
+    /// Spend one credit of the shared retry budget before retrying a call
+    ///
+    /// See [`crate::ApiClient::try_acquire_retry`].
+    pub fn try_acquire_retry(&self) -> bool {
+        self.retry_credits
+            .as_ref()
+            .is_none_or(|credits| credits.try_withdraw())
+    }
+
+    /// Get the clock skew measured on the latest successful panel response
+    ///
+    /// See [`crate::ApiClient::last_server_time_skew`].
+    pub fn last_server_time_skew(&self) -> Option<Duration> {
+        *self
+            .clock_skew
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Get per-endpoint request latency, keyed by URL path
+    #[cfg(feature = "metrics")]
+    pub fn latency_snapshot(&self) -> HashMap<String, LatencyStats> {
+        self.latency
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .snapshot()
+    }
+
+    /// Open a connection to `api_host` so later requests reuse it
+    ///
+    /// Sends a `HEAD` request to the base URL, paying DNS resolution and the
+    /// TLS handshake up front. Any HTTP status counts as success; only
+    /// transport failures are returned, and callers may safely ignore them.
+    pub fn warmup(&self) -> Result<()> {
+        let started = Instant::now();
+        let result = self.http_client.head(&self.base_url).send();
+
+        match result {
+            Ok(response) => {
+                debug!(
+                    "warmup to {} returned {} in {:?}",
+                    self.base_url,
+                    response.status(),
+                    started.elapsed()
+                );
+                Ok(())
+            }
+            Err(e) => {
+                debug!("warmup to {} failed: {}", self.base_url, e);
+                Err(ApiError::network_error(
+                    e.to_string(),
+                    &self.base_url,
+                    Some(e),
+                ))
+            }
+        }
+    }
+
+    /// Send a GET request and return the raw response headers
+    ///
+    /// Headers are returned for any HTTP status, so hints such as
+    /// `Retry-After` or `X-RateLimit-Remaining` on a 429 or 503 are readable.
+    /// Only transport failures are reported as errors.
+    pub fn get_headers(&self, path: &str, params: &[(&str, &str)]) -> Result<HeaderMap> {
+        let url = self.build_url(path, params)?;
+
+        if self.config.debug {
+            debug!("GET (headers) {}", url);
+        }
+
+        let request = self.http_client.get(&url);
+        let response = self.send(None, request, "GET", &url)?;
+
+        Ok(response.headers().clone())
+    }
+
+    /// Detect this host's public IP address
+    pub fn detect_public_ip(&self) -> Result<String> {
+        let url = &self.config.ip_echo_url;
+        let response = self
+            .http_client
+            .get(url)
+            .send()
+            .map_err(|e| ApiError::network_error(e.to_string(), url, Some(e)))?;
+        let response = self.check_response(response, url)?;
+        let (_, body) = self.read_body(response, url)?;
+
+        parse_ip_echo(&String::from_utf8_lossy(&body), url)
+    }
+
+    /// Clear the ETag cache
+    pub fn clear_etag_cache(&self) {
+        self.cache().clear();
+    }
+
+    /// Get the current ETag for a cache key
+    pub fn get_etag(&self, node_type: NodeType, register_id: &str) -> Option<String> {
+        let cache_key = CacheKey::new(node_type, register_id);
+        self.cache().peek(&cache_key)
+    }
+
+    /// Remove the cached ETag for a single registration
+    pub fn remove_etag(&self, node_type: NodeType, register_id: &str) -> Option<String> {
+        let cache_key = CacheKey::new(node_type, register_id);
+        self.cache().remove(&cache_key)
+    }
+
+    /// Get the number of entries in the ETag cache
+    pub fn etag_cache_len(&self) -> usize {
+        self.cache().len()
+    }
+
+    /// Get the keys currently present in the ETag cache
+    pub fn etag_cache_keys(&self) -> Vec<String> {
+        self.cache().keys()
+    }
+
+    /// Export the register_ids held by this client and their cached ETags
+    ///
+    /// Blocking counterpart of
+    /// [`ApiClient::export_state`](crate::ApiClient::export_state); the formats
+    /// are interchangeable.
+    pub fn export_state(&self) -> Result<serde_json::Value> {
+        export_client_state(&self.registrations, &self.etag_cache)
+    }
+
+    /// Restore state produced by [`export_state`](Self::export_state)
+    pub fn import_state(&self, state: serde_json::Value) -> Result<()> {
+        import_client_state(&self.registrations, &self.etag_cache, state)
+    }
+}
+
+impl std::fmt::Debug for ApiClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiClient")
+            .field("api_host", &self.config.api_host)
+            .field("timeout", &self.config.timeout)
+            .field("debug", &self.config.debug)
+            .finish()
+    }
+}
+
+/// Write a config to `path`, replacing the file atomically
+fn write_config_cache(path: &Path, config: &NodeConfigEnum) -> std::io::Result<()> {
+    let bytes = config
+        .to_api_response_bytes()
+        .map_err(std::io::Error::other)?;
+    let tmp = config_cache_tmp_path(path);
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, path)
+}
@@ -1,12 +1,128 @@
-use reqwest::{Client as HttpClient, Response, StatusCode};
+use bytes::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::HeaderMap;
+use reqwest::{Client as HttpClient, RequestBuilder, Response, StatusCode};
+use serde_json::value::RawValue;
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
-use tracing::{debug, error};
-
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch, OnceCell, RwLock};
+use tracing::{debug, error, warn};
+
+use crate::endpoints::Endpoints;
 use crate::error::{ApiError, Result};
+use crate::etag_cache::{CacheKey, EtagCache};
+use crate::guard::RegistrationGuard;
+#[cfg(feature = "metrics")]
+use crate::metrics::{LatencyRecorder, LatencyStats};
 use crate::models::*;
+use crate::observer::{redact_token, RequestObserver, WarningObserver};
+use crate::parser::ConfigParser;
+use crate::resolver::SniResolver;
+use crate::retry_budget::{RetryBudget, RetryCredits};
+use crate::submit_worker::SubmitWorker;
+
+/// Encoding applied to query parameter values, including the token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryEncoding {
+    /// `application/x-www-form-urlencoded`, spaces become `+` (default)
+    #[default]
+    Form,
+    /// Strict percent-encoding, spaces become `%20`
+    Percent,
+}
+
+/// Body encoding used for traffic submissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// JSON (`application/json`)
+    #[default]
+    Json,
+    /// MessagePack (`application/msgpack`), requires the `msgpack` feature
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+}
+
+impl Encoding {
+    /// Get the `Content-Type` header value for this encoding
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            Encoding::MsgPack => "application/msgpack",
+        }
+    }
+
+    /// Serialize a request body
+    ///
+    /// Errors carry `url` with the token removed.
+    pub(crate) fn encode<T: serde::Serialize>(&self, body: &T, url: &str) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Json => serde_json::to_vec(body)
+                .map_err(|e| ApiError::parse_error(e.to_string(), redact_token(url), Some(e))),
+            #[cfg(feature = "msgpack")]
+            Encoding::MsgPack => rmp_serde::to_vec_named(body)
+                .map_err(|e| ApiError::parse_error(e.to_string(), redact_token(url), None)),
+        }
+    }
+}
+
+/// Body layout used for per-user traffic submissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubmitFormat {
+    /// A single `SubmitRequest` object in the configured [`Encoding`] (default)
+    #[default]
+    Json,
+    /// One traffic entry per line (`application/x-ndjson`)
+    ///
+    /// `register_id` is sent as a query parameter; `online_users` is not sent.
+    JsonLines,
+}
+
+/// `Content-Type` of JSON Lines submissions
+pub(crate) const JSON_LINES_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// IP address family used to reach the panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpFamily {
+    /// Connect over IPv4 only
+    V4Only,
+    /// Connect over IPv6 only
+    V6Only,
+    /// Let happy-eyeballs pick between both families (default)
+    #[default]
+    Both,
+}
+
+impl IpFamily {
+    /// Unspecified local address that pins outgoing connections to this family
+    pub(crate) fn local_address(self) -> Option<IpAddr> {
+        match self {
+            IpFamily::V4Only => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            IpFamily::V6Only => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            IpFamily::Both => None,
+        }
+    }
+
+    /// Check whether `addr` belongs to this family
+    pub(crate) fn allows(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpFamily::V4Only => addr.is_ipv4(),
+            IpFamily::V6Only => addr.is_ipv6(),
+            IpFamily::Both => true,
+        }
+    }
+}
+
+/// Upper bound for the wait between failing heartbeats in `heartbeat_loop`
+pub const MAX_HEARTBEAT_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Default IP-echo endpoint, answering with the caller's IPv4 or IPv6 address
+pub const DEFAULT_IP_ECHO_URL: &str = "https://api64.ipify.org";
 
 /// Client configuration
 #[derive(Debug, Clone)]
@@ -17,8 +133,46 @@ pub struct Config {
     pub token: String,
     /// Request timeout (default: 5 seconds)
     pub timeout: Duration,
+    /// Per-node-type timeouts overriding `timeout`
+    pub timeout_overrides: HashMap<NodeType, Duration>,
+    /// TCP connect timeout (default: none, reqwest's behavior)
+    pub connect_timeout: Option<Duration>,
     /// Enable debug logging
     pub debug: bool,
+    /// Maximum number of cached ETags (0 = unbounded)
+    pub etag_cache_capacity: usize,
+    /// Domain presented as TLS SNI and Host while connecting to `api_host`'s address
+    pub sni_override: Option<String>,
+    /// Body encoding for traffic submissions (default: JSON)
+    pub content_encoding: Encoding,
+    /// Hook invoked on every request and response
+    pub observer: Option<Arc<dyn RequestObserver>>,
+    /// Proxy URL (http, https or socks5); `None` disables proxies entirely
+    pub proxy: Option<String>,
+    /// Endpoint returning the caller's public IP as plain text
+    pub ip_echo_url: String,
+    /// Field names used for per-user traffic submissions
+    pub traffic_field_style: TrafficFieldStyle,
+    /// Sub-path the panel is mounted under, prepended to every API path
+    pub path_prefix: Option<String>,
+    /// Encoding of query parameter values (default: form encoding)
+    pub query_encoding: QueryEncoding,
+    /// Largest response body accepted, in bytes (`None` = unbounded)
+    pub max_response_bytes: Option<usize>,
+    /// Body layout for per-user traffic submissions (default: single JSON object)
+    pub submit_format: SubmitFormat,
+    /// Callback for the `message` of successful responses
+    pub warning_observer: Option<WarningObserver>,
+    /// Address family used to connect to `api_host` (default: both)
+    pub ip_family: IpFamily,
+    /// Clock skew against the panel that triggers a warning (default: 30 seconds)
+    pub clock_skew_threshold: Duration,
+    /// Gzip POST bodies larger than 1 KiB (default: off)
+    pub request_compression: bool,
+    /// Cap on retries shared across calls (default: unlimited)
+    pub retry_budget: Option<RetryBudget>,
+    /// Refuse to register private or loopback IP addresses (default: off)
+    pub public_hosts_only: bool,
 }
 
 impl Config {
@@ -28,103 +182,505 @@ impl Config {
             api_host: api_host.into(),
             token: token.into(),
             timeout: Duration::from_secs(5),
+            timeout_overrides: HashMap::new(),
+            connect_timeout: None,
             debug: false,
+            etag_cache_capacity: 0,
+            sni_override: None,
+            content_encoding: Encoding::Json,
+            observer: None,
+            proxy: None,
+            ip_echo_url: DEFAULT_IP_ECHO_URL.to_string(),
+            traffic_field_style: TrafficFieldStyle::Short,
+            path_prefix: None,
+            query_encoding: QueryEncoding::Form,
+            max_response_bytes: None,
+            submit_format: SubmitFormat::Json,
+            warning_observer: None,
+            ip_family: IpFamily::Both,
+            clock_skew_threshold: DEFAULT_CLOCK_SKEW_THRESHOLD,
+            request_compression: false,
+            retry_budget: None,
+            public_hosts_only: false,
         }
     }
 
+    /// Create a configuration from environment variables
+    ///
+    /// Reads `SERVER_R_API_HOST` and `SERVER_R_API_TOKEN` (required), plus the
+    /// optional `SERVER_R_TIMEOUT_SECS` and `SERVER_R_DEBUG` (`1`/`true`/`yes`
+    /// or `0`/`false`/`no`).
+    pub fn from_env() -> Result<Self> {
+        let required = |name: &str| {
+            std::env::var(name).map_err(|_| {
+                ApiError::config_error(format!("environment variable {} is not set", name))
+            })
+        };
+        let optional = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+        let mut config = Self::new(
+            required("SERVER_R_API_HOST")?,
+            required("SERVER_R_API_TOKEN")?,
+        );
+
+        if let Some(secs) = optional("SERVER_R_TIMEOUT_SECS") {
+            let secs: u64 = secs.trim().parse().map_err(|_| {
+                ApiError::config_error(format!("SERVER_R_TIMEOUT_SECS is not a number: {}", secs))
+            })?;
+            config.timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(debug) = optional("SERVER_R_DEBUG") {
+            config.debug = match debug.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" => true,
+                "0" | "false" | "no" => false,
+                _ => {
+                    return Err(ApiError::config_error(format!(
+                        "SERVER_R_DEBUG is not a boolean: {}",
+                        debug
+                    )))
+                }
+            };
+        }
+
+        Ok(config)
+    }
+
     /// Set request timeout
     pub fn with_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = timeout;
         self
     }
 
+    /// Set the request timeout for one node type, overriding `timeout`
+    pub fn with_timeout_for(mut self, node_type: NodeType, timeout: Duration) -> Self {
+        self.timeout_overrides.insert(node_type, timeout);
+        self
+    }
+
+    /// Limit how long establishing a connection may take
+    ///
+    /// Applies within `timeout`, so a dead panel address fails fast while slow
+    /// responses still get the full request timeout.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Gzip POST bodies larger than 1 KiB and mark them with `Content-Encoding`
+    ///
+    /// Smaller bodies are sent as-is. The panel must accept gzip request bodies.
+    pub fn with_request_compression(mut self, enabled: bool) -> Self {
+        self.request_compression = enabled;
+        self
+    }
+
+    /// Limit retries to `ratio` per request sent, plus `min_per_sec` per second
+    ///
+    /// Callers check the budget with `ApiClient::try_acquire_retry` before
+    /// retrying a failed call. See [`RetryBudget`].
+    pub fn with_retry_budget(mut self, ratio: f64, min_per_sec: f64) -> Self {
+        self.retry_budget = Some(RetryBudget::new(ratio, min_per_sec));
+        self
+    }
+
+    /// Refuse registrations whose hostname or node IP is a non-public address
+    ///
+    /// See [`RegisterRequest::validate_public`].
+    pub fn with_public_hosts_only(mut self, enabled: bool) -> Self {
+        self.public_hosts_only = enabled;
+        self
+    }
+
+    /// Validate a registration according to `public_hosts_only`
+    pub(crate) fn check_register_request(&self, request: &RegisterRequest) -> Result<()> {
+        if self.public_hosts_only {
+            request.validate_public()
+        } else {
+            request.validate()
+        }
+    }
+
+    /// Get the request timeout that applies to `node_type`
+    pub fn timeout_for(&self, node_type: NodeType) -> Duration {
+        self.timeout_overrides
+            .get(&node_type)
+            .copied()
+            .unwrap_or(self.timeout)
+    }
+
     /// Enable debug mode
     pub fn with_debug(mut self, debug: bool) -> Self {
         self.debug = debug;
         self
     }
+
+    /// Bound the ETag cache, evicting the least-recently-used entry when full
+    ///
+    /// A capacity of 0 keeps the cache unbounded.
+    pub fn with_etag_cache_capacity(mut self, capacity: usize) -> Self {
+        self.etag_cache_capacity = capacity;
+        self
+    }
+
+    /// Connect to `api_host`'s address while presenting `domain` as SNI
+    ///
+    /// Requests are sent to `domain` (so TLS SNI and the `Host` header carry it),
+    /// and the domain is resolved to the address of `api_host`, looked up again
+    /// on each new connection. This allows reaching the panel by IP with a
+    /// custom server name.
+    pub fn with_sni_override(mut self, domain: impl Into<String>) -> Self {
+        self.sni_override = Some(domain.into());
+        self
+    }
+
+    /// Set the body encoding used by `submit` and stats submissions
+    pub fn with_content_encoding(mut self, encoding: Encoding) -> Self {
+        self.content_encoding = encoding;
+        self
+    }
+
+    /// Set an observer notified on every request and response
+    pub fn with_observer(mut self, observer: Arc<dyn RequestObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Route requests through a proxy
+    ///
+    /// Accepts `http://`, `https://`, `socks5://` and `socks5h://` URLs. `None`
+    /// keeps the default of ignoring all proxies, including system ones.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Set the IP-echo endpoint used by `detect_public_ip`
+    pub fn with_ip_echo_url(mut self, url: impl Into<String>) -> Self {
+        self.ip_echo_url = url.into();
+        self
+    }
+
+    /// Set the field names used when submitting per-user traffic
+    pub fn with_traffic_field_style(mut self, style: TrafficFieldStyle) -> Self {
+        self.traffic_field_style = style;
+        self
+    }
+
+    /// Set how query parameter values, including the token, are encoded
+    pub fn with_query_encoding(mut self, encoding: QueryEncoding) -> Self {
+        self.query_encoding = encoding;
+        self
+    }
+
+    /// Call `observer` whenever a successful response carries a non-empty `message`
+    ///
+    /// Lets operators see server advisories without failing the request.
+    pub fn with_warning_observer(mut self, observer: Arc<dyn Fn(&str) + Send + Sync>) -> Self {
+        self.warning_observer = Some(WarningObserver::new(observer));
+        self
+    }
+
+    /// Set the body layout used for per-user traffic submissions
+    pub fn with_submit_format(mut self, format: SubmitFormat) -> Self {
+        self.submit_format = format;
+        self
+    }
+
+    /// Restrict connections to `api_host` to one IP address family
+    pub fn with_ip_family(mut self, family: IpFamily) -> Self {
+        self.ip_family = family;
+        self
+    }
+
+    /// Warn once the local clock drifts more than `threshold` from the panel's
+    ///
+    /// The warning goes to the warning observer and the `tracing` log.
+    pub fn with_clock_skew_threshold(mut self, threshold: Duration) -> Self {
+        self.clock_skew_threshold = threshold;
+        self
+    }
+
+    /// Reject response bodies larger than `limit` bytes
+    ///
+    /// Oversized responses fail with [`ApiError::ResponseTooLarge`].
+    pub fn with_max_response_bytes(mut self, limit: usize) -> Self {
+        self.max_response_bytes = Some(limit);
+        self
+    }
+
+    /// Mount all API paths under `prefix`, e.g. `/panel`
+    ///
+    /// Leading and trailing slashes are optional.
+    pub fn with_path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.path_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Validate the configuration
+    ///
+    /// Checks that `api_host` is an http/https URL and that `token` is non-empty.
+    pub fn validate(&self) -> Result<()> {
+        let url = url::Url::parse(&self.api_host).map_err(|e| {
+            ApiError::config_error(format!("Invalid api_host '{}': {}", self.api_host, e))
+        })?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(ApiError::config_error(format!(
+                "Invalid api_host '{}': scheme must be http or https",
+                self.api_host
+            )));
+        }
+
+        if url.host_str().is_none_or(str::is_empty) {
+            return Err(ApiError::config_error(format!(
+                "Invalid api_host '{}': missing host",
+                self.api_host
+            )));
+        }
+
+        if self.token.trim().is_empty() {
+            return Err(ApiError::config_error("token must not be empty"));
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let scheme = url::Url::parse(proxy)
+                .map(|url| url.scheme().to_string())
+                .map_err(|e| ApiError::config_error(format!("Invalid proxy '{}': {}", proxy, e)))?;
+            if !matches!(scheme.as_str(), "http" | "https" | "socks5" | "socks5h") {
+                return Err(ApiError::config_error(format!(
+                    "Invalid proxy '{}': unsupported scheme '{}'",
+                    proxy, scheme
+                )));
+            }
+        }
+
+        if let Some(sni) = &self.sni_override {
+            if url::Host::parse(sni).is_err() {
+                return Err(ApiError::config_error(format!(
+                    "Invalid sni_override '{}'",
+                    sni
+                )));
+            }
+        }
+
+        if let Some(budget) = &self.retry_budget {
+            let valid = |v: f64| v.is_finite() && v >= 0.0;
+            if !valid(budget.ratio) || !valid(budget.min_per_sec) {
+                return Err(ApiError::config_error(
+                    "retry_budget ratio and min_per_sec must be finite and non-negative",
+                ));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// API Client for xflash-panda server
 #[derive(Clone)]
 pub struct ApiClient {
     config: Config,
+    base_url: String,
     http_client: HttpClient,
-    etag_cache: Arc<RwLock<HashMap<String, String>>>,
+    etag_cache: Arc<Mutex<EtagCache>>,
+    token: Arc<std::sync::RwLock<String>>,
+    users_inflight: Arc<RwLock<HashMap<CacheKey, UsersFlight>>>,
+    config_parsers: Arc<HashMap<String, Arc<dyn ConfigParser>>>,
+    sync_tokens: Arc<Mutex<HashMap<CacheKey, String>>>,
+    registrations: Arc<Registrations>,
+    etag_changes: Arc<broadcast::Sender<(String, String)>>,
+    clock_skew: Arc<Mutex<Option<Duration>>>,
+    retry_credits: Option<Arc<RetryCredits>>,
+    shutdown: Arc<watch::Sender<bool>>,
+    #[cfg(feature = "metrics")]
+    latency: Arc<RwLock<LatencyRecorder>>,
 }
 
+/// Buffered ETag changes per subscriber before it starts lagging
+const ETAG_CHANGE_CAPACITY: usize = 64;
+
+/// Shared result of an in-flight `users` request
+type UsersFlight = Arc<OnceCell<Result<Vec<User>>>>;
+
 impl ApiClient {
     /// Create a new API client
     pub fn new(config: Config) -> Result<Self> {
-        let http_client = HttpClient::builder()
+        config.validate()?;
+
+        let mut builder = HttpClient::builder()
             .timeout(config.timeout)
-            .no_proxy()
+            .local_address(config.ip_family.local_address());
+        if let Some(connect_timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        builder = match &config.proxy {
+            Some(proxy) => builder.proxy(build_proxy(proxy)?),
+            None => builder.no_proxy(),
+        };
+        let mut base_url = config.api_host.trim_end_matches('/').to_string();
+
+        if let Some(sni) = &config.sni_override {
+            let (url, resolver) = sni_target(&config.api_host, sni, config.ip_family)?;
+            builder = builder.dns_resolver(Arc::new(resolver));
+            base_url = url;
+        }
+        let base_url = apply_path_prefix(&base_url, config.path_prefix.as_deref());
+
+        let http_client = builder
             .build()
             .map_err(|e| ApiError::config_error(format!("Failed to create HTTP client: {}", e)))?;
 
-        Ok(Self {
+        Ok(Self::from_parts(config, base_url, http_client))
+    }
+
+    /// Create a client that sends requests through an existing HTTP client
+    ///
+    /// Transport settings such as the timeouts come from `http_client`, so
+    /// `Config::sni_override`, `Config::proxy` and `Config::connect_timeout`
+    /// must be unset and `Config::ip_family` must be `Both`.
+    pub fn with_http_client(config: Config, http_client: HttpClient) -> Result<Self> {
+        config.validate()?;
+
+        if config.sni_override.is_some()
+            || config.proxy.is_some()
+            || config.connect_timeout.is_some()
+            || config.ip_family != IpFamily::Both
+        {
+            return Err(ApiError::config_error(
+                "sni_override, proxy, connect_timeout and ip_family cannot be used with an injected HTTP client",
+            ));
+        }
+
+        let base_url = apply_path_prefix(
+            config.api_host.trim_end_matches('/'),
+            config.path_prefix.as_deref(),
+        );
+
+        Ok(Self::from_parts(config, base_url, http_client))
+    }
+
+    /// Parse configs for `node_type` with `parser` instead of the built-in parser
+    ///
+    /// `node_type` is matched case-insensitively against the node type name,
+    /// e.g. `"trojan"`; other names are fetched with
+    /// [`config_by_name`](Self::config_by_name). Registering a type again
+    /// replaces the earlier parser.
+    pub fn with_config_parser(
+        mut self,
+        node_type: impl Into<String>,
+        parser: Box<dyn ConfigParser>,
+    ) -> Self {
+        Arc::make_mut(&mut self.config_parsers)
+            .insert(node_type.into().to_ascii_lowercase(), Arc::from(parser));
+        self
+    }
+
+    /// Assemble a client from validated parts
+    fn from_parts(config: Config, base_url: String, http_client: HttpClient) -> Self {
+        let etag_cache = EtagCache::new(config.etag_cache_capacity);
+        let token = Arc::new(std::sync::RwLock::new(config.token.clone()));
+        let retry_credits = config.retry_budget.map(|b| Arc::new(RetryCredits::new(b)));
+
+        Self {
             config,
+            base_url,
             http_client,
-            etag_cache: Arc::new(RwLock::new(HashMap::new())),
-        })
+            etag_cache: Arc::new(Mutex::new(etag_cache)),
+            token,
+            users_inflight: Arc::new(RwLock::new(HashMap::new())),
+            config_parsers: Arc::new(HashMap::new()),
+            sync_tokens: Arc::new(Mutex::new(HashMap::new())),
+            registrations: Arc::new(Mutex::new(HashMap::new())),
+            etag_changes: Arc::new(broadcast::channel(ETAG_CHANGE_CAPACITY).0),
+            clock_skew: Arc::new(Mutex::new(None)),
+            retry_credits,
+            shutdown: Arc::new(watch::channel(false).0),
+            #[cfg(feature = "metrics")]
+            latency: Arc::new(RwLock::new(LatencyRecorder::default())),
+        }
     }
 
-    /// Build URL with query parameters
-    fn build_url(&self, path: &str, params: &[(&str, &str)]) -> String {
-        let mut url = format!("{}{}", self.config.api_host.trim_end_matches('/'), path);
+    /// Lock the ETag cache, recovering from a poisoned lock
+    ///
+    /// A plain mutex keeps the cache independent of the async runtime; the
+    /// guard must never be held across an `.await`.
+    fn cache(&self) -> MutexGuard<'_, EtagCache> {
+        self.etag_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 
-        let mut query_params: Vec<(&str, &str)> = vec![("token", &self.config.token)];
-        query_params.extend(params);
+    /// Lock the stored user sync tokens, recovering from a poisoned lock
+    fn sync_tokens(&self) -> MutexGuard<'_, HashMap<CacheKey, String>> {
+        self.sync_tokens
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 
-        if !query_params.is_empty() {
-            url.push('?');
-            let params_str: Vec<String> = query_params
-                .iter()
-                .map(|(k, v)| {
-                    let encoded: String =
-                        url::form_urlencoded::byte_serialize(v.as_bytes()).collect();
-                    format!("{}={}", k, encoded)
-                })
-                .collect();
-            url.push_str(&params_str.join("&"));
-        }
+    /// Drop the cached ETag and sync token of a registration
+    fn forget_user_state(&self, cache_key: &CacheKey) {
+        self.cache().remove(cache_key);
+        self.sync_tokens().remove(cache_key);
+    }
 
-        url
+    /// Build URL with query parameters
+    fn build_url(&self, path: &str, params: &[(&str, &str)]) -> Result<String> {
+        let token = self.token.read().unwrap_or_else(|p| p.into_inner());
+        build_url(
+            &self.base_url,
+            &token,
+            path,
+            params,
+            self.config.query_encoding,
+        )
     }
 
     /// Make a GET request
-    async fn get(&self, path: &str, params: &[(&str, &str)]) -> Result<Response> {
-        let url = self.build_url(path, params);
+    async fn get(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Response> {
+        let url = self.build_url(path, params)?;
 
         if self.config.debug {
             debug!("GET {}", url);
         }
 
-        let response = self
+        let request = self
             .http_client
             .get(&url)
-            .header("Content-Type", "application/json")
-            .send()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &url, Some(e)))?;
+            .header("Content-Type", "application/json");
+        let response = self.send(node_type, request, "GET", &url).await?;
 
-        self.check_response(response, &url).await
+        self.check_panel_response(response, &url).await
     }
 
     /// Make a GET request with ETag support
+    ///
+    /// With `revalidate` unset the cached ETag is not sent, but a fresh ETag
+    /// is still stored.
     async fn get_with_etag(
         &self,
+        node_type: Option<NodeType>,
         path: &str,
         params: &[(&str, &str)],
-        cache_key: &str,
+        cache_key: &CacheKey,
+        revalidate: bool,
     ) -> Result<Response> {
-        let url = self.build_url(path, params);
+        let url = self.build_url(path, params)?;
 
         if self.config.debug {
             debug!("GET (with ETag) {}", url);
         }
 
-        let etag = self.etag_cache.read().await.get(cache_key).cloned();
+        let etag = if revalidate {
+            self.cache().get(cache_key)
+        } else {
+            None
+        };
 
         let mut request = self
             .http_client
@@ -135,51 +691,248 @@ impl ApiClient {
             request = request.header("If-None-Match", etag);
         }
 
-        let response = request
-            .send()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &url, Some(e)))?;
+        let response = self.send(node_type, request, "GET", &url).await?;
 
         if response.status() == StatusCode::NOT_MODIFIED {
+            self.rotate_token(response.headers(), &url);
             return Err(ApiError::not_modified(&url));
         }
 
-        // Store the new ETag if present
+        let response = self.check_panel_response(response, &url).await?;
+
+        // Store the new ETag if present; failed responses never get here
         if let Some(new_etag) = response.headers().get("ETag") {
             if let Ok(etag_str) = new_etag.to_str() {
-                self.etag_cache
-                    .write()
-                    .await
-                    .insert(cache_key.to_string(), etag_str.to_string());
+                let previous = self.cache().insert(cache_key.clone(), etag_str.to_string());
+                if previous.as_deref() != Some(etag_str) {
+                    // No subscribers is not an error
+                    let _ = self
+                        .etag_changes
+                        .send((cache_key.to_string(), etag_str.to_string()));
+                }
             }
         }
 
-        self.check_response(response, &url).await
+        Ok(response)
     }
 
     /// Make a POST request with JSON body
     async fn post<T: serde::Serialize>(
         &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        params: &[(&str, &str)],
+        body: &T,
+    ) -> Result<Response> {
+        self.post_encoded(node_type, path, params, body, Encoding::Json, &[])
+            .await
+    }
+
+    /// Make a traffic submission POST using the configured content encoding
+    async fn post_submission<T: serde::Serialize>(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        body: &T,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        self.post_encoded(
+            node_type,
+            path,
+            &[],
+            body,
+            self.config.content_encoding,
+            headers,
+        )
+        .await
+    }
+
+    /// Submit per-user traffic with an `Idempotency-Key`
+    ///
+    /// Uses the caller's key from `headers` if there is one; otherwise a new
+    /// UUID is generated for this call.
+    async fn submit_traffic(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        request: &SubmitRequest,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        if headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(IDEMPOTENCY_KEY_HEADER))
+        {
+            return self.send_traffic(node_type, path, request, headers).await;
+        }
+
+        let key = uuid::Uuid::new_v4().to_string();
+        let mut with_key = headers.to_vec();
+        with_key.push((IDEMPOTENCY_KEY_HEADER, &key));
+
+        self.send_traffic(node_type, path, request, &with_key).await
+    }
+
+    /// Submit per-user traffic using the configured field style and encoding
+    async fn send_traffic(
+        &self,
+        node_type: Option<NodeType>,
+        path: &str,
+        request: &SubmitRequest,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        if self.config.submit_format == SubmitFormat::JsonLines {
+            let url = self.build_url(path, &[("register_id", &request.register_id)])?;
+            let body = match self.config.traffic_field_style {
+                TrafficFieldStyle::Short => encode_json_lines(&request.data, &url)?,
+                TrafficFieldStyle::Verbose => encode_json_lines(
+                    &request
+                        .map_data(|t| UserTrafficVerbose::from(t.clone()))
+                        .data,
+                    &url,
+                )?,
+            };
+
+            if self.config.debug {
+                debug!("POST {} body: {}", url, String::from_utf8_lossy(&body));
+            }
+
+            return self
+                .post_bytes(node_type, &url, body, JSON_LINES_CONTENT_TYPE, headers)
+                .await;
+        }
+
+        match self.config.traffic_field_style {
+            TrafficFieldStyle::Short => {
+                self.post_submission(node_type, path, &request, headers)
+                    .await
+            }
+            TrafficFieldStyle::Verbose => {
+                let request = request.map_data(|t| UserTrafficVerbose::from(t.clone()));
+                self.post_submission(node_type, path, &request, headers)
+                    .await
+            }
+        }
+    }
+
+    /// Make a POST request with an encoded body and extra headers
+    async fn post_encoded<T: serde::Serialize>(
+        &self,
+        node_type: Option<NodeType>,
         path: &str,
         params: &[(&str, &str)],
         body: &T,
+        encoding: Encoding,
+        headers: &[(&str, &str)],
     ) -> Result<Response> {
-        let url = self.build_url(path, params);
+        let url = self.build_url(path, params)?;
+
+        let body = encoding.encode(body, &url)?;
 
         if self.config.debug {
-            debug!("POST {}", url);
+            if encoding == Encoding::Json {
+                debug!("POST {} body: {}", url, String::from_utf8_lossy(&body));
+            } else {
+                debug!("POST {} ({} byte body)", url, body.len());
+            }
         }
 
-        let response = self
+        self.post_bytes(node_type, &url, body, encoding.content_type(), headers)
+            .await
+    }
+
+    /// POST an already encoded body to a fully built URL
+    async fn post_bytes(
+        &self,
+        node_type: Option<NodeType>,
+        url: &str,
+        mut body: Vec<u8>,
+        content_type: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Response> {
+        let mut request = self
             .http_client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(body)
+            .post(url)
+            .header("Content-Type", content_type);
+
+        if self.config.request_compression && body.len() > REQUEST_COMPRESSION_THRESHOLD {
+            body = gzip(&body, url)?;
+            request = request.header("Content-Encoding", "gzip");
+        }
+
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let response = self
+            .send(node_type, request.body(body), "POST", url)
+            .await?;
+
+        self.check_panel_response(response, url).await
+    }
+
+    /// Send a request, notifying the configured observer
+    ///
+    /// `node_type` selects the timeout override from `Config::timeout_overrides`.
+    async fn send(
+        &self,
+        node_type: Option<NodeType>,
+        request: RequestBuilder,
+        method: &str,
+        url: &str,
+    ) -> Result<Response> {
+        let request = match node_type.and_then(|nt| self.config.timeout_overrides.get(&nt)) {
+            Some(timeout) => request.timeout(*timeout),
+            None => request,
+        };
+        let observer = self.config.observer.as_deref();
+        if let Some(observer) = observer {
+            observer.on_request(method, &redact_token(url));
+        }
+        if let Some(credits) = &self.retry_credits {
+            credits.deposit();
+        }
+
+        let started = Instant::now();
+        let response = request
             .send()
             .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &url, Some(e)))?;
+            .map_err(|e| ApiError::network_error(e.to_string(), url, Some(e)))?;
+
+        let elapsed = started.elapsed();
+
+        #[cfg(feature = "metrics")]
+        self.latency.write().await.record(url, elapsed);
+
+        if let Some(observer) = observer {
+            observer.on_response(
+                response.status().as_u16(),
+                elapsed,
+                response.content_length(),
+            );
+        }
+
+        Ok(response)
+    }
+
+    /// Switch to a panel-issued token, if the response carries one
+    fn rotate_token(&self, headers: &HeaderMap, url: &str) {
+        apply_token_rotation(
+            headers,
+            url,
+            &self.base_url,
+            &self.token,
+            self.config.observer.as_deref(),
+        );
+    }
 
-        self.check_response(response, &url).await
+    /// Check a panel response, applying token rotation and tracking clock skew
+    async fn check_panel_response(&self, response: Response, url: &str) -> Result<Response> {
+        self.rotate_token(response.headers(), url);
+        let response = self.check_response(response, url).await?;
+        if let Some(skew) = date_header_skew(response.headers()) {
+            record_clock_skew(&self.clock_skew, skew, &self.config);
+        }
+        Ok(response)
     }
 
     /// Check response status and handle errors
@@ -190,155 +943,566 @@ impl ApiClient {
             Ok(response)
         } else if status == StatusCode::NOT_MODIFIED {
             Err(ApiError::not_modified(url))
+        } else if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = parse_retry_after(response.headers());
+            error!("API rate limited: retry after {:?} - {}", retry_after, url);
+            Err(ApiError::RateLimited {
+                retry_after,
+                url: url.to_string(),
+            })
         } else {
             let status_code = status.as_u16();
-            let message = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
+            let message = match self.read_body(response, url).await {
+                Ok((_, body)) => String::from_utf8_lossy(&body).into_owned(),
+                Err(e @ ApiError::ResponseTooLarge { .. }) => return Err(e),
+                Err(_) => "Unknown error".to_string(),
+            };
 
             error!("API error: {} - {} - {}", status_code, message, url);
             Err(ApiError::from_status_code(status_code, message, url))
         }
     }
 
-    // ==================== Configuration APIs ====================
+    /// Read the full response body, enforcing `Config::max_response_bytes`
+    async fn read_body(&self, mut response: Response, url: &str) -> Result<(u16, Bytes)> {
+        let status = response.status().as_u16();
+        let network_error =
+            |e: reqwest::Error| ApiError::network_error(e.to_string(), url, Some(e));
 
-    /// Get raw node configuration
-    pub async fn raw_config(&self, node_type: NodeType, node_id: i64) -> Result<Vec<u8>> {
-        let path = format!("/api/v1/server/enhanced/{}/config", node_type);
-        let node_id_str = node_id.to_string();
-        let params = [("node_id", node_id_str.as_str())];
+        let Some(limit) = self.config.max_response_bytes else {
+            let bytes = response.bytes().await.map_err(network_error)?;
+            return Ok((status, bytes));
+        };
 
-        let response = self.get(&path, &params).await?;
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
+        check_content_length(response.content_length(), limit, url)?;
 
-        Ok(bytes.to_vec())
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(network_error)? {
+            check_body_len(body.len() + chunk.len(), limit, url)?;
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok((status, Bytes::from(body)))
     }
 
-    /// Get parsed node configuration (enhanced)
-    pub async fn config(&self, node_type: NodeType, node_id: i64) -> Result<NodeConfigEnum> {
-        let path = format!("/api/v1/server/enhanced/{}/config", node_type);
-        let node_id_str = node_id.to_string();
-        let params = [("node_id", node_id_str.as_str())];
+    /// Pass a successful response's `message` to the warning observer
+    fn notify_warning<T>(&self, response: &ApiResponse<T>) {
+        if let Some(observer) = &self.config.warning_observer {
+            observer.notify(response.message.as_deref());
+        }
+    }
 
-        let response = self.get(&path, &params).await?;
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
+    /// Finish a request whose response data is unused
+    ///
+    /// The body is only read when a warning observer wants its `message`.
+    async fn discard_body(&self, response: Response, path: &str) -> Result<()> {
+        let Some(observer) = &self.config.warning_observer else {
+            return Ok(());
+        };
+
+        #[derive(serde::Deserialize)]
+        struct Envelope {
+            #[serde(default)]
+            message: Option<String>,
+        }
 
-        // Parse the response wrapper first
-        let api_response: ApiResponse<serde_json::Value> = serde_json::from_slice(&bytes)
-            .map_err(|e| ApiError::parse_error(e.to_string(), &path, Some(e)))?;
+        let (_, bytes) = self.read_body(response, path).await?;
+        if let Ok(envelope) = serde_json::from_slice::<Envelope>(&bytes) {
+            observer.notify(envelope.message.as_deref());
+        }
+        Ok(())
+    }
 
-        // Then parse the config data
-        let config_bytes = serde_json::to_vec(&api_response.data)
-            .map_err(|e| ApiError::parse_error(e.to_string(), &path, Some(e)))?;
+    /// Read a submission response into its acknowledgement
+    async fn read_ack(&self, response: Response, path: &str) -> Result<SubmitAck> {
+        let (status, bytes) = self.read_body(response, path).await?;
+        let api_response: ApiResponse<Option<SubmitAck>> =
+            parse_api_response(&bytes, status, path)?;
+        self.notify_warning(&api_response);
 
-        parse_config(node_type, &config_bytes)
+        let ack = api_response.data.unwrap_or_default();
+        if let Some(skew) = ack.server_time.and_then(unix_time_skew) {
+            record_clock_skew(&self.clock_skew, skew, &self.config);
+        }
+        Ok(ack)
     }
 
-    // ==================== Node Management APIs ====================
+    // ==================== Configuration APIs ====================
 
-    /// Register a node with the server
-    pub async fn register(
+    /// Get raw node configuration
+    pub async fn raw_config(
         &self,
         node_type: NodeType,
-        node_id: i64,
-        request: RegisterRequest,
-    ) -> Result<String> {
-        let path = format!("/api/v1/server/enhanced/{}/register", node_type);
-        let node_id_str = node_id.to_string();
+        node_id: impl Into<NodeId>,
+    ) -> Result<Vec<u8>> {
+        let path = Endpoints::config(node_type);
+        let node_id_str = node_id.into().checked()?.to_string();
         let params = [("node_id", node_id_str.as_str())];
 
-        let response = self.post(&path, &params, &request).await?;
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
-        let api_response: RegisterResponse = serde_json::from_slice(&bytes)
-            .map_err(|e| ApiError::parse_error(e.to_string(), &path, Some(e)))?;
+        let response = self.get(Some(node_type), &path, &params).await?;
+        let (_, bytes) = self.read_body(response, &path).await?;
 
-        Ok(api_response.data.register_id)
+        Ok(bytes.to_vec())
     }
 
-    /// Unregister a node
-    pub async fn unregister(&self, node_type: NodeType, register_id: &str) -> Result<()> {
-        let path = format!("/api/v1/server/enhanced/{}/unregister", node_type);
-        let params = [("register_id", register_id)];
+    /// Stream raw node configuration without buffering the whole body
+    ///
+    /// Status errors are reported before the stream is returned; errors while
+    /// reading the body are yielded as stream items.
+    pub async fn raw_config_stream(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+    ) -> Result<impl Stream<Item = Result<Bytes>> + Send + 'static> {
+        let path = Endpoints::config(node_type);
+        let node_id_str = node_id.into().checked()?.to_string();
+        let params = [("node_id", node_id_str.as_str())];
 
-        // Empty body for unregister
+        let response = self.get(Some(node_type), &path, &params).await?;
+        let limit = self.config.max_response_bytes;
+        if let Some(limit) = limit {
+            check_content_length(response.content_length(), limit, &path)?;
+        }
+
+        let mut received = 0;
+        Ok(response.bytes_stream().map(move |chunk| {
+            let chunk =
+                chunk.map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
+            received += chunk.len();
+            if let Some(limit) = limit {
+                check_body_len(received, limit, &path)?;
+            }
+            Ok(chunk)
+        }))
+    }
+
+    /// Get parsed node configuration (enhanced)
+    pub async fn config(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+    ) -> Result<NodeConfigEnum> {
+        self.config_by_name(node_type.as_str(), node_id).await
+    }
+
+    /// Get parsed node configuration for a node type given by name
+    ///
+    /// Unlike [`config`](Self::config), this also reaches node types this
+    /// client does not know about. The config is parsed by the parser
+    /// registered for `node_type` with [`with_config_parser`](Self::with_config_parser),
+    /// else by the built-in parser; unknown types without a parser yield
+    /// [`NodeConfigEnum::Unknown`].
+    pub async fn config_by_name(
+        &self,
+        node_type: &str,
+        node_id: impl Into<NodeId>,
+    ) -> Result<NodeConfigEnum> {
+        let name = node_type.to_ascii_lowercase();
+        let known = name.parse::<NodeType>().ok();
+        let path = match known {
+            Some(node_type) => Endpoints::config(node_type),
+            None => Endpoints::config_by_name(&name)?,
+        };
+        let node_id_str = node_id.into().checked()?.to_string();
+        let params = [("node_id", node_id_str.as_str())];
+
+        let response = self.get(known, &path, &params).await?;
+        let (status, bytes) = self.read_body(response, &path).await?;
+
+        // Parse the response wrapper first
+        let api_response: ApiResponse<serde_json::Value> =
+            parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+
+        // Then parse the config data
+        let config_bytes = config_data_bytes(&api_response.data, &path)?;
+
+        match config_parser(&self.config_parsers, &name) {
+            Some(parser) => parser.parse(&config_bytes),
+            None => parse_config_by_name(&name, &config_bytes),
+        }
+    }
+
+    /// Get the node configuration, falling back to a cache file
+    ///
+    /// A successfully fetched config is written to `cache_path` in the panel's
+    /// `{"data": ...}` format. When the fetch fails with a network error or
+    /// timeout, the file is parsed with [`parse_raw_config_response`] instead;
+    /// if it cannot be read, the original error is returned. Failing to write
+    /// the cache is only logged.
+    pub async fn config_or_cached(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        cache_path: impl AsRef<Path>,
+    ) -> Result<NodeConfigEnum> {
+        let cache_path = cache_path.as_ref();
+
+        match self.config(node_type, node_id).await {
+            Ok(config) => {
+                if let Err(e) = write_config_cache(cache_path, &config).await {
+                    warn!(
+                        "failed to write config cache {}: {}",
+                        cache_path.display(),
+                        e
+                    );
+                }
+                Ok(config)
+            }
+            Err(e) if e.is_network_error() || e.is_timeout() => {
+                let Ok(cached) = tokio::fs::read(cache_path).await else {
+                    return Err(e);
+                };
+                warn!(
+                    "panel unreachable ({}), using cached config {}",
+                    e,
+                    cache_path.display()
+                );
+                parse_raw_config_response(node_type, &cached)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fetch the node configuration and user list concurrently
+    ///
+    /// The users half goes through the ETag cache like [`ApiClient::users`];
+    /// it is `None` if the cached list is still current.
+    pub async fn bootstrap(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        register_id: &str,
+    ) -> Result<(NodeConfigEnum, Option<Vec<User>>)> {
+        let (config, users) = tokio::join!(
+            self.config(node_type, node_id),
+            self.users(node_type, register_id)
+        );
+
+        Ok((config?, unless_not_modified(users)?))
+    }
+
+    // ==================== Node Management APIs ====================
+
+    /// Register a node with the server
+    pub async fn register(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        request: RegisterRequest,
+    ) -> Result<String> {
+        let data = self.register_full(node_type, node_id, request).await?;
+        Ok(data.register_id)
+    }
+
+    /// Register a node and unregister it automatically when the guard drops
+    ///
+    /// Must be called within a tokio runtime for the drop-time unregister to run.
+    pub async fn register_guarded(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        request: RegisterRequest,
+    ) -> Result<RegistrationGuard> {
+        let register_id = self.register(node_type, node_id, request).await?;
+        Ok(RegistrationGuard::new(self.clone(), node_type, register_id))
+    }
+
+    /// Register a node and return the full registration metadata
+    pub async fn register_full(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        request: RegisterRequest,
+    ) -> Result<RegisterResponseData> {
+        let path = Endpoints::register(node_type);
+        let node_id_str = node_id.into().checked()?.to_string();
+        let params = [("node_id", node_id_str.as_str())];
+        self.config.check_register_request(&request)?;
+
+        let response = self.post(Some(node_type), &path, &params, &request).await?;
+        let (_, bytes) = self.read_body(response, &path).await?;
+        let api_response: RegisterResponse =
+            serde_json::from_slice(&bytes).map_err(|e| body_parse_error(e, &path, &bytes))?;
+        self.notify_warning(&api_response);
+
+        // A fresh registration must not resume a recycled id's ETag or sync token
+        self.forget_user_state(&CacheKey::new(node_type, &api_response.data.register_id));
+        track_registration(
+            &self.registrations,
+            node_type,
+            &api_response.data.register_id,
+        );
+
+        Ok(api_response.data)
+    }
+
+    /// Register several nodes in one request using the panel's `node_ids` form
+    ///
+    /// Returns `(node_id, register_id)` pairs in the order the panel lists
+    /// them. Errors carry the batch's node_ids in their message.
+    pub async fn register_multi(
+        &self,
+        node_type: NodeType,
+        node_ids: &[i64],
+        request: RegisterRequest,
+    ) -> Result<Vec<(i64, String)>> {
+        if node_ids.is_empty() {
+            return Err(ApiError::config_error("node_ids must not be empty"));
+        }
+        for &node_id in node_ids {
+            NodeId::new(node_id)?;
+        }
+        self.config.check_register_request(&request)?;
+
+        let path = Endpoints::register(node_type);
+        let context = format!("register node_ids {:?}", node_ids);
+        let request = RegisterMultiRequest::new(node_ids.to_vec(), request);
+
+        let result = async {
+            let response = self.post(Some(node_type), &path, &[], &request).await?;
+            let (status, bytes) = self.read_body(response, &path).await?;
+            parse_api_response::<Vec<RegisterMultiEntry>>(&bytes, status, &path)
+        }
+        .await;
+        let api_response = result.map_err(|e| e.with_context(&context))?;
+        self.notify_warning(&api_response);
+
+        for entry in &api_response.data {
+            self.forget_user_state(&CacheKey::new(node_type, &entry.register_id));
+            track_registration(&self.registrations, node_type, &entry.register_id);
+        }
+
+        Ok(api_response
+            .data
+            .into_iter()
+            .map(|entry| (entry.node_id, entry.register_id))
+            .collect())
+    }
+
+    /// Unregister a node
+    pub async fn unregister(&self, node_type: NodeType, register_id: &str) -> Result<()> {
+        let path = Endpoints::unregister(node_type);
+        let params = [("register_id", register_id)];
+
+        // Empty body for unregister
         let empty: HashMap<String, String> = HashMap::new();
-        self.post(&path, &params, &empty).await?;
+        let response = self.post(Some(node_type), &path, &params, &empty).await?;
+        self.forget_user_state(&CacheKey::new(node_type, register_id));
+        untrack_registration(&self.registrations, node_type, register_id);
 
-        Ok(())
+        self.discard_body(response, &path).await
+    }
+
+    /// Unregister several nodes concurrently
+    ///
+    /// Intended for graceful shutdown: every entry is attempted regardless of
+    /// earlier failures, and each outcome is reported in input order.
+    pub async fn unregister_many(
+        &self,
+        entries: &[(NodeType, &str)],
+    ) -> Vec<(NodeType, String, Result<()>)> {
+        let calls = entries.iter().map(|&(node_type, register_id)| async move {
+            let result = self.unregister(node_type, register_id).await;
+            (node_type, register_id.to_string(), result)
+        });
+
+        futures_util::future::join_all(calls).await
     }
 
     /// Verify if a register_id is valid
     pub async fn verify(&self, node_type: NodeType, register_id: &str) -> Result<bool> {
-        let path = format!("/api/v1/server/enhanced/{}/verify", node_type);
+        Ok(self.verify_detailed(node_type, register_id).await?.valid)
+    }
+
+    /// Verify a register_id, including the panel's reason when it is invalid
+    pub async fn verify_detailed(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<VerifyResponseData> {
+        let path = Endpoints::verify(node_type);
         let request = VerifyRequest::new(register_id);
 
-        let response = self.post(&path, &[], &request).await?;
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
-        let api_response: VerifyResponse = serde_json::from_slice(&bytes)
-            .map_err(|e| ApiError::parse_error(e.to_string(), &path, Some(e)))?;
+        let response = self.post(Some(node_type), &path, &[], &request).await?;
+        let (_, bytes) = self.read_body(response, &path).await?;
+        let api_response: VerifyResponse =
+            serde_json::from_slice(&bytes).map_err(|e| body_parse_error(e, &path, &bytes))?;
+        self.notify_warning(&api_response);
 
         Ok(api_response.data)
     }
 
+    /// Verify several register_ids, running up to `concurrency` requests at once
+    ///
+    /// Results are returned in input order. A `concurrency` of 0 is treated as 1.
+    pub async fn verify_many(
+        &self,
+        node_type: NodeType,
+        register_ids: &[&str],
+        concurrency: usize,
+    ) -> Vec<(String, Result<bool>)> {
+        stream::iter(register_ids)
+            .map(|&register_id| async move {
+                let result = self.verify(node_type, register_id).await;
+                (register_id.to_string(), result)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Make sure a registration is valid, re-registering if needed
+    ///
+    /// Verifies `register_id` and returns it unchanged when valid. When the
+    /// server reports it as invalid, the node is registered again, the stale
+    /// id's cached ETag and sync token are dropped and the new register_id is
+    /// returned. Errors from `verify` itself are propagated.
+    pub async fn ensure_registered(
+        &self,
+        node_type: NodeType,
+        node_id: impl Into<NodeId>,
+        register_id: &str,
+        request: RegisterRequest,
+    ) -> Result<String> {
+        let node_id = node_id.into();
+        if self.verify(node_type, register_id).await? {
+            return Ok(register_id.to_string());
+        }
+
+        debug!(
+            "register_id {} is no longer valid, re-registering {} node {}",
+            register_id, node_type, node_id
+        );
+        self.forget_user_state(&CacheKey::new(node_type, register_id));
+        untrack_registration(&self.registrations, node_type, register_id);
+        self.register(node_type, node_id, request).await
+    }
+
     // ==================== User Management APIs ====================
 
     /// Get raw users data with ETag caching support
     pub async fn raw_users(&self, node_type: NodeType, register_id: &str) -> Result<Vec<u8>> {
-        let path = format!("/api/v1/server/enhanced/{}/users", node_type);
+        let path = Endpoints::users(node_type);
         let params = [("register_id", register_id)];
-        let cache_key = format!("{}:{}", node_type, register_id);
+        let cache_key = CacheKey::new(node_type, register_id);
 
-        let response = self.get_with_etag(&path, &params, &cache_key).await?;
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
+        let response = self
+            .get_with_etag(Some(node_type), &path, &params, &cache_key, true)
+            .await?;
+        let (_, bytes) = self.read_body(response, &path).await?;
 
         Ok(bytes.to_vec())
     }
 
     /// Get parsed user list
+    ///
+    /// Concurrent calls for the same node type and register ID share a single
+    /// HTTP request; every caller receives a copy of its result.
     pub async fn users(&self, node_type: NodeType, register_id: &str) -> Result<Vec<User>> {
-        let path = format!("/api/v1/server/enhanced/{}/users", node_type);
-        let params = [("register_id", register_id)];
-        let cache_key = format!("{}:{}", node_type, register_id);
+        let cache_key = CacheKey::new(node_type, register_id);
 
-        let response = self.get_with_etag(&path, &params, &cache_key).await?;
-        let bytes = response
-            .bytes()
+        let flight = self
+            .users_inflight
+            .write()
             .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
-        let api_response: ApiResponse<Vec<User>> = serde_json::from_slice(&bytes)
-            .map_err(|e| ApiError::parse_error(e.to_string(), &path, Some(e)))?;
+            .entry(cache_key.clone())
+            .or_default()
+            .clone();
+
+        let result = flight
+            .get_or_init(|| async {
+                let result = self
+                    .fetch_users(node_type, register_id, &cache_key, true)
+                    .await;
+
+                // Later callers must start a new request rather than reuse this result
+                let mut inflight = self.users_inflight.write().await;
+                if inflight
+                    .get(&cache_key)
+                    .is_some_and(|current| Arc::ptr_eq(current, &flight))
+                {
+                    inflight.remove(&cache_key);
+                }
+
+                result
+            })
+            .await;
+
+        match result {
+            Ok(users) => Ok(users.clone()),
+            Err(e) => Err(e.duplicate()),
+        }
+    }
+
+    /// Get the user list, bypassing the cached ETag
+    ///
+    /// Sends no `If-None-Match`, so the panel always returns the full list.
+    /// The fresh ETag replaces the cached one.
+    pub async fn users_force(&self, node_type: NodeType, register_id: &str) -> Result<Vec<User>> {
+        let cache_key = CacheKey::new(node_type, register_id);
+        self.fetch_users(node_type, register_id, &cache_key, false)
+            .await
+    }
+
+    /// Fetch and parse the user list without request sharing
+    async fn fetch_users(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        cache_key: &CacheKey,
+        revalidate: bool,
+    ) -> Result<Vec<User>> {
+        let path = Endpoints::users(node_type);
+        let params = [("register_id", register_id)];
+
+        let response = self
+            .get_with_etag(Some(node_type), &path, &params, cache_key, revalidate)
+            .await?;
+        let (status, bytes) = self.read_body(response, &path).await?;
+        let api_response: ApiResponse<Vec<User>> = parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
 
         Ok(api_response.data)
     }
 
+    /// Get users as an iterator that parses entries on demand
+    ///
+    /// Avoids materializing the whole `Vec<User>` for large nodes. ETag handling
+    /// matches [`users`](Self::users): an unchanged list yields `NotModified`.
+    pub async fn users_iter(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<impl Iterator<Item = Result<User>>> {
+        let path = Endpoints::users(node_type);
+        let params = [("register_id", register_id)];
+        let cache_key = CacheKey::new(node_type, register_id);
+
+        let response = self
+            .get_with_etag(Some(node_type), &path, &params, &cache_key, true)
+            .await?;
+        let (status, bytes) = self.read_body(response, &path).await?;
+        let api_response: ApiResponse<Box<RawValue>> = parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+        drop(bytes);
+
+        UserArrayIter::new(api_response.data, path)
+    }
+
     /// Get users with ETag information
     pub async fn users_with_etag(
         &self,
         node_type: NodeType,
         register_id: &str,
     ) -> Result<UsersResponse<Vec<User>>> {
-        let path = format!("/api/v1/server/enhanced/{}/users", node_type);
+        let path = Endpoints::users(node_type);
         let params = [("register_id", register_id)];
-        let cache_key = format!("{}:{}", node_type, register_id);
+        let cache_key = CacheKey::new(node_type, register_id);
 
-        let response = self.get_with_etag(&path, &params, &cache_key).await?;
+        let response = self
+            .get_with_etag(Some(node_type), &path, &params, &cache_key, true)
+            .await?;
 
         let etag = response
             .headers()
@@ -346,44 +1510,329 @@ impl ApiClient {
             .and_then(|v| v.to_str().ok())
             .map(|s| s.to_string());
 
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| ApiError::network_error(e.to_string(), &path, Some(e)))?;
-        let api_response: ApiResponse<Vec<User>> = serde_json::from_slice(&bytes)
-            .map_err(|e| ApiError::parse_error(e.to_string(), &path, Some(e)))?;
+        let (status, bytes) = self.read_body(response, &path).await?;
+        let api_response: ApiResponse<Vec<User>> = parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
 
         Ok(UsersResponse::new(api_response.data, etag))
     }
 
+    /// Get users, reporting an unchanged list instead of failing
+    ///
+    /// Distinguishes a fresh list (possibly empty, see
+    /// [`UsersResponse::is_empty`]) from a 304, which yields
+    /// [`UsersResponse::is_not_modified`] with the cached ETag and empty
+    /// `data` that must be ignored.
+    pub async fn users_with_status(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<UsersResponse<Vec<User>>> {
+        match self.users_with_etag(node_type, register_id).await {
+            Err(ApiError::NotModified { .. }) => {
+                let etag = self.cache().get(&CacheKey::new(node_type, register_id));
+                Ok(UsersResponse::unchanged(Vec::new(), etag))
+            }
+            result => result,
+        }
+    }
+
+    /// Fetch the users changed since the previous sync
+    ///
+    /// The panel's `next_token` is remembered per registration and sent as
+    /// `sync_token` on the next call; without a stored token the full list is
+    /// fetched.
+    pub async fn users_sync(&self, node_type: NodeType, register_id: &str) -> Result<UserSync> {
+        let path = Endpoints::users(node_type);
+        let cache_key = CacheKey::new(node_type, register_id);
+        let sync_token = self.sync_tokens().get(&cache_key).cloned();
+
+        let mut params = vec![("register_id", register_id)];
+        if let Some(sync_token) = &sync_token {
+            params.push(("sync_token", sync_token));
+        }
+
+        let response = self.get(Some(node_type), &path, &params).await?;
+        let (status, bytes) = self.read_body(response, &path).await?;
+        let api_response: ApiResponse<UserSync> = parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+        let sync = api_response.data;
+
+        let mut sync_tokens = self.sync_tokens();
+        if sync.next_token.is_empty() {
+            sync_tokens.remove(&cache_key);
+        } else {
+            sync_tokens.insert(cache_key, sync.next_token.clone());
+        }
+
+        Ok(sync)
+    }
+
+    /// Get the user list only if it changed since the last call
+    ///
+    /// Returns `Ok(None)` when the server replies 304 Not Modified instead of
+    /// surfacing [`ApiError::NotModified`].
+    pub async fn users_changed(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+    ) -> Result<Option<Vec<User>>> {
+        match self.users(node_type, register_id).await {
+            Ok(users) => Ok(Some(users)),
+            Err(ApiError::NotModified { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Prefetch user lists to populate the ETag cache
+    ///
+    /// Every entry is attempted; `NotModified` counts as success. Failed
+    /// registrations are returned with their error in input order, so an empty
+    /// list means every entry was warmed.
+    pub async fn warm_cache(
+        &self,
+        entries: &[(NodeType, &str)],
+    ) -> Vec<(NodeType, String, ApiError)> {
+        let calls = entries.iter().map(|&(node_type, register_id)| async move {
+            let result = self.users_with_etag(node_type, register_id).await;
+            (node_type, register_id, result)
+        });
+        let results = futures_util::future::join_all(calls).await;
+
+        warm_cache_failures(results)
+    }
+
+    /// Fetch users for several registrations concurrently under one deadline
+    ///
+    /// Fetches still running at `deadline` are abandoned and reported as
+    /// [`ApiError::Timeout`]. Results are returned in input order.
+    pub async fn users_many_deadline(
+        &self,
+        entries: &[(NodeType, &str)],
+        deadline: Instant,
+    ) -> Vec<(NodeType, String, Result<Vec<User>>)> {
+        let deadline = tokio::time::Instant::from_std(deadline);
+
+        let calls = entries.iter().map(|&(node_type, register_id)| async move {
+            let fetch = self.users(node_type, register_id);
+            let result = tokio::time::timeout_at(deadline, fetch)
+                .await
+                .unwrap_or_else(|_| Err(ApiError::timeout(Endpoints::users(node_type))));
+            (node_type, register_id.to_string(), result)
+        });
+
+        futures_util::future::join_all(calls).await
+    }
+
+    /// Get parsed user list, aborting when `cancel` completes
+    ///
+    /// Returns [`ApiError::Cancelled`] if `cancel` resolves first.
+    pub async fn users_cancellable(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<Vec<User>> {
+        self.cancellable(self.users(node_type, register_id), cancel)
+            .await
+    }
+
+    /// Poll the user list continuously as a stream
+    ///
+    /// Polls on every `interval` tick using ETag caching. `NotModified` responses
+    /// are skipped, so an item is only yielded when the user list changed. Errors
+    /// are yielded without terminating the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn users_stream(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<Vec<User>>> + Send + 'static {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let state = (self.clone(), register_id.to_string(), ticker);
+
+        stream::unfold(state, move |(client, register_id, mut ticker)| async move {
+            loop {
+                ticker.tick().await;
+                match client.users_with_etag(node_type, &register_id).await {
+                    Ok(response) => {
+                        return Some((Ok(response.data), (client, register_id, ticker)));
+                    }
+                    Err(ApiError::NotModified { .. }) => continue,
+                    Err(e) => return Some((Err(e), (client, register_id, ticker))),
+                }
+            }
+        })
+    }
+
     // ==================== Traffic/Statistics APIs ====================
 
     /// Submit user traffic data
+    ///
+    /// The request carries a new `Idempotency-Key` on every call. To retry
+    /// under a stable key, so the panel can dedupe an attempt whose response
+    /// was lost, use [`submit_idempotent`](Self::submit_idempotent).
     pub async fn submit(
         &self,
         node_type: NodeType,
         register_id: &str,
         data: Vec<UserTraffic>,
     ) -> Result<()> {
-        let path = format!("/api/v1/server/enhanced/{}/submit", node_type);
-        let request = SubmitRequest::new(register_id, data);
+        self.submit_request(node_type, &SubmitRequest::new(register_id, data))
+            .await
+    }
+
+    /// Submit a prepared traffic request like [`submit`](Self::submit)
+    ///
+    /// The request is borrowed so the caller keeps its entries if it fails.
+    pub(crate) async fn submit_request(
+        &self,
+        node_type: NodeType,
+        request: &SubmitRequest,
+    ) -> Result<()> {
+        let path = Endpoints::submit(node_type);
 
-        self.post(&path, &[], &request).await?;
-        Ok(())
+        let response = self
+            .submit_traffic(Some(node_type), &path, request, &[])
+            .await?;
+        self.discard_body(response, &path).await
+    }
+
+    /// Submit user traffic data and return the panel's acknowledgement
+    pub async fn submit_ack(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<SubmitAck> {
+        let path = Endpoints::submit(node_type);
+
+        let response = self
+            .submit_traffic(
+                Some(node_type),
+                &path,
+                &SubmitRequest::new(register_id, data),
+                &[],
+            )
+            .await?;
+        self.read_ack(response, &path).await
+    }
+
+    /// Submit user traffic data and report which users the panel accepted
+    ///
+    /// Panels that reply without per-user detail, e.g. with `"data": null` or
+    /// `"data": true`, yield an empty result.
+    pub async fn submit_detailed(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<SubmitResult> {
+        let path = Endpoints::submit(node_type);
+
+        let response = self
+            .submit_traffic(
+                Some(node_type),
+                &path,
+                &SubmitRequest::new(register_id, data),
+                &[],
+            )
+            .await?;
+        let (status, bytes) = self.read_body(response, &path).await?;
+        let api_response: ApiResponse<serde_json::Value> =
+            parse_api_response(&bytes, status, &path)?;
+        self.notify_warning(&api_response);
+
+        submit_result(api_response.data, &path, &bytes)
+    }
+
+    /// Submit user traffic data with an `Idempotency-Key` header
+    ///
+    /// The key identifies one logical submission and must stay the same across
+    /// retries so the panel can dedupe requests whose response was lost.
+    pub async fn submit_idempotent(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+        key: &str,
+    ) -> Result<()> {
+        let path = Endpoints::submit(node_type);
+
+        let response = self
+            .submit_traffic(
+                Some(node_type),
+                &path,
+                &SubmitRequest::new(register_id, data),
+                &[(IDEMPOTENCY_KEY_HEADER, key)],
+            )
+            .await?;
+        self.discard_body(response, &path).await
     }
 
     /// Submit traffic data with agent information
+    ///
+    /// Carries an `Idempotency-Key` like [`submit`](Self::submit).
     pub async fn submit_with_agent(
         &self,
         node_type: NodeType,
         register_id: &str,
         data: Vec<UserTraffic>,
     ) -> Result<()> {
-        let path = format!("/api/v1/server/enhanced/{}/submitWithAgent", node_type);
-        let request = SubmitRequest::new(register_id, data);
+        let path = Endpoints::submit_with_agent(node_type);
 
-        self.post(&path, &[], &request).await?;
-        Ok(())
+        let response = self
+            .submit_traffic(
+                Some(node_type),
+                &path,
+                &SubmitRequest::new(register_id, data),
+                &[],
+            )
+            .await?;
+        self.discard_body(response, &path).await
+    }
+
+    /// Submit traffic data with agent information and return the acknowledgement
+    pub async fn submit_with_agent_ack(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<SubmitAck> {
+        let path = Endpoints::submit_with_agent(node_type);
+
+        let response = self
+            .submit_traffic(
+                Some(node_type),
+                &path,
+                &SubmitRequest::new(register_id, data),
+                &[],
+            )
+            .await?;
+        self.read_ack(response, &path).await
+    }
+
+    /// Submit user traffic data together with the currently online user IDs
+    pub async fn submit_with_online(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+        online: Vec<i64>,
+    ) -> Result<()> {
+        let path = Endpoints::submit_with_agent(node_type);
+        let request = SubmitRequest::new(register_id, data).with_online_users(online);
+
+        let response = self
+            .submit_traffic(Some(node_type), &path, &request, &[])
+            .await?;
+        self.discard_body(response, &path).await
     }
 
     /// Submit aggregated traffic statistics
@@ -393,22 +1842,89 @@ impl ApiClient {
         register_id: &str,
         data: TrafficStats,
     ) -> Result<()> {
-        let path = format!("/api/v1/server/enhanced/{}/submitStatsWithAgent", node_type);
+        let path = Endpoints::submit_stats_with_agent(node_type);
         let request = SubmitStatsRequest::new(register_id, data);
 
-        self.post(&path, &[], &request).await?;
-        Ok(())
+        let response = self
+            .post_submission(Some(node_type), &path, &request, &[])
+            .await?;
+        self.discard_body(response, &path).await
+    }
+
+    /// Submit aggregated traffic statistics and return the acknowledgement
+    pub async fn submit_stats_with_agent_ack(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: TrafficStats,
+    ) -> Result<SubmitAck> {
+        let path = Endpoints::submit_stats_with_agent(node_type);
+        let request = SubmitStatsRequest::new(register_id, data);
+
+        let response = self
+            .post_submission(Some(node_type), &path, &request, &[])
+            .await?;
+        self.read_ack(response, &path).await
+    }
+
+    /// Submit per-user traffic and the statistics derived from it
+    ///
+    /// Posts `data` via `submit_with_agent`, then the matching
+    /// `TrafficStats::from_traffic` via `submit_stats_with_agent`. The stats
+    /// are not sent if the traffic submission fails.
+    pub async fn submit_traffic_and_stats(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        data: Vec<UserTraffic>,
+    ) -> Result<()> {
+        let stats = TrafficStats::from_traffic(&data);
+
+        self.submit_with_agent(node_type, register_id, data).await?;
+        self.submit_stats_with_agent(node_type, register_id, stats)
+            .await
+    }
+
+    /// Submit traffic received on `rx` in batches from a background task
+    ///
+    /// A batch is sent via [`submit`](Self::submit) every `flush_interval`, or
+    /// once `max_batch` entries are buffered. Failed batches are reported
+    /// through the returned [`SubmitWorker`]. Must be called within a tokio
+    /// runtime.
+    pub fn spawn_submit_worker(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        rx: tokio::sync::mpsc::Receiver<UserTraffic>,
+        flush_interval: Duration,
+        max_batch: usize,
+    ) -> Result<SubmitWorker> {
+        if flush_interval.is_zero() {
+            return Err(ApiError::config_error("flush_interval must not be zero"));
+        }
+        if max_batch == 0 {
+            return Err(ApiError::config_error("max_batch must not be zero"));
+        }
+
+        Ok(SubmitWorker::spawn(
+            self.clone(),
+            node_type,
+            register_id.to_string(),
+            rx,
+            flush_interval,
+            max_batch,
+        ))
     }
 
     // ==================== Health Monitoring APIs ====================
 
     /// Send heartbeat to server
     pub async fn heartbeat(&self, node_type: NodeType, register_id: &str) -> Result<()> {
-        let path = format!("/api/v1/server/enhanced/{}/heartbeat", node_type);
+        let path = Endpoints::heartbeat(node_type);
         let request = HeartbeatRequest::new(register_id);
 
-        self.post(&path, &[], &request).await?;
-        Ok(())
+        let response = self.post(Some(node_type), &path, &[], &request).await?;
+        self.discard_body(response, &path).await
     }
 
     /// Send heartbeat with node IP
@@ -418,25 +1934,867 @@ impl ApiClient {
         register_id: &str,
         node_ip: &str,
     ) -> Result<()> {
-        let path = format!("/api/v1/server/enhanced/{}/heartbeat", node_type);
+        let path = Endpoints::heartbeat(node_type);
         let request = HeartbeatRequest::new(register_id).with_node_ip(node_ip);
 
-        self.post(&path, &[], &request).await?;
-        Ok(())
+        let response = self.post(Some(node_type), &path, &[], &request).await?;
+        self.discard_body(response, &path).await
+    }
+
+    /// Send heartbeat with the node's current resource usage
+    pub async fn heartbeat_with_load(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        load: NodeLoad,
+    ) -> Result<()> {
+        let path = Endpoints::heartbeat(node_type);
+        let request = HeartbeatRequest::new(register_id).with_load(load);
+
+        let response = self.post(Some(node_type), &path, &[], &request).await?;
+        self.discard_body(response, &path).await
+    }
+
+    /// Send heartbeats periodically, backing off on repeated failure
+    ///
+    /// The first heartbeat is sent immediately. Each attempt's result is
+    /// yielded; after consecutive failures the wait doubles, up to
+    /// `MAX_HEARTBEAT_BACKOFF` (or `base_interval` if larger), and resets to
+    /// `base_interval` after a success. A `Retry-After` from a rate-limited
+    /// heartbeat overrides the backoff, but never waits less than
+    /// `base_interval`. With a [retry budget](Config::with_retry_budget), a
+    /// failed heartbeat that finds the budget exhausted waits the full cap.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use futures_util::StreamExt;
+    /// use server_r_client::{ApiClient, Config, NodeType};
+    /// use std::time::Duration;
+    ///
+    /// # async fn run(client: ApiClient) {
+    /// let beats = client.heartbeat_loop(NodeType::Trojan, "reg-1", Duration::from_secs(30));
+    /// tokio::pin!(beats);
+    /// while let Some(result) = beats.next().await {
+    ///     if let Err(e) = result {
+    ///         eprintln!("heartbeat failed: {}", e);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn heartbeat_loop(
+        &self,
+        node_type: NodeType,
+        register_id: &str,
+        base_interval: Duration,
+    ) -> impl Stream<Item = Result<()>> + Send + 'static {
+        let cap = MAX_HEARTBEAT_BACKOFF.max(base_interval);
+        let state = (self.clone(), register_id.to_string(), Duration::ZERO, 0u32);
+
+        stream::unfold(
+            state,
+            move |(client, register_id, wait, failures)| async move {
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+
+                let result = client.heartbeat(node_type, &register_id).await;
+                let failures = if result.is_ok() { 0 } else { failures + 1 };
+                let mut wait = match result.as_ref().err().and_then(ApiError::retry_after) {
+                    Some(retry_after) => retry_after.max(base_interval),
+                    None => base_interval.saturating_mul(1 << failures.min(16)).min(cap),
+                };
+                if result.is_err() && !client.try_acquire_retry() {
+                    wait = wait.max(cap);
+                }
+
+                Some((result, (client, register_id, wait, failures)))
+            },
+        )
+    }
+
+    /// Detect this host's public IP address
+    ///
+    /// Queries `Config::ip_echo_url`, which must answer with the bare IPv4 or
+    /// IPv6 address. The result can be passed to `RegisterRequest::with_node_ip`
+    /// or `heartbeat_with_ip`.
+    ///
+    /// The endpoint is not the panel: the request skips the observer, metrics,
+    /// retry budget, token rotation and clock skew tracking.
+    pub async fn detect_public_ip(&self) -> Result<String> {
+        let url = &self.config.ip_echo_url;
+        let response = self
+            .http_client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| ApiError::network_error(e.to_string(), url, Some(e)))?;
+        let response = self.check_response(response, url).await?;
+        let (_, body) = self.read_body(response, url).await?;
+
+        parse_ip_echo(&String::from_utf8_lossy(&body), url)
+    }
+
+    /// Race any API call against a cancel signal
+    ///
+    /// Returns [`ApiError::Cancelled`] if `cancel` resolves before `operation`,
+    /// dropping the in-flight request.
+    ///
+    /// ```rust,no_run
+    /// # use server_r_client::{ApiClient, NodeType};
+    /// # async fn example(client: &ApiClient) {
+    /// let cancel = async {
+    ///     let _ = tokio::signal::ctrl_c().await;
+    /// };
+    /// let result = client
+    ///     .cancellable(client.heartbeat(NodeType::Trojan, "register-id"), cancel)
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn cancellable<T>(
+        &self,
+        operation: impl Future<Output = Result<T>>,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<T> {
+        tokio::select! {
+            result = operation => result,
+            _ = cancel => {
+                if self.config.debug {
+                    debug!("Request cancelled");
+                }
+                Err(ApiError::Cancelled)
+            }
+        }
     }
 
     // ==================== Utility Methods ====================
 
+    /// Get the token currently used for requests
+    ///
+    /// Starts as `Config::token` and changes when the panel rotates it.
+    pub fn token(&self) -> String {
+        self.token.read().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// Build the URL a request to `path` would use, without sending it
+    ///
+    /// Parameters are encoded exactly as for a real request; the token value
+    /// is replaced with `REDACTED`. A `path` that does not start with `/` or
+    /// carries a query or fragment is a `ConfigError`.
+    pub fn preview_url(&self, path: &str, params: &[(&str, &str)]) -> Result<String> {
+        build_url(
+            &self.base_url,
+            "REDACTED",
+            path,
+            params,
+            self.config.query_encoding,
+        )
+    }
+
+    /// Get per-endpoint request latency, keyed by URL path
+    #[cfg(feature = "metrics")]
+    pub async fn latency_snapshot(&self) -> HashMap<String, LatencyStats> {
+        self.latency.read().await.snapshot()
+    }
+
+    /// Open a connection to `api_host` so later requests reuse it
+    ///
+    /// Sends a `HEAD` request to the base URL, paying DNS resolution and the
+    /// TLS handshake up front. Any HTTP status counts as success; only
+    /// transport failures are returned, and callers may safely ignore them.
+    pub async fn warmup(&self) -> Result<()> {
+        let started = Instant::now();
+        let result = self.http_client.head(&self.base_url).send().await;
+
+        match result {
+            Ok(response) => {
+                debug!(
+                    "warmup to {} returned {} in {:?}",
+                    self.base_url,
+                    response.status(),
+                    started.elapsed()
+                );
+                Ok(())
+            }
+            Err(e) => {
+                debug!("warmup to {} failed: {}", self.base_url, e);
+                Err(ApiError::network_error(
+                    e.to_string(),
+                    &self.base_url,
+                    Some(e),
+                ))
+            }
+        }
+    }
+
+    /// Send a GET request and return the raw response headers
+    ///
+    /// Headers are returned for any HTTP status, so hints such as
+    /// `Retry-After` or `X-RateLimit-Remaining` on a 429 or 503 are readable.
+    /// Only transport failures are reported as errors.
+    pub async fn get_headers(&self, path: &str, params: &[(&str, &str)]) -> Result<HeaderMap> {
+        let url = self.build_url(path, params)?;
+
+        if self.config.debug {
+            debug!("GET (headers) {}", url);
+        }
+
+        let request = self.http_client.get(&url);
+        let response = self.send(None, request, "GET", &url).await?;
+
+        Ok(response.headers().clone())
+    }
+
     /// Clear the ETag cache
     pub async fn clear_etag_cache(&self) {
-        self.etag_cache.write().await.clear();
+        self.cache().clear();
     }
 
     /// Get the current ETag for a cache key
     pub async fn get_etag(&self, node_type: NodeType, register_id: &str) -> Option<String> {
-        let cache_key = format!("{}:{}", node_type, register_id);
-        self.etag_cache.read().await.get(&cache_key).cloned()
+        let cache_key = CacheKey::new(node_type, register_id);
+        self.cache().peek(&cache_key)
+    }
+
+    /// Remove the cached ETag for a single registration
+    pub async fn remove_etag(&self, node_type: NodeType, register_id: &str) -> Option<String> {
+        let cache_key = CacheKey::new(node_type, register_id);
+        self.cache().remove(&cache_key)
+    }
+
+    /// Get the number of entries in the ETag cache
+    pub async fn etag_cache_len(&self) -> usize {
+        self.cache().len()
+    }
+
+    /// Get the keys currently present in the ETag cache
+    ///
+    /// Keys have the form `"{node_type}:{register_id}"`.
+    pub async fn etag_cache_keys(&self) -> Vec<String> {
+        self.cache().keys()
+    }
+
+    /// Subscribe to ETag cache updates
+    ///
+    /// Receives `(cache_key, new_etag)` whenever a user list response stores a
+    /// different ETag. Keys have the form `"{node_type}:{register_id}"`. All
+    /// clones of this client share the same channel; slow receivers may miss
+    /// updates and see `RecvError::Lagged`.
+    pub fn etag_change_receiver(&self) -> broadcast::Receiver<(String, String)> {
+        self.etag_changes.subscribe()
+    }
+
+    /// Spend one credit of the shared retry budget before retrying a call
+    ///
+    /// Returns `false` when `Config::retry_budget` is exhausted, in which case
+    /// the caller should give up rather than retry. Always `true` without a
+    /// budget. Credits are shared by every clone of this client.
+    pub fn try_acquire_retry(&self) -> bool {
+        self.retry_credits
+            .as_ref()
+            .is_none_or(|credits| credits.try_withdraw())
+    }
+
+    /// Stop background workers started from this client, then drop it
+    ///
+    /// Signals every `HeartbeatManager` loop and `SubmitWorker` built on any
+    /// clone of this client, and waits until they have finished; submit
+    /// workers flush their buffered traffic first. Workers started afterwards
+    /// exit immediately. Idle connections are released once the remaining
+    /// clones are dropped as well.
+    pub async fn close(self) {
+        self.shutdown.send_replace(true);
+        self.shutdown.closed().await;
     }
+
+    /// Subscribe to the signal sent by [`close`](Self::close)
+    ///
+    /// Background tasks hold the receiver until they finish, which is what
+    /// `close` waits for.
+    pub(crate) fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// Get the clock skew measured on the latest successful panel response
+    ///
+    /// Taken from the `Date` header of panel API responses, or from `server_time` in submission
+    /// acknowledgements. The value is the absolute difference, with one second
+    /// precision; `None` until the panel has reported its time.
+    pub fn last_server_time_skew(&self) -> Option<Duration> {
+        *self
+            .clock_skew
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Export the register_ids held by this client and their cached ETags
+    ///
+    /// Every successful registration is recorded until it is unregistered or
+    /// replaced by `ensure_registered`. The value can be persisted and handed
+    /// to [`ApiClient::import_state`] after a restart.
+    pub fn export_state(&self) -> Result<serde_json::Value> {
+        export_client_state(&self.registrations, &self.etag_cache)
+    }
+
+    /// Restore state produced by [`ApiClient::export_state`]
+    ///
+    /// Replaces the recorded registrations and seeds the ETag cache with the
+    /// exported ETags. Other cached ETags are kept.
+    pub fn import_state(&self, state: serde_json::Value) -> Result<()> {
+        import_client_state(&self.registrations, &self.etag_cache, state)
+    }
+}
+
+/// Write a config to `path`, replacing the file atomically
+async fn write_config_cache(path: &Path, config: &NodeConfigEnum) -> std::io::Result<()> {
+    let bytes = config
+        .to_api_response_bytes()
+        .map_err(std::io::Error::other)?;
+    let tmp = config_cache_tmp_path(path);
+    tokio::fs::write(&tmp, bytes).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+/// Find the parser registered for a lowercase node type name
+///
+/// Aliases of built-in types, e.g. `ss`, also find the parser registered
+/// under the canonical name.
+pub(crate) fn config_parser<'a>(
+    parsers: &'a HashMap<String, Arc<dyn ConfigParser>>,
+    name: &str,
+) -> Option<&'a Arc<dyn ConfigParser>> {
+    parsers.get(name).or_else(|| {
+        let node_type = name.parse::<NodeType>().ok()?;
+        parsers.get(node_type.as_str())
+    })
+}
+
+/// Read the per-user detail of a submission from a response's `data`
+///
+/// Anything but an object, including `null`, means the panel sent no detail.
+pub(crate) fn submit_result(
+    data: serde_json::Value,
+    url: &str,
+    bytes: &[u8],
+) -> Result<SubmitResult> {
+    if !data.is_object() {
+        return Ok(SubmitResult::default());
+    }
+    serde_json::from_value(data).map_err(|e| body_parse_error(e, url, bytes))
+}
+
+/// Register_ids held by a client, by node type
+pub(crate) type Registrations = Mutex<HashMap<NodeType, Vec<String>>>;
+
+/// Snapshot produced by [`ApiClient::export_state`]
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct ClientState {
+    #[serde(default)]
+    registrations: HashMap<NodeType, Vec<RegistrationState>>,
+}
+
+/// A held registration and its cached user list ETag
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct RegistrationState {
+    register_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+}
+
+/// Record a register_id as held by a client
+pub(crate) fn track_registration(
+    registrations: &Registrations,
+    node_type: NodeType,
+    register_id: &str,
+) {
+    let mut registrations = registrations
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let ids = registrations.entry(node_type).or_default();
+    if !ids.iter().any(|id| id == register_id) {
+        ids.push(register_id.to_string());
+    }
+}
+
+/// Forget a register_id previously recorded by [`track_registration`]
+pub(crate) fn untrack_registration(
+    registrations: &Registrations,
+    node_type: NodeType,
+    register_id: &str,
+) {
+    let mut registrations = registrations
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(ids) = registrations.get_mut(&node_type) {
+        ids.retain(|id| id != register_id);
+        if ids.is_empty() {
+            registrations.remove(&node_type);
+        }
+    }
+}
+
+/// Snapshot held registrations and their cached ETags
+pub(crate) fn export_client_state(
+    registrations: &Registrations,
+    cache: &Mutex<EtagCache>,
+) -> Result<serde_json::Value> {
+    let registrations = registrations
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    let cache = cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let state = ClientState {
+        registrations: registrations
+            .into_iter()
+            .map(|(node_type, ids)| {
+                let entries = ids
+                    .into_iter()
+                    .map(|register_id| RegistrationState {
+                        etag: cache.peek(&CacheKey::new(node_type, &register_id)),
+                        register_id,
+                    })
+                    .collect();
+                (node_type, entries)
+            })
+            .collect(),
+    };
+
+    serde_json::to_value(state).map_err(|e| {
+        ApiError::parse_error(format!("failed to export client state: {}", e), "", Some(e))
+    })
+}
+
+/// Restore a snapshot taken by [`export_client_state`]
+pub(crate) fn import_client_state(
+    registrations: &Registrations,
+    cache: &Mutex<EtagCache>,
+    state: serde_json::Value,
+) -> Result<()> {
+    let state: ClientState = serde_json::from_value(state).map_err(|e| {
+        ApiError::parse_error(format!("failed to import client state: {}", e), "", Some(e))
+    })?;
+
+    let mut restored = HashMap::new();
+    let mut cache = cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (node_type, entries) in state.registrations {
+        let ids: &mut Vec<String> = restored.entry(node_type).or_default();
+        for entry in entries {
+            if let Some(etag) = entry.etag {
+                cache.insert(CacheKey::new(node_type, &entry.register_id), etag);
+            }
+            if !ids.contains(&entry.register_id) {
+                ids.push(entry.register_id);
+            }
+        }
+    }
+    drop(cache);
+
+    restored.retain(|_, ids| !ids.is_empty());
+    *registrations
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = restored;
+    Ok(())
+}
+
+/// Temporary file a config cache is written to before being renamed into place
+pub(crate) fn config_cache_tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Append a path prefix to the base URL's path, normalizing slashes
+pub(crate) fn apply_path_prefix(base_url: &str, prefix: Option<&str>) -> String {
+    let prefix = prefix.map(|p| p.trim_matches('/')).unwrap_or_default();
+    if prefix.is_empty() {
+        return base_url.to_string();
+    }
+
+    let Ok(mut url) = url::Url::parse(base_url) else {
+        return format!("{}/{}", base_url.trim_end_matches('/'), prefix);
+    };
+
+    let full_path = format!("{}/{}", url.path().trim_end_matches('/'), prefix);
+    url.set_path(&full_path);
+    url.into()
+}
+
+/// Build a request URL from the base URL, token and query parameters
+///
+/// `path` is appended to the base URL's path, query parameters already present
+/// on the base URL are kept, and `token` plus `params` are appended after them.
+/// A base URL that does not parse is reported as `ApiError::ConfigError`.
+pub(crate) fn build_url(
+    base_url: &str,
+    token: &str,
+    path: &str,
+    params: &[(&str, &str)],
+    encoding: QueryEncoding,
+) -> Result<String> {
+    let mut url = url::Url::parse(base_url)
+        .map_err(|e| ApiError::config_error(format!("Invalid URL '{}': {}", base_url, e)))?;
+    if !path.starts_with('/') || path.contains(['?', '#']) {
+        return Err(ApiError::config_error(format!(
+            "Invalid URL '{}{}': path must start with '/' and carry no query or fragment",
+            base_url, path
+        )));
+    }
+
+    let full_path = format!("{}{}", url.path().trim_end_matches('/'), path);
+    url.set_path(&full_path);
+
+    match encoding {
+        QueryEncoding::Form => {
+            url.query_pairs_mut()
+                .append_pair("token", token)
+                .extend_pairs(params);
+        }
+        QueryEncoding::Percent => {
+            let mut query = url.query().unwrap_or_default().to_string();
+            for (key, value) in std::iter::once(("token", token)).chain(params.iter().copied()) {
+                if !query.is_empty() {
+                    query.push('&');
+                }
+                query.extend(utf8_percent_encode(key, QUERY_COMPONENT));
+                query.push('=');
+                query.extend(utf8_percent_encode(value, QUERY_COMPONENT));
+            }
+            url.set_query(Some(&query));
+        }
+    }
+
+    Ok(url.into())
+}
+
+/// Everything except RFC 3986 unreserved characters
+const QUERY_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Fail if a declared `Content-Length` exceeds `limit`
+pub(crate) fn check_content_length(length: Option<u64>, limit: usize, url: &str) -> Result<()> {
+    match length {
+        Some(actual) if actual > limit as u64 => Err(ApiError::ResponseTooLarge {
+            limit,
+            actual,
+            url: url.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Fail once the bytes read so far exceed `limit`
+pub(crate) fn check_body_len(read: usize, limit: usize, url: &str) -> Result<()> {
+    if read > limit {
+        return Err(ApiError::ResponseTooLarge {
+            limit,
+            actual: read as u64,
+            url: url.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Header naming one logical traffic submission
+pub(crate) const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Serialize entries as newline-delimited JSON
+///
+/// Errors carry `url` with the token removed.
+pub(crate) fn encode_json_lines<T: serde::Serialize>(entries: &[T], url: &str) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut body, entry)
+            .map_err(|e| ApiError::parse_error(e.to_string(), redact_token(url), Some(e)))?;
+        body.push(b'\n');
+    }
+    Ok(body)
+}
+
+/// Bodies up to this many bytes are not worth compressing
+pub(crate) const REQUEST_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Gzip-compress a request body
+///
+/// Errors carry `url` with the token removed, like [`Encoding::encode`].
+pub(crate) fn gzip(body: &[u8], url: &str) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(body)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| {
+            ApiError::parse_error(
+                format!("failed to compress request body: {}", e),
+                redact_token(url),
+                None,
+            )
+        })
+}
+
+/// Maximum number of response bytes quoted in parse errors
+const PARSE_ERROR_SNIPPET_LEN: usize = 512;
+
+/// Create a parse error quoting the start of the offending response body
+pub(crate) fn body_parse_error(e: serde_json::Error, url: &str, bytes: &[u8]) -> ApiError {
+    let snippet = String::from_utf8_lossy(&bytes[..bytes.len().min(PARSE_ERROR_SNIPPET_LEN)]);
+    let ellipsis = if bytes.len() > PARSE_ERROR_SNIPPET_LEN {
+        "..."
+    } else {
+        ""
+    };
+    ApiError::parse_error(
+        format!("{} (body: {}{})", e, snippet, ellipsis),
+        redact_token(url),
+        Some(e),
+    )
+}
+
+/// Turn a 304 from the ETag cache into `None`
+pub(crate) fn unless_not_modified<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.is_not_modified() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse an API response body, surfacing a non-zero body `code` as a server error
+pub(crate) fn parse_api_response<T: serde::de::DeserializeOwned>(
+    bytes: &[u8],
+    status: u16,
+    url: &str,
+) -> Result<ApiResponse<T>> {
+    /// Envelope without `data`, used when an error body has no usable payload
+    #[derive(serde::Deserialize)]
+    struct Envelope {
+        #[serde(default)]
+        code: Option<i64>,
+        #[serde(default)]
+        message: Option<String>,
+    }
+
+    let code_error = |code: i64, message: Option<String>| {
+        let message = format!("code {}: {}", code, message.unwrap_or_default());
+        error!("API error: {} - {} - {}", status, message, url);
+        ApiError::from_status_code(status, message, url)
+    };
+
+    match serde_json::from_slice::<ApiResponse<T>>(bytes) {
+        Ok(api_response) if api_response.is_ok() => Ok(api_response),
+        Ok(api_response) => Err(code_error(
+            api_response.code.unwrap_or_default(),
+            api_response.message,
+        )),
+        Err(e) => match serde_json::from_slice::<Envelope>(bytes) {
+            Ok(Envelope {
+                code: Some(code),
+                message,
+            }) if code != 0 => Err(code_error(code, message)),
+            _ => Err(body_parse_error(e, url, bytes)),
+        },
+    }
+}
+
+/// Response header carrying a replacement token issued by the panel
+const NEW_TOKEN_HEADER: &str = "X-New-Token";
+
+/// Store a rotated token from a panel response and notify the observer
+///
+/// Responses from outside `base_url` (such as the IP-echo endpoint) are ignored.
+pub(crate) fn apply_token_rotation(
+    headers: &HeaderMap,
+    url: &str,
+    base_url: &str,
+    token: &std::sync::RwLock<String>,
+    observer: Option<&dyn RequestObserver>,
+) {
+    if !is_panel_url(url, base_url) {
+        return;
+    }
+
+    let Some(new_token) = headers
+        .get(NEW_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    else {
+        return;
+    };
+
+    {
+        let mut current = token.write().unwrap_or_else(|p| p.into_inner());
+        if *current == new_token {
+            return;
+        }
+        *current = new_token.to_string();
+    }
+
+    debug!("API token rotated by panel");
+    if let Some(observer) = observer {
+        observer.on_token_rotated(new_token);
+    }
+}
+
+/// Check that `url` has the scheme, host and port of `base_url` and lies under its path
+fn is_panel_url(url: &str, base_url: &str) -> bool {
+    let (Ok(url), Ok(base)) = (url::Url::parse(url), url::Url::parse(base_url)) else {
+        return false;
+    };
+    let base_path = base.path().trim_end_matches('/');
+
+    url.scheme() == base.scheme()
+        && url.host() == base.host()
+        && url.port_or_known_default() == base.port_or_known_default()
+        && url
+            .path()
+            .strip_prefix(base_path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Parse a `Retry-After` header in delta-seconds or HTTP-date form
+///
+/// Dates in the past yield a zero duration.
+pub(crate) fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(
+        at.duration_since(std::time::SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+/// Wait until the client owning `signal` is closed
+pub(crate) async fn closed(signal: &mut watch::Receiver<bool>) {
+    let _ = signal.wait_for(|closed| *closed).await;
+}
+
+/// Default for [`Config::clock_skew_threshold`]
+const DEFAULT_CLOCK_SKEW_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// Measure the distance between the local clock and a `Date` header
+pub(crate) fn date_header_skew(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::DATE)?.to_str().ok()?;
+    let server_time = httpdate::parse_http_date(value.trim()).ok()?;
+    Some(clock_distance(server_time))
+}
+
+/// Measure the distance between the local clock and a Unix timestamp
+pub(crate) fn unix_time_skew(server_time: i64) -> Option<Duration> {
+    let secs = u64::try_from(server_time).ok()?;
+    Some(clock_distance(
+        std::time::UNIX_EPOCH + Duration::from_secs(secs),
+    ))
+}
+
+/// Absolute difference between `server_time` and now
+fn clock_distance(server_time: std::time::SystemTime) -> Duration {
+    let now = std::time::SystemTime::now();
+    now.duration_since(server_time)
+        .or_else(|_| server_time.duration_since(now))
+        .unwrap_or_default()
+}
+
+/// Store the latest clock skew, warning when it crosses the configured threshold
+///
+/// Only the transition past the threshold is reported, not every response.
+pub(crate) fn record_clock_skew(slot: &Mutex<Option<Duration>>, skew: Duration, config: &Config) {
+    let threshold = config.clock_skew_threshold;
+    let previous = slot
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .replace(skew);
+
+    if skew > threshold && previous.is_none_or(|p| p <= threshold) {
+        let message = format!(
+            "local clock is {}s off from the panel (threshold {}s), check NTP",
+            skew.as_secs(),
+            threshold.as_secs()
+        );
+        warn!("{}", message);
+        if let Some(observer) = &config.warning_observer {
+            observer.notify(Some(&message));
+        }
+    }
+}
+
+/// Keep the failed entries of a cache warm-up, treating `NotModified` as success
+pub(crate) fn warm_cache_failures<T>(
+    results: impl IntoIterator<Item = (NodeType, impl AsRef<str>, Result<T>)>,
+) -> Vec<(NodeType, String, ApiError)> {
+    results
+        .into_iter()
+        .filter_map(|(node_type, register_id, result)| match result {
+            Ok(_) | Err(ApiError::NotModified { .. }) => None,
+            Err(e) => Some((node_type, register_id.as_ref().to_string(), e)),
+        })
+        .collect()
+}
+
+/// Parse the plain-text body of an IP-echo endpoint
+pub(crate) fn parse_ip_echo(text: &str, url: &str) -> Result<String> {
+    let text = text.trim();
+    text.parse::<std::net::IpAddr>()
+        .map(|ip| ip.to_string())
+        .map_err(|e| {
+            ApiError::parse_error(format!("invalid IP address '{}': {}", text, e), url, None)
+        })
+}
+
+/// Build a proxy applying to all requests
+pub(crate) fn build_proxy(proxy: &str) -> Result<reqwest::Proxy> {
+    reqwest::Proxy::all(proxy)
+        .map_err(|e| ApiError::config_error(format!("Invalid proxy '{}': {}", proxy, e)))
+}
+
+/// Rewrite `api_host` to use the SNI domain and build a resolver for its original address
+///
+/// Nothing is resolved here; see [`SniResolver`].
+pub(crate) fn sni_target(
+    api_host: &str,
+    sni: &str,
+    family: IpFamily,
+) -> Result<(String, SniResolver)> {
+    let mut url = url::Url::parse(api_host)
+        .map_err(|e| ApiError::config_error(format!("Invalid api_host '{}': {}", api_host, e)))?;
+
+    let port = url.port_or_known_default().unwrap_or(443);
+    let host = match url.host() {
+        Some(url::Host::Ipv4(ip)) => ip.to_string(),
+        Some(url::Host::Ipv6(ip)) => ip.to_string(),
+        Some(url::Host::Domain(domain)) => domain.to_string(),
+        None => {
+            return Err(ApiError::config_error(format!(
+                "Invalid api_host '{}': missing host",
+                api_host
+            )))
+        }
+    };
+
+    url.set_host(Some(sni))
+        .map_err(|e| ApiError::config_error(format!("Invalid sni_override '{}': {}", sni, e)))?;
+
+    Ok((
+        url.as_str().trim_end_matches('/').to_string(),
+        SniResolver::new(sni, host, port, family),
+    ))
 }
 
 impl std::fmt::Debug for ApiClient {
@@ -448,3 +2806,36 @@ impl std::fmt::Debug for ApiClient {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Body whose serialization always fails
+    struct Unserializable;
+
+    impl serde::Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> std::result::Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("unserializable"))
+        }
+    }
+
+    #[test]
+    fn test_encode_errors_redact_token() {
+        let url = "https://api.example.com/api/v1/server/enhanced/trojan/submit?token=secret-token";
+
+        let err = Encoding::Json.encode(&Unserializable, url).unwrap_err();
+        assert!(err.is_parse_error());
+        assert!(!err.to_string().contains("secret-token"));
+
+        #[cfg(feature = "msgpack")]
+        {
+            let err = Encoding::MsgPack.encode(&Unserializable, url).unwrap_err();
+            assert!(!err.to_string().contains("secret-token"));
+        }
+
+        let err = encode_json_lines(&[Unserializable], url).unwrap_err();
+        assert!(err.is_parse_error());
+        assert!(!err.to_string().contains("secret-token"));
+    }
+}
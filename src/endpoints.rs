@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::error::{ApiError, Result};
+use crate::models::NodeType;
+
+/// Base path shared by all node endpoints
+const ENHANCED_PREFIX: &str = "/api/v1/server/enhanced";
+
+/// Single source of truth for panel endpoint paths
+pub(crate) struct Endpoints;
+
+impl Endpoints {
+    /// Build the path of a per-node-type endpoint
+    fn node(node_type: impl fmt::Display, action: &str) -> String {
+        format!("{}/{}/{}", ENHANCED_PREFIX, node_type, action)
+    }
+
+    /// Node configuration
+    pub(crate) fn config(node_type: NodeType) -> String {
+        Self::node(node_type, "config")
+    }
+
+    /// Node configuration for a node type given by name
+    ///
+    /// The name becomes a path segment, so only ASCII letters, digits, `-`
+    /// and `_` are accepted.
+    pub(crate) fn config_by_name(node_type: &str) -> Result<String> {
+        let valid = !node_type.is_empty()
+            && node_type
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ApiError::config_error(format!(
+                "invalid node type name '{}'",
+                node_type
+            )));
+        }
+        Ok(Self::node(node_type, "config"))
+    }
+
+    /// Node registration
+    pub(crate) fn register(node_type: NodeType) -> String {
+        Self::node(node_type, "register")
+    }
+
+    /// Node unregistration
+    pub(crate) fn unregister(node_type: NodeType) -> String {
+        Self::node(node_type, "unregister")
+    }
+
+    /// Registration verification
+    pub(crate) fn verify(node_type: NodeType) -> String {
+        Self::node(node_type, "verify")
+    }
+
+    /// User list
+    pub(crate) fn users(node_type: NodeType) -> String {
+        Self::node(node_type, "users")
+    }
+
+    /// Per-user traffic submission
+    pub(crate) fn submit(node_type: NodeType) -> String {
+        Self::node(node_type, "submit")
+    }
+
+    /// Per-user traffic submission with agent information
+    pub(crate) fn submit_with_agent(node_type: NodeType) -> String {
+        Self::node(node_type, "submitWithAgent")
+    }
+
+    /// Node statistics submission with agent information
+    pub(crate) fn submit_stats_with_agent(node_type: NodeType) -> String {
+        Self::node(node_type, "submitStatsWithAgent")
+    }
+
+    /// Heartbeat
+    pub(crate) fn heartbeat(node_type: NodeType) -> String {
+        Self::node(node_type, "heartbeat")
+    }
+}
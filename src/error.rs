@@ -1,3 +1,4 @@
+use std::time::Duration;
 use thiserror::Error;
 
 /// Error types for API operations
@@ -7,10 +8,20 @@ pub enum ErrorType {
     ServerError,
     /// Connection/network failures
     NetworkError,
+    /// HTTP 401/403 authentication failures
+    Auth,
+    /// HTTP 429 rate limiting
+    RateLimited,
     /// JSON parsing failures
     ParseError,
     /// HTTP 304 Not Modified
     NotModified,
+    /// Response body exceeded the configured size limit
+    ResponseTooLarge,
+    /// Request cancelled by the caller
+    Cancelled,
+    /// Caller-supplied deadline passed before the request finished
+    Timeout,
     /// Unexpected errors
     Unknown,
 }
@@ -25,6 +36,15 @@ pub enum ApiError {
         url: String,
     },
 
+    #[error("Authentication failed (status {status_code}) - URL: {url}")]
+    AuthError { status_code: u16, url: String },
+
+    #[error("Rate limited (retry after {retry_after:?}) - URL: {url}")]
+    RateLimited {
+        retry_after: Option<Duration>,
+        url: String,
+    },
+
     #[error("Network error: {message} - URL: {url}")]
     NetworkError {
         message: String,
@@ -44,6 +64,20 @@ pub enum ApiError {
     #[error("Not modified (304) - URL: {url}")]
     NotModified { url: String },
 
+    /// `actual` is the `Content-Length`, or the bytes read before giving up
+    #[error("Response too large: {actual} bytes exceeds limit of {limit} - URL: {url}")]
+    ResponseTooLarge {
+        limit: usize,
+        actual: u64,
+        url: String,
+    },
+
+    #[error("Request cancelled")]
+    Cancelled,
+
+    #[error("Deadline exceeded - URL: {url}")]
+    Timeout { url: String },
+
     #[error("Unknown error: {message}")]
     Unknown { message: String },
 
@@ -59,9 +93,14 @@ impl ApiError {
     pub fn error_type(&self) -> ErrorType {
         match self {
             ApiError::ServerError { .. } => ErrorType::ServerError,
+            ApiError::AuthError { .. } => ErrorType::Auth,
+            ApiError::RateLimited { .. } => ErrorType::RateLimited,
             ApiError::NetworkError { .. } => ErrorType::NetworkError,
             ApiError::ParseError { .. } => ErrorType::ParseError,
             ApiError::NotModified { .. } => ErrorType::NotModified,
+            ApiError::ResponseTooLarge { .. } => ErrorType::ResponseTooLarge,
+            ApiError::Cancelled => ErrorType::Cancelled,
+            ApiError::Timeout { .. } => ErrorType::Timeout,
             ApiError::Unknown { .. }
             | ApiError::ConfigError { .. }
             | ApiError::TypeConversionError { .. } => ErrorType::Unknown,
@@ -73,6 +112,24 @@ impl ApiError {
         matches!(self, ApiError::ServerError { .. })
     }
 
+    /// Check if this is an authentication error (401/403)
+    pub fn is_auth_error(&self) -> bool {
+        matches!(self, ApiError::AuthError { .. })
+    }
+
+    /// Check if this is a 429 rate-limit response
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, ApiError::RateLimited { .. })
+    }
+
+    /// Get the server-requested wait before retrying, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ApiError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
     /// Check if this is a network error
     pub fn is_network_error(&self) -> bool {
         matches!(self, ApiError::NetworkError { .. })
@@ -88,16 +145,84 @@ impl ApiError {
         matches!(self, ApiError::NotModified { .. })
     }
 
+    /// Check if the response body exceeded `Config::max_response_bytes`
+    pub fn is_response_too_large(&self) -> bool {
+        matches!(self, ApiError::ResponseTooLarge { .. })
+    }
+
+    /// Check if the request was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, ApiError::Cancelled)
+    }
+
+    /// Check if a caller-supplied deadline passed
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ApiError::Timeout { .. })
+    }
+
+    /// Prefix the message of message-bearing variants with `context`
+    pub(crate) fn with_context(self, context: impl std::fmt::Display) -> Self {
+        match self {
+            ApiError::ServerError {
+                status_code,
+                message,
+                url,
+            } => ApiError::ServerError {
+                status_code,
+                message: format!("{}: {}", context, message),
+                url,
+            },
+            ApiError::NetworkError {
+                message,
+                url,
+                source,
+            } => ApiError::NetworkError {
+                message: format!("{}: {}", context, message),
+                url,
+                source,
+            },
+            ApiError::ParseError {
+                message,
+                url,
+                source,
+            } => ApiError::ParseError {
+                message: format!("{}: {}", context, message),
+                url,
+                source,
+            },
+            ApiError::Unknown { message } => ApiError::Unknown {
+                message: format!("{}: {}", context, message),
+            },
+            ApiError::ConfigError { message } => ApiError::ConfigError {
+                message: format!("{}: {}", context, message),
+            },
+            other => other,
+        }
+    }
+
     /// Create a server error from status code
+    ///
+    /// 401 and 403 produce an [`ApiError::AuthError`], 429 an
+    /// [`ApiError::RateLimited`] without a retry hint.
     pub fn from_status_code(
         status_code: u16,
         message: impl Into<String>,
         url: impl Into<String>,
     ) -> Self {
-        ApiError::ServerError {
-            status_code,
-            message: message.into(),
-            url: url.into(),
+        match status_code {
+            401 | 403 => ApiError::AuthError {
+                status_code,
+                url: url.into(),
+            },
+            429 => ApiError::RateLimited {
+                retry_after: None,
+                url: url.into(),
+            },
+            _ => ApiError::ServerError {
+                status_code,
+                message: message.into(),
+                url: url.into(),
+            },
         }
     }
 
@@ -132,6 +257,11 @@ impl ApiError {
         ApiError::NotModified { url: url.into() }
     }
 
+    /// Create a deadline-exceeded error
+    pub fn timeout(url: impl Into<String>) -> Self {
+        ApiError::Timeout { url: url.into() }
+    }
+
     /// Create a config error
     pub fn config_error(message: impl Into<String>) -> Self {
         ApiError::ConfigError {
@@ -146,6 +276,52 @@ impl ApiError {
             actual: actual.into(),
         }
     }
+
+    /// Copy this error for another awaiter of a shared request
+    ///
+    /// The underlying `source` is not cloneable and is dropped from the copy.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            ApiError::ServerError {
+                status_code,
+                message,
+                url,
+            } => ApiError::ServerError {
+                status_code: *status_code,
+                message: message.clone(),
+                url: url.clone(),
+            },
+            ApiError::AuthError { status_code, url } => ApiError::AuthError {
+                status_code: *status_code,
+                url: url.clone(),
+            },
+            ApiError::RateLimited { retry_after, url } => ApiError::RateLimited {
+                retry_after: *retry_after,
+                url: url.clone(),
+            },
+            ApiError::NetworkError { message, url, .. } => {
+                ApiError::network_error(message.clone(), url.clone(), None)
+            }
+            ApiError::ParseError { message, url, .. } => {
+                ApiError::parse_error(message.clone(), url.clone(), None)
+            }
+            ApiError::NotModified { url } => ApiError::not_modified(url.clone()),
+            ApiError::ResponseTooLarge { limit, actual, url } => ApiError::ResponseTooLarge {
+                limit: *limit,
+                actual: *actual,
+                url: url.clone(),
+            },
+            ApiError::Cancelled => ApiError::Cancelled,
+            ApiError::Timeout { url } => ApiError::timeout(url.clone()),
+            ApiError::Unknown { message } => ApiError::Unknown {
+                message: message.clone(),
+            },
+            ApiError::ConfigError { message } => ApiError::config_error(message.clone()),
+            ApiError::TypeConversionError { expected, actual } => {
+                ApiError::type_conversion_error(expected.clone(), actual.clone())
+            }
+        }
+    }
 }
 
 /// Result type alias for API operations
@@ -0,0 +1,123 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::models::NodeType;
+
+/// Key under which a registration's user list ETag is cached
+///
+/// Always built through [`CacheKey::new`] so every call site agrees on the
+/// `"{node_type}:{register_id}"` format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey(String);
+
+impl CacheKey {
+    pub(crate) fn new(node_type: NodeType, register_id: &str) -> Self {
+        Self(format!("{}:{}", node_type, register_id))
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// ETag cache with optional least-recently-used eviction
+///
+/// A capacity of 0 means the cache is unbounded.
+#[derive(Debug, Default)]
+pub(crate) struct EtagCache {
+    capacity: usize,
+    clock: u64,
+    entries: HashMap<CacheKey, Entry>,
+    /// Keys by `last_used`, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    etag: String,
+    last_used: u64,
+}
+
+impl EtagCache {
+    /// Create a cache holding at most `capacity` entries (0 = unbounded)
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            clock: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Get an ETag and mark it as recently used
+    pub(crate) fn get(&mut self, key: &CacheKey) -> Option<String> {
+        let now = self.tick();
+        let entry = self.entries.get_mut(key)?;
+        let key = self.recency.remove(&entry.last_used)?;
+        self.recency.insert(now, key);
+        entry.last_used = now;
+        Some(entry.etag.clone())
+    }
+
+    /// Get an ETag without affecting recency
+    pub(crate) fn peek(&self, key: &CacheKey) -> Option<String> {
+        self.entries.get(key).map(|entry| entry.etag.clone())
+    }
+
+    /// Insert an ETag, evicting the least-recently-used entry when full
+    ///
+    /// Returns the ETag previously stored under `key`.
+    pub(crate) fn insert(&mut self, key: CacheKey, etag: String) -> Option<String> {
+        let now = self.tick();
+
+        if self.capacity > 0
+            && !self.entries.contains_key(&key)
+            && self.entries.len() >= self.capacity
+        {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.recency.insert(now, key.clone());
+        let previous = self.entries.insert(
+            key,
+            Entry {
+                etag,
+                last_used: now,
+            },
+        )?;
+        self.recency.remove(&previous.last_used);
+        Some(previous.etag)
+    }
+
+    /// Remove an ETag
+    pub(crate) fn remove(&mut self, key: &CacheKey) -> Option<String> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.last_used);
+        Some(entry.etag)
+    }
+
+    /// Remove all entries
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    /// Number of cached entries
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Cached keys in arbitrary order
+    pub(crate) fn keys(&self) -> Vec<String> {
+        self.entries.keys().map(ToString::to_string).collect()
+    }
+}
@@ -0,0 +1,83 @@
+use tracing::{debug, error, warn};
+
+use crate::client::ApiClient;
+use crate::error::Result;
+use crate::models::NodeType;
+
+/// Registration that is unregistered when dropped
+///
+/// Returned by [`ApiClient::register_guarded`]. On drop, a best-effort
+/// `unregister` is spawned on the current tokio runtime; failures are only
+/// logged. Call [`RegistrationGuard::unregister`] to await the outcome, or
+/// [`RegistrationGuard::defuse`] to keep the registration.
+#[derive(Debug)]
+pub struct RegistrationGuard {
+    client: ApiClient,
+    node_type: NodeType,
+    register_id: String,
+    armed: bool,
+}
+
+impl RegistrationGuard {
+    pub(crate) fn new(client: ApiClient, node_type: NodeType, register_id: String) -> Self {
+        Self {
+            client,
+            node_type,
+            register_id,
+            armed: true,
+        }
+    }
+
+    /// Get the guarded register_id
+    pub fn register_id(&self) -> &str {
+        &self.register_id
+    }
+
+    /// Get the node type the registration belongs to
+    pub fn node_type(&self) -> NodeType {
+        self.node_type
+    }
+
+    /// Keep the registration and return its register_id
+    pub fn defuse(mut self) -> String {
+        self.armed = false;
+        std::mem::take(&mut self.register_id)
+    }
+
+    /// Unregister now and report the outcome
+    pub async fn unregister(mut self) -> Result<()> {
+        self.armed = false;
+        self.client
+            .unregister(self.node_type, &self.register_id)
+            .await
+    }
+}
+
+impl Drop for RegistrationGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            warn!(
+                "no tokio runtime to unregister {} node {}; registration leaked",
+                self.node_type, self.register_id
+            );
+            return;
+        };
+
+        let client = self.client.clone();
+        let node_type = self.node_type;
+        let register_id = std::mem::take(&mut self.register_id);
+        handle.spawn(async move {
+            match client.unregister(node_type, &register_id).await {
+                Ok(()) => debug!("unregistered {} node {} on drop", node_type, register_id),
+                Err(e) => error!(
+                    "failed to unregister {} node {} on drop: {}",
+                    node_type, register_id, e
+                ),
+            }
+        });
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::client::{closed, ApiClient};
+use crate::error::ApiError;
+use crate::models::NodeType;
+
+/// Number of unread failures kept before newer ones are dropped
+const FAILURE_QUEUE_CAPACITY: usize = 64;
+
+/// Failed heartbeat reported by a [`HeartbeatManager`]
+#[derive(Debug)]
+pub struct HeartbeatFailure {
+    pub node_type: NodeType,
+    pub register_id: String,
+    pub error: ApiError,
+}
+
+/// Supervises one heartbeat loop per registration
+///
+/// Each loop runs [`ApiClient::heartbeat_loop`] on its own tokio task, so it
+/// keeps the same backoff behavior. Failed heartbeats are sent to the
+/// receiver returned by [`HeartbeatManager::new`]; loops keep running after a
+/// failure. Up to 64 unread failures are kept; further ones are logged and
+/// counted in [`HeartbeatManager::dropped_failures`]. Dropping the manager or
+/// [closing](ApiClient::close) the client stops every loop.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use server_r_client::{ApiClient, Config, HeartbeatManager, NodeType};
+/// use std::time::Duration;
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = ApiClient::new(Config::new("https://api.example.com", "token"))?;
+/// let (manager, mut failures) = HeartbeatManager::new(client);
+///
+/// manager.add(NodeType::Trojan, "register-id", Duration::from_secs(30));
+///
+/// while let Some(failure) = failures.recv().await {
+///     eprintln!("heartbeat for {} failed: {}", failure.register_id, failure.error);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct HeartbeatManager {
+    client: ApiClient,
+    tasks: Mutex<HashMap<(NodeType, String), JoinHandle<()>>>,
+    failures: mpsc::Sender<HeartbeatFailure>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl HeartbeatManager {
+    /// Create a manager and the receiver its failures are reported to
+    pub fn new(client: ApiClient) -> (Self, mpsc::Receiver<HeartbeatFailure>) {
+        let (failures, receiver) = mpsc::channel(FAILURE_QUEUE_CAPACITY);
+        let manager = Self {
+            client,
+            tasks: Mutex::new(HashMap::new()),
+            failures,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        (manager, receiver)
+    }
+
+    /// Lock the task map, recovering from a poisoned lock
+    fn tasks(&self) -> MutexGuard<'_, HashMap<(NodeType, String), JoinHandle<()>>> {
+        self.tasks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Start sending heartbeats for a registration every `interval`
+    ///
+    /// Replaces any loop already running for the same registration. Must be
+    /// called within a tokio runtime.
+    pub fn add(&self, node_type: NodeType, register_id: &str, interval: Duration) {
+        let beats = self.client.heartbeat_loop(node_type, register_id, interval);
+        let failures = self.failures.clone();
+        let dropped = self.dropped.clone();
+        let id = register_id.to_string();
+        let mut shutdown = self.client.shutdown_signal();
+
+        let handle = tokio::spawn(async move {
+            let mut beats = std::pin::pin!(beats);
+            loop {
+                let result = tokio::select! {
+                    _ = closed(&mut shutdown) => break,
+                    result = beats.next() => match result {
+                        Some(result) => result,
+                        None => break,
+                    },
+                };
+                if let Err(error) = result {
+                    debug!("heartbeat for {} node {} failed: {}", node_type, id, error);
+                    let failure = HeartbeatFailure {
+                        node_type,
+                        register_id: id.clone(),
+                        error,
+                    };
+                    // Nobody listening is not a reason to stop heartbeating
+                    if let Err(mpsc::error::TrySendError::Full(_)) = failures.try_send(failure) {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                        warn!(
+                            "dropping heartbeat failure for {} node {}: failure queue is full",
+                            node_type, id
+                        );
+                    }
+                }
+            }
+        });
+
+        if let Some(previous) = self
+            .tasks()
+            .insert((node_type, register_id.to_string()), handle)
+        {
+            previous.abort();
+        }
+    }
+
+    /// Stop the loop of a registration
+    ///
+    /// Returns `false` if the registration was not managed.
+    pub fn remove(&self, node_type: NodeType, register_id: &str) -> bool {
+        match self.tasks().remove(&(node_type, register_id.to_string())) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check whether a registration is managed
+    pub fn contains(&self, node_type: NodeType, register_id: &str) -> bool {
+        self.tasks()
+            .contains_key(&(node_type, register_id.to_string()))
+    }
+
+    /// Get the number of managed registrations
+    pub fn len(&self) -> usize {
+        self.tasks().len()
+    }
+
+    /// Number of failures dropped because too many were left unread
+    pub fn dropped_failures(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Check whether no registrations are managed
+    pub fn is_empty(&self) -> bool {
+        self.tasks().is_empty()
+    }
+
+    /// Stop every loop and wait for the tasks to finish
+    pub async fn shutdown(&self) {
+        let handles: Vec<_> = self.tasks().drain().map(|(_, handle)| handle).collect();
+        for handle in &handles {
+            handle.abort();
+        }
+        for handle in handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+impl Drop for HeartbeatManager {
+    fn drop(&mut self) {
+        for (_, handle) in self.tasks().drain() {
+            handle.abort();
+        }
+    }
+}
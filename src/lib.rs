@@ -10,9 +10,12 @@
 //!
 //! - Node lifecycle management (registration, verification, unregistration)
 //! - Configuration retrieval for various proxy protocols
-//! - User list management with ETag caching
-//! - Traffic statistics reporting
-//! - Heartbeat/health check functionality
+//! - User list management with ETag caching and continuous polling streams
+//! - Traffic statistics reporting, optionally batched from a channel
+//! - Heartbeat/health check functionality with failure backoff
+//! - Supervised heartbeats for many registrations via `HeartbeatManager`
+//! - Optional synchronous client behind the `blocking` feature
+//! - Optional per-endpoint latency metrics behind the `metrics` feature
 //!
 //! ## Example
 //!
@@ -57,9 +60,14 @@
 //! The library provides detailed error types through the [`ApiError`] enum:
 //!
 //! - `ServerError` - HTTP 4xx/5xx errors
+//! - `AuthError` - HTTP 401/403 authentication failures
+//! - `RateLimited` - HTTP 429, with the parsed `Retry-After` delay
 //! - `NetworkError` - Connection/network failures
 //! - `ParseError` - JSON parsing failures
 //! - `NotModified` - HTTP 304 (useful for ETag caching)
+//! - `ResponseTooLarge` - Body exceeded `Config::with_max_response_bytes`
+//! - `Cancelled` - Request aborted through a cancel signal
+//! - `Timeout` - A caller-supplied deadline passed first
 //!
 //! ```rust,no_run
 //! use server_r_client::{ApiClient, Config, NodeType, ApiError};
@@ -76,10 +84,36 @@
 //! }
 //! ```
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod client;
+mod endpoints;
 mod error;
+mod etag_cache;
+mod guard;
+mod heartbeat;
+#[cfg(feature = "metrics")]
+mod metrics;
 pub mod models;
+mod observer;
+mod parser;
+mod resolver;
+mod retry_budget;
+mod submit_worker;
 
-pub use client::{ApiClient, Config};
+pub use bytes::Bytes;
+pub use client::{
+    ApiClient, Config, Encoding, IpFamily, QueryEncoding, SubmitFormat, DEFAULT_IP_ECHO_URL,
+    MAX_HEARTBEAT_BACKOFF,
+};
 pub use error::{ApiError, ErrorType, Result};
+pub use guard::RegistrationGuard;
+pub use heartbeat::{HeartbeatFailure, HeartbeatManager};
+#[cfg(feature = "metrics")]
+pub use metrics::LatencyStats;
 pub use models::*;
+pub use observer::{RequestObserver, WarningObserver};
+pub use parser::ConfigParser;
+pub use reqwest::header::HeaderMap;
+pub use retry_budget::RetryBudget;
+pub use submit_worker::{SubmitFailure, SubmitWorker};
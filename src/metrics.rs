@@ -0,0 +1,84 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Number of recent samples kept per endpoint for percentile estimates
+const MAX_SAMPLES: usize = 1024;
+
+/// Request latency summary for one endpoint
+///
+/// `count`, `min` and `max` cover every request; percentiles are computed
+/// over the most recent samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+/// Per-endpoint latency recorder
+#[derive(Debug, Default)]
+pub(crate) struct LatencyRecorder {
+    endpoints: HashMap<String, Samples>,
+}
+
+#[derive(Debug)]
+struct Samples {
+    count: u64,
+    min: Duration,
+    max: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl LatencyRecorder {
+    /// Record one request duration for the endpoint addressed by `url`
+    pub(crate) fn record(&mut self, url: &str, elapsed: Duration) {
+        let endpoint = url::Url::parse(url)
+            .map(|u| u.path().to_string())
+            .unwrap_or_else(|_| url.to_string());
+
+        let samples = self.endpoints.entry(endpoint).or_insert_with(|| Samples {
+            count: 0,
+            min: elapsed,
+            max: elapsed,
+            recent: VecDeque::new(),
+        });
+
+        samples.count += 1;
+        samples.min = samples.min.min(elapsed);
+        samples.max = samples.max.max(elapsed);
+        if samples.recent.len() == MAX_SAMPLES {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back(elapsed);
+    }
+
+    /// Summarize every endpoint seen so far, keyed by URL path
+    pub(crate) fn snapshot(&self) -> HashMap<String, LatencyStats> {
+        self.endpoints
+            .iter()
+            .map(|(endpoint, samples)| {
+                let mut sorted: Vec<Duration> = samples.recent.iter().copied().collect();
+                sorted.sort_unstable();
+
+                let stats = LatencyStats {
+                    count: samples.count,
+                    min: samples.min,
+                    max: samples.max,
+                    p50: percentile(&sorted, 50),
+                    p90: percentile(&sorted, 90),
+                    p99: percentile(&sorted, 99),
+                };
+                (endpoint.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+/// Nearest-rank percentile of a sorted, non-empty sample set
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
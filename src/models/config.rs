@@ -43,6 +43,223 @@ where
     })
 }
 
+/// Deserialize an optional number that might come as a JSON string (`"100"`)
+///
+/// Empty strings and `null` map to `None`.
+fn number_from_string_or_int<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString<T> {
+        Number(T),
+        String(String),
+    }
+
+    match Option::<NumberOrString<T>>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(NumberOrString::Number(n)) => Ok(Some(n)),
+        Some(NumberOrString::String(s)) => {
+            let s = s.trim();
+            if s.is_empty() {
+                Ok(None)
+            } else {
+                s.parse().map(Some).map_err(serde::de::Error::custom)
+            }
+        }
+    }
+}
+
+/// Deserialize a list of strings that might come as a single string
+///
+/// `null` and `""` produce an empty list.
+fn string_or_vec<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrVec {
+        String(String),
+        Vec(Vec<String>),
+    }
+
+    match Option::<StringOrVec>::deserialize(deserializer)? {
+        None => Ok(Vec::new()),
+        Some(StringOrVec::String(s)) if s.is_empty() => Ok(Vec::new()),
+        Some(StringOrVec::String(s)) => Ok(vec![s]),
+        Some(StringOrVec::Vec(v)) => Ok(v),
+    }
+}
+
+/// Deserialize an optional ALPN list that might come as `"h3,h2"`
+///
+/// Entries of the string form are trimmed; `null` and `""` map to `None`.
+fn alpn_from_string_or_vec<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrVec {
+        String(String),
+        Vec(Vec<String>),
+    }
+
+    match Option::<StringOrVec>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(StringOrVec::String(s)) if s.trim().is_empty() => Ok(None),
+        Some(StringOrVec::String(s)) => Ok(Some(
+            s.split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(String::from)
+                .collect(),
+        )),
+        Some(StringOrVec::Vec(v)) => Ok(Some(v)),
+    }
+}
+
+/// Transport network of a node configuration
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Network {
+    Tcp,
+    Ws,
+    Grpc,
+    H2,
+    /// Any network not known to this crate
+    Other(String),
+}
+
+impl Network {
+    /// Get the wire representation of this network
+    pub fn as_str(&self) -> &str {
+        match self {
+            Network::Tcp => "tcp",
+            Network::Ws => "ws",
+            Network::Grpc => "grpc",
+            Network::H2 => "h2",
+            Network::Other(s) => s,
+        }
+    }
+}
+
+impl std::fmt::Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for Network {
+    fn from(s: &str) -> Self {
+        match s {
+            "tcp" => Network::Tcp,
+            "ws" => Network::Ws,
+            "grpc" => Network::Grpc,
+            "h2" => Network::H2,
+            other => Network::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::str::FromStr for Network {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Network::from(s))
+    }
+}
+
+impl Serialize for Network {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Network {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(Network::from(s.as_str()))
+    }
+}
+
+/// Transport protocol of a Hysteria node
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HysteriaProtocol {
+    Udp,
+    WechatVideo,
+    FakeTcp,
+    /// Any protocol not known to this crate
+    Other(String),
+}
+
+impl HysteriaProtocol {
+    /// Get the wire representation of this protocol
+    pub fn as_str(&self) -> &str {
+        match self {
+            HysteriaProtocol::Udp => "udp",
+            HysteriaProtocol::WechatVideo => "wechat-video",
+            HysteriaProtocol::FakeTcp => "faketcp",
+            HysteriaProtocol::Other(s) => s,
+        }
+    }
+}
+
+impl std::fmt::Display for HysteriaProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for HysteriaProtocol {
+    fn from(s: &str) -> Self {
+        match s {
+            "udp" => HysteriaProtocol::Udp,
+            "wechat-video" => HysteriaProtocol::WechatVideo,
+            "faketcp" => HysteriaProtocol::FakeTcp,
+            other => HysteriaProtocol::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::str::FromStr for HysteriaProtocol {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(HysteriaProtocol::from(s))
+    }
+}
+
+impl Serialize for HysteriaProtocol {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for HysteriaProtocol {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(HysteriaProtocol::from(s.as_str()))
+    }
+}
+
 /// Base trait for all node configurations
 pub trait NodeConfig: Send + Sync {
     /// Get the node type name
@@ -59,17 +276,26 @@ pub trait NodeConfig: Send + Sync {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrojanConfig {
     pub id: i64,
+    #[serde(alias = "serverPort")]
     pub server_port: u16,
     #[serde(default)]
+    #[serde(alias = "allowInsecure")]
     pub allow_insecure: bool,
+    /// SNIs to rotate through; a single string is read as one entry
+    #[serde(default, deserialize_with = "string_or_vec")]
+    #[serde(alias = "serverName")]
+    pub server_name: Vec<String>,
     #[serde(default)]
-    pub server_name: Option<String>,
-    #[serde(default)]
-    pub network: Option<String>,
+    pub network: Option<Network>,
     #[serde(default)]
+    #[serde(alias = "websocketConfig")]
     pub websocket_config: Option<WebSocketConfig>,
     #[serde(default)]
+    #[serde(alias = "grpcConfig")]
     pub grpc_config: Option<GrpcConfig>,
+    /// ALPN protocols; a comma-separated string is also accepted
+    #[serde(default, deserialize_with = "alpn_from_string_or_vec")]
+    pub alpn: Option<Vec<String>>,
 }
 
 impl NodeConfig for TrojanConfig {
@@ -86,15 +312,31 @@ impl NodeConfig for TrojanConfig {
     }
 }
 
+impl TrojanConfig {
+    /// Get the first configured SNI
+    pub fn primary_server_name(&self) -> Option<&str> {
+        self.server_name.first().map(String::as_str)
+    }
+
+    /// Get the ALPN protocols, empty when none are configured
+    pub fn alpn_list(&self) -> Vec<String> {
+        self.alpn.clone().unwrap_or_default()
+    }
+}
+
 /// ShadowSocks configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShadowsocksConfig {
     pub id: i64,
+    #[serde(alias = "serverPort")]
     pub server_port: u16,
     #[serde(default)]
     pub method: Option<String>,
+    /// Supported ciphers, returned by newer panels
+    #[serde(default)]
+    pub methods: Option<Vec<String>>,
     #[serde(default)]
-    pub network: Option<String>,
+    pub network: Option<Network>,
 }
 
 impl NodeConfig for ShadowsocksConfig {
@@ -111,25 +353,121 @@ impl NodeConfig for ShadowsocksConfig {
     }
 }
 
+impl ShadowsocksConfig {
+    /// Get `method`, falling back to the first entry of `methods`
+    pub fn primary_method(&self) -> Option<&str> {
+        self.method
+            .as_deref()
+            .or_else(|| self.methods.as_ref()?.first().map(String::as_str))
+    }
+}
+
 /// Hysteria configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HysteriaConfig {
     pub id: i64,
+    #[serde(alias = "serverPort")]
     pub server_port: u16,
     #[serde(default)]
-    pub protocol: Option<String>,
-    #[serde(default)]
-    pub obfs: Option<String>,
+    pub protocol: Option<HysteriaProtocol>,
     #[serde(default)]
+    pub obfs: Obfs,
+    #[serde(default, deserialize_with = "number_from_string_or_int")]
+    #[serde(alias = "upMbps")]
     pub up_mbps: Option<i32>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "number_from_string_or_int")]
+    #[serde(alias = "downMbps")]
     pub down_mbps: Option<i32>,
     #[serde(default)]
+    #[serde(alias = "disableMtuDiscovery")]
     pub disable_mtu_discovery: bool,
     #[serde(default)]
+    #[serde(alias = "disableUdp")]
     pub disable_udp: bool,
 }
 
+impl HysteriaConfig {
+    /// Get the protocol as its wire string
+    pub fn protocol_str(&self) -> Option<&str> {
+        self.protocol.as_ref().map(HysteriaProtocol::as_str)
+    }
+}
+
+/// Obfuscation settings of Hysteria variants
+///
+/// Accepts both the legacy plain-string form, where the string is the
+/// obfuscation password, and the object form
+/// `{"type": "salamander", "password": "..."}`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Obfs {
+    /// No obfuscation
+    #[default]
+    None,
+    /// Salamander obfuscation
+    Salamander { password: String },
+    /// Any other obfuscation value, kept verbatim
+    Other(serde_json::Value),
+}
+
+impl Obfs {
+    /// Check whether obfuscation is disabled
+    pub fn is_none(&self) -> bool {
+        matches!(self, Obfs::None)
+    }
+
+    /// Get the obfuscation password, if any
+    pub fn password(&self) -> Option<&str> {
+        match self {
+            Obfs::Salamander { password } => Some(password),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Obfs {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Obfs::None => serializer.serialize_none(),
+            Obfs::Salamander { password } => {
+                serde_json::json!({ "type": "salamander", "password": password })
+                    .serialize(serializer)
+            }
+            Obfs::Other(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Obfs {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct SalamanderObject {
+            #[serde(rename = "type")]
+            obfs_type: String,
+            #[serde(default)]
+            password: String,
+        }
+
+        let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+        Ok(match value {
+            None | Some(serde_json::Value::Null) => Obfs::None,
+            Some(serde_json::Value::String(s)) if s.is_empty() => Obfs::None,
+            Some(serde_json::Value::String(password)) => Obfs::Salamander { password },
+            Some(value) => match SalamanderObject::deserialize(&value) {
+                Ok(obj) if obj.obfs_type.eq_ignore_ascii_case("salamander") => Obfs::Salamander {
+                    password: obj.password,
+                },
+                _ => Obfs::Other(value),
+            },
+        })
+    }
+}
+
 impl NodeConfig for HysteriaConfig {
     fn type_name(&self) -> &'static str {
         "hysteria"
@@ -148,17 +486,25 @@ impl NodeConfig for HysteriaConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Hysteria2Config {
     pub id: i64,
+    #[serde(alias = "serverPort")]
     pub server_port: u16,
     #[serde(default)]
-    pub obfs: Option<String>,
-    #[serde(default)]
+    pub obfs: Obfs,
+    #[serde(default, deserialize_with = "number_from_string_or_int")]
+    #[serde(alias = "upMbps")]
     pub up_mbps: Option<i32>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "number_from_string_or_int")]
+    #[serde(alias = "downMbps")]
     pub down_mbps: Option<i32>,
     #[serde(default)]
+    #[serde(alias = "ignoreCliBandwidth")]
     pub ignore_cli_bandwidth: bool,
     #[serde(default)]
+    #[serde(alias = "disableUdp")]
     pub disable_udp: bool,
+    /// ALPN protocols; a comma-separated string is also accepted
+    #[serde(default, deserialize_with = "alpn_from_string_or_vec")]
+    pub alpn: Option<Vec<String>>,
 }
 
 impl NodeConfig for Hysteria2Config {
@@ -175,28 +521,48 @@ impl NodeConfig for Hysteria2Config {
     }
 }
 
+impl Hysteria2Config {
+    /// Get the ALPN protocols, empty when none are configured
+    pub fn alpn_list(&self) -> Vec<String> {
+        self.alpn.clone().unwrap_or_default()
+    }
+}
+
 /// VMess configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VMessConfig {
     pub id: i64,
+    #[serde(alias = "serverPort")]
     pub server_port: u16,
     #[serde(default)]
     pub tls: bool,
     #[serde(default)]
-    pub network: Option<String>,
+    pub network: Option<Network>,
+    #[serde(default)]
+    #[serde(alias = "alterId")]
+    pub alter_id: Option<u32>,
+    #[serde(default)]
+    pub security: Option<String>,
     #[serde(default)]
+    #[serde(alias = "tlsConfig")]
     pub tls_config: Option<TlsConfig>,
     #[serde(default)]
+    #[serde(alias = "websocketConfig")]
     pub websocket_config: Option<WebSocketConfig>,
     #[serde(default)]
+    #[serde(alias = "h2Config")]
     pub h2_config: Option<HttpConfig>,
     #[serde(default)]
+    #[serde(alias = "tcpConfig")]
     pub tcp_config: Option<TcpConfig>,
     #[serde(default)]
+    #[serde(alias = "grpcConfig")]
     pub grpc_config: Option<GrpcConfig>,
     #[serde(default)]
+    #[serde(alias = "routerSettings")]
     pub router_settings: Option<RouterConfig>,
     #[serde(default)]
+    #[serde(alias = "dnsSettings")]
     pub dns_settings: Option<DnsConfig>,
 }
 
@@ -214,17 +580,154 @@ impl NodeConfig for VMessConfig {
     }
 }
 
+impl VMessConfig {
+    /// Start building a configuration with every optional setting unset
+    pub fn builder(id: i64, server_port: u16) -> VMessConfigBuilder {
+        VMessConfigBuilder::new(id, server_port)
+    }
+}
+
+/// Fluent builder for [`VMessConfig`]
+///
+/// # Example
+///
+/// ```
+/// use server_r_client::{Network, TlsConfig, VMessConfig, WebSocketConfig};
+///
+/// let config = VMessConfig::builder(5, 443)
+///     .with_tls(true)
+///     .with_network(Network::Ws)
+///     .with_tls_config(TlsConfig {
+///         server_name: Some("example.com".to_string()),
+///         ..Default::default()
+///     })
+///     .with_websocket_config(WebSocketConfig {
+///         path: Some("/ws".to_string()),
+///         ..Default::default()
+///     })
+///     .build();
+///
+/// assert_eq!(config.network, Some(Network::Ws));
+/// assert!(config.grpc_config.is_none());
+/// ```
+#[derive(Debug, Clone)]
+pub struct VMessConfigBuilder {
+    config: VMessConfig,
+}
+
+impl VMessConfigBuilder {
+    /// Create a builder for a node without TLS or transport settings
+    pub fn new(id: i64, server_port: u16) -> Self {
+        Self {
+            config: VMessConfig {
+                id,
+                server_port,
+                tls: false,
+                network: None,
+                alter_id: None,
+                security: None,
+                tls_config: None,
+                websocket_config: None,
+                h2_config: None,
+                tcp_config: None,
+                grpc_config: None,
+                router_settings: None,
+                dns_settings: None,
+            },
+        }
+    }
+
+    /// Enable TLS
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.config.tls = tls;
+        self
+    }
+
+    /// Set the transport network
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.config.network = Some(network);
+        self
+    }
+
+    /// Set the legacy alterId
+    pub fn with_alter_id(mut self, alter_id: u32) -> Self {
+        self.config.alter_id = Some(alter_id);
+        self
+    }
+
+    /// Set the cipher, e.g. `auto`
+    pub fn with_security(mut self, security: impl Into<String>) -> Self {
+        self.config.security = Some(security.into());
+        self
+    }
+
+    /// Set the TLS settings
+    pub fn with_tls_config(mut self, tls_config: TlsConfig) -> Self {
+        self.config.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Set the WebSocket transport settings
+    pub fn with_websocket_config(mut self, websocket_config: WebSocketConfig) -> Self {
+        self.config.websocket_config = Some(websocket_config);
+        self
+    }
+
+    /// Set the HTTP/2 transport settings
+    pub fn with_h2_config(mut self, h2_config: HttpConfig) -> Self {
+        self.config.h2_config = Some(h2_config);
+        self
+    }
+
+    /// Set the TCP transport settings
+    pub fn with_tcp_config(mut self, tcp_config: TcpConfig) -> Self {
+        self.config.tcp_config = Some(tcp_config);
+        self
+    }
+
+    /// Set the gRPC transport settings
+    pub fn with_grpc_config(mut self, grpc_config: GrpcConfig) -> Self {
+        self.config.grpc_config = Some(grpc_config);
+        self
+    }
+
+    /// Set the routing rules
+    pub fn with_router_settings(mut self, router_settings: RouterConfig) -> Self {
+        self.config.router_settings = Some(router_settings);
+        self
+    }
+
+    /// Set the DNS settings
+    pub fn with_dns_settings(mut self, dns_settings: DnsConfig) -> Self {
+        self.config.dns_settings = Some(dns_settings);
+        self
+    }
+
+    /// Finish building the configuration
+    pub fn build(self) -> VMessConfig {
+        self.config
+    }
+}
+
 /// AnyTLS configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnyTLSConfig {
     pub id: i64,
+    #[serde(alias = "serverPort")]
     pub server_port: u16,
     #[serde(default)]
+    #[serde(alias = "allowInsecure")]
     pub allow_insecure: bool,
+    /// SNIs to rotate through; a single string is read as one entry
+    #[serde(default, deserialize_with = "string_or_vec")]
+    #[serde(alias = "serverName")]
+    pub server_name: Vec<String>,
     #[serde(default)]
-    pub server_name: Option<String>,
-    #[serde(default)]
+    #[serde(alias = "paddingRules")]
     pub padding_rules: Option<Vec<String>>,
+    /// ALPN protocols; a comma-separated string is also accepted
+    #[serde(default, deserialize_with = "alpn_from_string_or_vec")]
+    pub alpn: Option<Vec<String>>,
 }
 
 impl NodeConfig for AnyTLSConfig {
@@ -241,17 +744,122 @@ impl NodeConfig for AnyTLSConfig {
     }
 }
 
+impl AnyTLSConfig {
+    /// Get the first configured SNI
+    pub fn primary_server_name(&self) -> Option<&str> {
+        self.server_name.first().map(String::as_str)
+    }
+
+    /// Get the ALPN protocols, empty when none are configured
+    pub fn alpn_list(&self) -> Vec<String> {
+        self.alpn.clone().unwrap_or_default()
+    }
+
+    /// Parse `padding_rules` into typed rules
+    ///
+    /// Returns an empty list when no rules are configured.
+    pub fn parsed_padding_rules(&self) -> Result<Vec<PaddingRule>> {
+        self.padding_rules
+            .iter()
+            .flatten()
+            .map(|rule| rule.parse())
+            .collect()
+    }
+}
+
+/// Padding size of an AnyTLS padding rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaddingSize {
+    /// Exactly this many bytes
+    Fixed(u32),
+    /// A random size within the inclusive range
+    Range(u32, u32),
+}
+
+/// AnyTLS padding rule in `start-end:bytes` form
+///
+/// `bytes` is either a fixed size (`100`) or an inclusive range (`100-200`).
+/// A single packet index (`3:100`) is shorthand for `3-3:100`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaddingRule {
+    pub from: u32,
+    pub to: u32,
+    pub size: PaddingSize,
+}
+
+impl PaddingRule {
+    fn parse_range(s: &str) -> Option<(u32, u32)> {
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+            None => {
+                let n = s.trim().parse().ok()?;
+                (n, n)
+            }
+        };
+        (start <= end).then_some((start, end))
+    }
+}
+
+impl std::str::FromStr for PaddingRule {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            ApiError::parse_error(
+                format!("invalid padding rule '{}': {}", s, reason),
+                "",
+                None,
+            )
+        };
+
+        let (range, bytes) = s
+            .split_once(':')
+            .ok_or_else(|| invalid("expected 'start-end:bytes'"))?;
+        let (from, to) = Self::parse_range(range).ok_or_else(|| invalid("invalid packet range"))?;
+        let size = match Self::parse_range(bytes).ok_or_else(|| invalid("invalid size"))? {
+            (min, max) if min == max && !bytes.contains('-') => PaddingSize::Fixed(min),
+            (min, max) => PaddingSize::Range(min, max),
+        };
+
+        Ok(PaddingRule { from, to, size })
+    }
+}
+
+impl std::fmt::Display for PaddingRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}:", self.from, self.to)?;
+        match self.size {
+            PaddingSize::Fixed(n) => write!(f, "{}", n),
+            PaddingSize::Range(min, max) => write!(f, "{}-{}", min, max),
+        }
+    }
+}
+
 /// TUIC configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TuicConfig {
     pub id: i64,
+    #[serde(alias = "serverPort")]
     pub server_port: u16,
     #[serde(default, deserialize_with = "bool_from_int")]
+    #[serde(alias = "allowInsecure")]
     pub allow_insecure: bool,
-    #[serde(default)]
-    pub server_name: Option<String>,
+    /// SNIs to rotate through; a single string is read as one entry
+    #[serde(default, deserialize_with = "string_or_vec")]
+    #[serde(alias = "serverName")]
+    pub server_name: Vec<String>,
     #[serde(default, deserialize_with = "bool_from_int")]
+    #[serde(alias = "zeroRttHandshake")]
     pub zero_rtt_handshake: bool,
+    #[serde(default)]
+    #[serde(alias = "congestionControl")]
+    pub congestion_control: Option<String>,
+    #[serde(default)]
+    #[serde(alias = "udpRelayMode")]
+    pub udp_relay_mode: Option<String>,
+    /// ALPN protocols; a comma-separated string is also accepted
+    #[serde(default, deserialize_with = "alpn_from_string_or_vec")]
+    pub alpn: Option<Vec<String>>,
 }
 
 impl NodeConfig for TuicConfig {
@@ -268,17 +876,42 @@ impl NodeConfig for TuicConfig {
     }
 }
 
+impl TuicConfig {
+    /// Get the first configured SNI
+    pub fn primary_server_name(&self) -> Option<&str> {
+        self.server_name.first().map(String::as_str)
+    }
+
+    /// Get the ALPN protocols, empty when none are configured
+    pub fn alpn_list(&self) -> Vec<String> {
+        self.alpn.clone().unwrap_or_default()
+    }
+}
+
 /// TLS configuration
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct TlsConfig {
     #[serde(default)]
+    #[serde(alias = "serverName")]
     pub server_name: Option<String>,
     #[serde(default)]
+    #[serde(alias = "allowInsecure")]
     pub allow_insecure: bool,
     #[serde(default)]
     pub certificate: Option<String>,
     #[serde(default)]
+    #[serde(alias = "privateKey")]
     pub private_key: Option<String>,
+    /// ALPN protocols; a comma-separated string is also accepted
+    #[serde(default, deserialize_with = "alpn_from_string_or_vec")]
+    pub alpn: Option<Vec<String>>,
+}
+
+impl TlsConfig {
+    /// Get the ALPN protocols, empty when none are configured
+    pub fn alpn_list(&self) -> Vec<String> {
+        self.alpn.clone().unwrap_or_default()
+    }
 }
 
 /// WebSocket configuration
@@ -287,7 +920,125 @@ pub struct WebSocketConfig {
     #[serde(default)]
     pub path: Option<String>,
     #[serde(default)]
-    pub headers: Option<HashMap<String, String>>,
+    pub headers: Option<WebSocketHeaders>,
+}
+
+impl WebSocketConfig {
+    /// Get a header value by name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.as_ref().and_then(|headers| headers.get(name))
+    }
+}
+
+/// Order-preserving WebSocket headers
+///
+/// Serialized as a JSON object; entries keep the order in which they appear
+/// in the source document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebSocketHeaders(Vec<(String, String)>);
+
+impl WebSocketHeaders {
+    /// Create an empty header list
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Get a header value by name (case-insensitive)
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Insert a header, replacing the value in place if the name already exists
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        let value = value.into();
+        match self
+            .0
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(&name))
+        {
+            Some(entry) => entry.1 = value,
+            None => self.0.push((name, value)),
+        }
+    }
+
+    /// Iterate over headers in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Number of headers
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if there are no headers
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Convert into an ordered list of `(name, value)` pairs
+    pub fn into_vec(self) -> Vec<(String, String)> {
+        self.0
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for WebSocketHeaders {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut headers = Self::new();
+        for (k, v) in iter {
+            headers.insert(k, v);
+        }
+        headers
+    }
+}
+
+impl Serialize for WebSocketHeaders {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in &self.0 {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for WebSocketHeaders {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HeadersVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for HeadersVisitor {
+            type Value = WebSocketHeaders;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of header names to values")
+            }
+
+            fn visit_map<A>(self, mut access: A) -> std::result::Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut headers = Vec::with_capacity(access.size_hint().unwrap_or(0));
+                while let Some((k, v)) = access.next_entry::<String, String>()? {
+                    headers.push((k, v));
+                }
+                Ok(WebSocketHeaders(headers))
+            }
+        }
+
+        deserializer.deserialize_map(HeadersVisitor)
+    }
 }
 
 /// HTTP/2 configuration
@@ -344,10 +1095,48 @@ pub struct TcpHeaderResponse {
 }
 
 /// gRPC configuration
+///
+/// `service_name` may be a single string or, for multiplexed gRPC, an array.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GrpcConfig {
-    #[serde(default)]
-    pub service_name: Option<String>,
+    #[serde(default, rename = "service_name", alias = "serviceName")]
+    #[serde(deserialize_with = "string_or_vec", serialize_with = "vec_as_string")]
+    service_names: Vec<String>,
+}
+
+impl GrpcConfig {
+    /// Create a gRPC configuration with the given service names
+    pub fn new<I, S>(service_names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            service_names: service_names.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Get the first service name
+    pub fn service_name(&self) -> Option<&str> {
+        self.service_names.first().map(String::as_str)
+    }
+
+    /// Get every service name
+    pub fn service_names(&self) -> Vec<String> {
+        self.service_names.clone()
+    }
+}
+
+/// Serialize a single-entry list as a plain string and an empty one as `null`
+fn vec_as_string<S>(values: &[String], serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match values {
+        [] => serializer.serialize_none(),
+        [value] => serializer.serialize_str(value),
+        values => values.serialize(serializer),
+    }
 }
 
 /// Router configuration
@@ -367,6 +1156,7 @@ pub struct RouterRule {
     #[serde(default)]
     pub ip: Option<Vec<String>>,
     #[serde(default)]
+    #[serde(alias = "outboundTag")]
     pub outbound_tag: Option<String>,
 }
 
@@ -384,13 +1174,23 @@ pub enum DnsServer {
     Simple(String),
     Complex {
         address: String,
-        #[serde(default)]
+        #[serde(default, deserialize_with = "number_from_string_or_int")]
         port: Option<u16>,
         #[serde(default)]
         domains: Option<Vec<String>>,
     },
 }
 
+impl DnsServer {
+    /// Get the server address regardless of variant
+    pub fn address(&self) -> &str {
+        match self {
+            DnsServer::Simple(address) => address,
+            DnsServer::Complex { address, .. } => address,
+        }
+    }
+}
+
 /// Enum wrapper for different node configurations
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -403,6 +1203,18 @@ pub enum NodeConfigEnum {
     VMess(VMessConfig),
     AnyTLS(AnyTLSConfig),
     Tuic(TuicConfig),
+    /// Configuration for a node type this client does not know about
+    #[serde(skip_deserializing)]
+    Unknown {
+        type_name: String,
+        raw: serde_json::Value,
+    },
+    /// Configuration produced by a custom [`ConfigParser`](crate::ConfigParser)
+    #[serde(skip_deserializing)]
+    Custom {
+        node_type: String,
+        data: serde_json::Value,
+    },
 }
 
 impl NodeConfigEnum {
@@ -483,7 +1295,30 @@ impl NodeConfigEnum {
         }
     }
 
+    /// Serialize into the panel's `{"data": ...}` response format
+    ///
+    /// The output can be read back with [`parse_raw_config_response`].
+    /// Unknown and custom configs are emitted as their original JSON.
+    pub fn to_api_response_bytes(&self) -> Result<Vec<u8>> {
+        use crate::models::ApiResponse;
+
+        let result = match self {
+            NodeConfigEnum::Unknown { raw, .. } => serde_json::to_vec(&ApiResponse::new(raw)),
+            NodeConfigEnum::Custom { data, .. } => serde_json::to_vec(&ApiResponse::new(data)),
+            config => serde_json::to_vec(&ApiResponse::new(config)),
+        };
+        result.map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))
+    }
+
+    /// Check whether this configuration is for an unrecognized node type
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, NodeConfigEnum::Unknown { .. })
+    }
+
     /// Get the type name
+    ///
+    /// Unknown and custom configs report `"unknown"` and `"custom"`; see
+    /// [`node_type_name`](Self::node_type_name) for the panel's name.
     pub fn type_name(&self) -> &'static str {
         match self {
             NodeConfigEnum::Trojan(_) => "trojan",
@@ -493,50 +1328,247 @@ impl NodeConfigEnum {
             NodeConfigEnum::VMess(_) => "vmess",
             NodeConfigEnum::AnyTLS(_) => "anytls",
             NodeConfigEnum::Tuic(_) => "tuic",
+            NodeConfigEnum::Unknown { .. } => "unknown",
+            NodeConfigEnum::Custom { .. } => "custom",
+        }
+    }
+
+    /// Get the node type name as the panel knows it
+    ///
+    /// Same as [`type_name`](Self::type_name) for built-in types.
+    pub fn node_type_name(&self) -> &str {
+        match self {
+            NodeConfigEnum::Unknown { type_name, .. } => type_name,
+            NodeConfigEnum::Custom { node_type, .. } => node_type,
+            _ => self.type_name(),
         }
     }
 }
 
-/// Parse configuration based on node type
-pub fn parse_config(node_type: NodeType, data: &[u8]) -> Result<NodeConfigEnum> {
-    let config = match node_type {
-        NodeType::Trojan => {
-            let config: TrojanConfig = serde_json::from_slice(data)
-                .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
-            NodeConfigEnum::Trojan(config)
+/// One-line summary for logs, e.g. `trojan[id=1 port=443 sni=example.com network=ws]`
+///
+/// Only the most relevant fields are shown and secrets are never included;
+/// use `Debug` for the full configuration.
+impl std::fmt::Display for NodeConfigEnum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut fields: Vec<(&str, String)> = Vec::new();
+        let mut field = |name, value: Option<String>| {
+            if let Some(value) = value {
+                fields.push((name, value));
+            }
+        };
+        let obfs = |obfs: &Obfs| match obfs {
+            Obfs::None => None,
+            Obfs::Salamander { .. } => Some("salamander".to_string()),
+            Obfs::Other(_) => Some("other".to_string()),
+        };
+
+        match self {
+            NodeConfigEnum::Trojan(c) => {
+                field("id", Some(c.id.to_string()));
+                field("port", Some(c.server_port.to_string()));
+                field("sni", c.primary_server_name().map(str::to_string));
+                field("network", c.network.as_ref().map(Network::to_string));
+            }
+            NodeConfigEnum::ShadowSocks(c) => {
+                field("id", Some(c.id.to_string()));
+                field("port", Some(c.server_port.to_string()));
+                field("method", c.primary_method().map(str::to_string));
+                field("network", c.network.as_ref().map(Network::to_string));
+            }
+            NodeConfigEnum::Hysteria(c) => {
+                field("id", Some(c.id.to_string()));
+                field("port", Some(c.server_port.to_string()));
+                field(
+                    "protocol",
+                    c.protocol.as_ref().map(HysteriaProtocol::to_string),
+                );
+                field("obfs", obfs(&c.obfs));
+                field("up", c.up_mbps.map(|v| v.to_string()));
+                field("down", c.down_mbps.map(|v| v.to_string()));
+            }
+            NodeConfigEnum::Hysteria2(c) => {
+                field("id", Some(c.id.to_string()));
+                field("port", Some(c.server_port.to_string()));
+                field("obfs", obfs(&c.obfs));
+                field("up", c.up_mbps.map(|v| v.to_string()));
+                field("down", c.down_mbps.map(|v| v.to_string()));
+            }
+            NodeConfigEnum::VMess(c) => {
+                field("id", Some(c.id.to_string()));
+                field("port", Some(c.server_port.to_string()));
+                field("tls", Some(c.tls.to_string()));
+                field("network", c.network.as_ref().map(Network::to_string));
+                field("security", c.security.clone());
+            }
+            NodeConfigEnum::AnyTLS(c) => {
+                field("id", Some(c.id.to_string()));
+                field("port", Some(c.server_port.to_string()));
+                field("sni", c.primary_server_name().map(str::to_string));
+            }
+            NodeConfigEnum::Tuic(c) => {
+                field("id", Some(c.id.to_string()));
+                field("port", Some(c.server_port.to_string()));
+                field("sni", c.primary_server_name().map(str::to_string));
+                field("congestion", c.congestion_control.clone());
+            }
+            NodeConfigEnum::Unknown { raw, .. } | NodeConfigEnum::Custom { data: raw, .. } => {
+                field("id", raw.get("id").map(ToString::to_string));
+                field("port", raw.get("server_port").map(ToString::to_string));
+            }
         }
-        NodeType::ShadowSocks => {
-            let config: ShadowsocksConfig = serde_json::from_slice(data)
-                .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
-            NodeConfigEnum::ShadowSocks(config)
+
+        write!(f, "{}[", self.node_type_name())?;
+        for (i, (name, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", name, value)?;
         }
-        NodeType::Hysteria => {
-            let config: HysteriaConfig = serde_json::from_slice(data)
-                .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
-            NodeConfigEnum::Hysteria(config)
+        f.write_str("]")
+    }
+}
+
+/// Implement `TryFrom<&[u8]>` for config types, parsing JSON into `ApiError::ParseError`
+macro_rules! impl_try_from_bytes {
+    ($($config:ty),+ $(,)?) => {$(
+        impl TryFrom<&[u8]> for $config {
+            type Error = ApiError;
+
+            fn try_from(data: &[u8]) -> Result<Self> {
+                serde_json::from_slice(data)
+                    .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))
+            }
         }
-        NodeType::Hysteria2 => {
-            let config: Hysteria2Config = serde_json::from_slice(data)
-                .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
-            NodeConfigEnum::Hysteria2(config)
+    )+};
+}
+
+impl_try_from_bytes!(
+    TrojanConfig,
+    ShadowsocksConfig,
+    HysteriaConfig,
+    Hysteria2Config,
+    VMessConfig,
+    AnyTLSConfig,
+    TuicConfig,
+);
+
+/// Parse configuration based on node type
+pub fn parse_config(node_type: NodeType, data: &[u8]) -> Result<NodeConfigEnum> {
+    let config = match node_type {
+        NodeType::Trojan => NodeConfigEnum::Trojan(data.try_into()?),
+        NodeType::ShadowSocks => NodeConfigEnum::ShadowSocks(data.try_into()?),
+        NodeType::Hysteria => NodeConfigEnum::Hysteria(data.try_into()?),
+        NodeType::Hysteria2 => NodeConfigEnum::Hysteria2(data.try_into()?),
+        NodeType::VMess => NodeConfigEnum::VMess(data.try_into()?),
+        NodeType::AnyTLS => NodeConfigEnum::AnyTLS(data.try_into()?),
+        NodeType::Tuic => NodeConfigEnum::Tuic(data.try_into()?),
+    };
+    Ok(config)
+}
+
+/// Parse configuration, rejecting fields the client does not model
+///
+/// Intended for catching schema drift in CI: the config is parsed as with
+/// [`parse_config`], then any input key that did not survive into the typed
+/// model (at any nesting depth) is reported as a parse error.
+pub fn parse_config_strict(node_type: NodeType, data: &[u8]) -> Result<NodeConfigEnum> {
+    let config = parse_config(node_type, data)?;
+
+    let input: serde_json::Value = serde_json::from_slice(data)
+        .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
+    let modeled = serde_json::to_value(&config)
+        .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
+
+    let mut unknown = Vec::new();
+    collect_unknown_fields(&input, &modeled, "", &mut unknown);
+
+    if !unknown.is_empty() {
+        return Err(ApiError::parse_error(
+            format!(
+                "unknown {} config fields: {}",
+                node_type,
+                unknown.join(", ")
+            ),
+            "",
+            None,
+        ));
+    }
+
+    Ok(config)
+}
+
+/// Record keys present in `input` but missing from `modeled`, as dotted paths
+fn collect_unknown_fields(
+    input: &serde_json::Value,
+    modeled: &serde_json::Value,
+    prefix: &str,
+    unknown: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    match (input, modeled) {
+        (Value::Object(input), Value::Object(modeled)) => {
+            for (key, value) in input {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                // camelCase keys are accepted as aliases of the snake_case fields
+                match modeled.get(key).or_else(|| modeled.get(&snake_case(key))) {
+                    Some(modeled_value) => {
+                        collect_unknown_fields(value, modeled_value, &path, unknown)
+                    }
+                    None => unknown.push(path),
+                }
+            }
         }
-        NodeType::VMess => {
-            let config: VMessConfig = serde_json::from_slice(data)
-                .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
-            NodeConfigEnum::VMess(config)
+        (Value::Array(input), Value::Array(modeled)) => {
+            for (i, (value, modeled_value)) in input.iter().zip(modeled).enumerate() {
+                collect_unknown_fields(
+                    value,
+                    modeled_value,
+                    &format!("{}[{}]", prefix, i),
+                    unknown,
+                );
+            }
         }
-        NodeType::AnyTLS => {
-            let config: AnyTLSConfig = serde_json::from_slice(data)
-                .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
-            NodeConfigEnum::AnyTLS(config)
+        _ => {}
+    }
+}
+
+/// Convert a camelCase key to snake_case
+fn snake_case(key: &str) -> String {
+    let mut out = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            out.push('_');
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
         }
-        NodeType::Tuic => {
-            let config: TuicConfig = serde_json::from_slice(data)
+    }
+    out
+}
+
+/// Parse configuration for a node type given by name
+///
+/// Known names are parsed as with [`parse_config`]; any other name yields
+/// [`NodeConfigEnum::Unknown`] holding the raw JSON, so configs for protocols
+/// added by newer panels are preserved rather than rejected.
+pub fn parse_config_by_name(type_name: &str, data: &[u8]) -> Result<NodeConfigEnum> {
+    match type_name.parse::<NodeType>() {
+        Ok(node_type) => parse_config(node_type, data),
+        Err(_) => {
+            let raw: serde_json::Value = serde_json::from_slice(data)
                 .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
-            NodeConfigEnum::Tuic(config)
+            Ok(NodeConfigEnum::Unknown {
+                type_name: type_name.to_string(),
+                raw,
+            })
         }
-    };
-    Ok(config)
+    }
 }
 
 /// Parse configuration from API response with `{"data": ...}` wrapper
@@ -551,8 +1583,26 @@ pub fn parse_raw_config_response(node_type: NodeType, data: &[u8]) -> Result<Nod
     let api_response: ApiResponse<serde_json::Value> = serde_json::from_slice(data)
         .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
 
-    let config_bytes = serde_json::to_vec(&api_response.data)
-        .map_err(|e| ApiError::parse_error(e.to_string(), "", Some(e)))?;
+    let config_bytes = config_data_bytes(&api_response.data, "")?;
 
     parse_config(node_type, &config_bytes)
 }
+
+/// Get the config JSON carried in a response's `data` field
+///
+/// Some panels send the config as a base64-encoded JSON string instead of an
+/// object; such strings are decoded, anything else is re-serialized as is.
+pub(crate) fn config_data_bytes(data: &serde_json::Value, url: &str) -> Result<Vec<u8>> {
+    use base64::Engine;
+
+    match data {
+        serde_json::Value::String(encoded) => base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| {
+                ApiError::parse_error(format!("invalid base64 config data: {}", e), url, None)
+            }),
+        data => {
+            serde_json::to_vec(data).map_err(|e| ApiError::parse_error(e.to_string(), url, Some(e)))
+        }
+    }
+}
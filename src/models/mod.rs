@@ -1,10 +1,13 @@
 mod config;
+mod node_id;
 mod node_type;
 mod request;
 mod response;
+mod singbox;
 mod user;
 
 pub use config::*;
+pub use node_id::*;
 pub use node_type::*;
 pub use request::*;
 pub use response::*;
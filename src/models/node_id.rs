@@ -0,0 +1,51 @@
+use std::fmt;
+
+use crate::error::{ApiError, Result};
+
+/// Panel-assigned node identifier
+///
+/// Valid IDs are positive. [`NodeId::new`] checks this up front; values built
+/// through `From<i64>` are checked when passed to a client method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(i64);
+
+impl NodeId {
+    /// Create a node ID, rejecting zero and negative values
+    pub fn new(id: i64) -> Result<Self> {
+        if id <= 0 {
+            return Err(ApiError::config_error(format!(
+                "node_id must be positive, got {}",
+                id
+            )));
+        }
+        Ok(Self(id))
+    }
+
+    /// Get the raw ID
+    pub fn get(self) -> i64 {
+        self.0
+    }
+
+    /// Re-check an ID that may have been built without validation
+    pub(crate) fn checked(self) -> Result<Self> {
+        Self::new(self.0)
+    }
+}
+
+impl From<i64> for NodeId {
+    fn from(id: i64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<NodeId> for i64 {
+    fn from(id: NodeId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -18,6 +18,19 @@ pub enum NodeType {
 }
 
 impl NodeType {
+    /// Get every node type, in declaration order
+    pub fn all() -> &'static [NodeType] {
+        &[
+            NodeType::Trojan,
+            NodeType::ShadowSocks,
+            NodeType::Hysteria,
+            NodeType::Hysteria2,
+            NodeType::VMess,
+            NodeType::AnyTLS,
+            NodeType::Tuic,
+        ]
+    }
+
     /// Get the URL path segment for this node type
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 
 use super::user::{TrafficStats, UserTraffic};
+use crate::error::{ApiError, Result};
 
 /// Node registration request
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +26,73 @@ impl RegisterRequest {
         self.node_ip = Some(node_ip.into());
         self
     }
+
+    /// Check that the hostname is not empty and the port is not 0
+    pub fn validate(&self) -> Result<()> {
+        if self.hostname.trim().is_empty() {
+            return Err(ApiError::config_error("hostname must not be empty"));
+        }
+        if self.port == 0 {
+            return Err(ApiError::config_error("port must not be 0"));
+        }
+        Ok(())
+    }
+
+    /// Like [`validate`](Self::validate), also rejecting non-public IP literals
+    ///
+    /// Private, loopback, link-local and unspecified addresses in `hostname`
+    /// or `node_ip` are refused. Domain names are not resolved.
+    pub fn validate_public(&self) -> Result<()> {
+        self.validate()?;
+
+        let fields = [
+            ("hostname", Some(&self.hostname)),
+            ("node_ip", self.node_ip.as_ref()),
+        ];
+        for (field, value) in fields {
+            let Some(value) = value else { continue };
+            let literal = value.trim().trim_start_matches('[').trim_end_matches(']');
+            if literal.parse::<IpAddr>().is_ok_and(|ip| !is_public_ip(ip)) {
+                return Err(ApiError::config_error(format!(
+                    "{} '{}' is not a public address",
+                    field, value
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check whether an address is reachable from the public internet
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified())
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            let unique_local = first & 0xfe00 == 0xfc00;
+            let link_local = first & 0xffc0 == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+        }
+    }
+}
+
+/// Registration request covering several nodes at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterMultiRequest {
+    pub node_ids: Vec<i64>,
+    #[serde(flatten)]
+    pub request: RegisterRequest,
+}
+
+impl RegisterMultiRequest {
+    pub fn new(node_ids: Vec<i64>, request: RegisterRequest) -> Self {
+        Self { node_ids, request }
+    }
 }
 
 /// Verify request
@@ -40,12 +109,36 @@ impl VerifyRequest {
     }
 }
 
+/// Node resource usage reported with a heartbeat
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NodeLoad {
+    /// CPU usage in percent
+    pub cpu: f32,
+    /// Memory usage in percent
+    pub mem: f32,
+    /// Open client connections
+    pub connections: u64,
+}
+
+impl NodeLoad {
+    pub fn new(cpu: f32, mem: f32, connections: u64) -> Self {
+        Self {
+            cpu,
+            mem,
+            connections,
+        }
+    }
+}
+
 /// Heartbeat request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HeartbeatRequest {
     pub register_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub node_ip: Option<String>,
+    /// Resource usage for the panel's scheduler
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load: Option<NodeLoad>,
 }
 
 impl HeartbeatRequest {
@@ -53,6 +146,7 @@ impl HeartbeatRequest {
         Self {
             register_id: register_id.into(),
             node_ip: None,
+            load: None,
         }
     }
 
@@ -60,20 +154,47 @@ impl HeartbeatRequest {
         self.node_ip = Some(node_ip.into());
         self
     }
+
+    pub fn with_load(mut self, load: NodeLoad) -> Self {
+        self.load = Some(load);
+        self
+    }
 }
 
 /// Traffic submission request
+///
+/// `T` is the per-user entry type, [`UserTraffic`] unless the panel expects
+/// verbose field names (see [`super::UserTrafficVerbose`]).
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SubmitRequest {
+pub struct SubmitRequest<T = UserTraffic> {
     pub register_id: String,
-    pub data: Vec<UserTraffic>,
+    pub data: Vec<T>,
+    /// IDs of currently online users, for panel presence tracking
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub online_users: Option<Vec<i64>>,
 }
 
-impl SubmitRequest {
-    pub fn new(register_id: impl Into<String>, data: Vec<UserTraffic>) -> Self {
+impl<T> SubmitRequest<T> {
+    pub fn new(register_id: impl Into<String>, data: Vec<T>) -> Self {
         Self {
             register_id: register_id.into(),
             data,
+            online_users: None,
+        }
+    }
+
+    /// Attach the currently online user IDs
+    pub fn with_online_users(mut self, online_users: Vec<i64>) -> Self {
+        self.online_users = Some(online_users);
+        self
+    }
+
+    /// Convert the per-user entries, keeping the other fields
+    pub(crate) fn map_data<U>(&self, f: impl FnMut(&T) -> U) -> SubmitRequest<U> {
+        SubmitRequest {
+            register_id: self.register_id.clone(),
+            data: self.data.iter().map(f).collect(),
+            online_users: self.online_users.clone(),
         }
     }
 }
@@ -1,11 +1,16 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::User;
+
 /// Generic API response wrapper
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiResponse<T> {
     pub data: T,
     #[serde(default)]
     pub message: Option<String>,
+    /// Application-level status code; absent or 0 means success
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<i64>,
 }
 
 impl<T> ApiResponse<T> {
@@ -13,6 +18,7 @@ impl<T> ApiResponse<T> {
         Self {
             data,
             message: None,
+            code: None,
         }
     }
 
@@ -20,18 +26,112 @@ impl<T> ApiResponse<T> {
         Self {
             data,
             message: Some(message.into()),
+            code: None,
         }
     }
+
+    /// Check whether the body `code` indicates success
+    pub fn is_ok(&self) -> bool {
+        self.code.unwrap_or(0) == 0
+    }
 }
 
 /// Register response data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisterResponseData {
     pub register_id: String,
+    /// Registration expiry as a Unix timestamp
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Suggested heartbeat interval in seconds
+    #[serde(default)]
+    pub heartbeat_interval: Option<u64>,
+}
+
+/// Register_id issued for one node of a multi-node registration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RegisterMultiEntry {
+    pub node_id: i64,
+    pub register_id: String,
+}
+
+/// Verification result
+///
+/// Accepts both the bare boolean form (`{"data": true}`) and the detailed
+/// form (`{"data": {"valid": false, "reason": "expired"}}`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(from = "VerifyResponseRepr")]
+pub struct VerifyResponseData {
+    /// Whether the registration is still valid
+    pub valid: bool,
+    /// Why the registration is invalid, e.g. `"expired"` or `"not_found"`
+    #[serde(default)]
+    pub reason: Option<String>,
 }
 
-// Verify response data is a plain boolean inside ApiResponse: { "data": true }
-pub type VerifyResponseData = bool;
+/// Wire formats accepted for [`VerifyResponseData`]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VerifyResponseRepr {
+    Plain(bool),
+    Detailed {
+        valid: bool,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+}
+
+impl From<VerifyResponseRepr> for VerifyResponseData {
+    fn from(repr: VerifyResponseRepr) -> Self {
+        match repr {
+            VerifyResponseRepr::Plain(valid) => Self {
+                valid,
+                reason: None,
+            },
+            VerifyResponseRepr::Detailed { valid, reason } => Self { valid, reason },
+        }
+    }
+}
+
+/// Per-user outcome of a traffic submission
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct SubmitResult {
+    /// User IDs whose traffic was recorded
+    #[serde(default)]
+    pub accepted: Vec<i64>,
+    /// User IDs whose traffic was rejected, with the reason
+    #[serde(default)]
+    pub rejected: Vec<(i64, String)>,
+}
+
+/// Acknowledgement returned by the panel for a traffic submission
+///
+/// Panels that reply with `"data": null` yield the default acknowledgement.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct SubmitAck {
+    /// Number of entries the panel recorded
+    #[serde(default)]
+    pub accepted_count: u64,
+    /// Panel clock as a Unix timestamp
+    #[serde(default)]
+    pub server_time: Option<i64>,
+    /// Remaining quota in bytes
+    #[serde(default)]
+    pub remaining: Option<u64>,
+}
+
+/// Result of a cursor-based user sync
+///
+/// After a full fetch `users` holds the whole list; after a sync from a token
+/// it holds only the users changed since then.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UserSync {
+    #[serde(default)]
+    pub users: Vec<User>,
+    /// Token to send on the next sync; empty if the panel issued none
+    #[serde(default)]
+    pub next_token: String,
+}
 
 /// Empty response data (for operations that don't return data)
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -39,6 +139,7 @@ pub struct EmptyData {}
 
 /// Response type aliases
 pub type RegisterResponse = ApiResponse<RegisterResponseData>;
+pub type RegisterMultiResponse = ApiResponse<Vec<RegisterMultiEntry>>;
 pub type VerifyResponse = ApiResponse<VerifyResponseData>;
 pub type EmptyResponse = ApiResponse<EmptyData>;
 
@@ -47,10 +148,58 @@ pub type EmptyResponse = ApiResponse<EmptyData>;
 pub struct UsersResponse<T> {
     pub data: T,
     pub etag: Option<String>,
+    not_modified: bool,
 }
 
 impl<T> UsersResponse<T> {
+    /// Create a response carrying a freshly fetched user list
     pub fn new(data: T, etag: Option<String>) -> Self {
-        Self { data, etag }
+        Self {
+            data,
+            etag,
+            not_modified: false,
+        }
+    }
+
+    /// Create a response for a user list the panel reported as unchanged
+    ///
+    /// The panel sends no users with a 304, so `data` carries no meaning;
+    /// callers keep using the list from their previous fetch.
+    pub fn unchanged(data: T, etag: Option<String>) -> Self {
+        Self {
+            data,
+            etag,
+            not_modified: true,
+        }
+    }
+
+    /// Check whether the panel answered 304 Not Modified
+    ///
+    /// When `true`, `data` must be ignored.
+    pub fn is_not_modified(&self) -> bool {
+        self.not_modified
+    }
+}
+
+impl<T> UsersResponse<Vec<T>> {
+    /// Check whether `data` holds no users
+    ///
+    /// Only meaningful for fresh responses: a fetched `{"data": []}` is empty,
+    /// while an unchanged response must be checked with
+    /// [`is_not_modified`](Self::is_not_modified) first.
+    ///
+    /// ```
+    /// use server_r_client::{ApiResponse, User, UsersResponse};
+    ///
+    /// let parsed: ApiResponse<Vec<User>> = serde_json::from_str(r#"{"data": []}"#).unwrap();
+    /// let response = UsersResponse::new(parsed.data, None);
+    /// assert!(!response.is_not_modified());
+    /// assert!(response.is_empty());
+    ///
+    /// let unchanged = UsersResponse::<Vec<User>>::unchanged(Vec::new(), None);
+    /// assert!(unchanged.is_not_modified());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }
 }
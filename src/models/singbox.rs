@@ -0,0 +1,252 @@
+use serde_json::{json, Map, Value};
+
+use crate::error::{ApiError, Result};
+use crate::models::{GrpcConfig, Network, NodeConfigEnum, Obfs, WebSocketConfig};
+
+impl NodeConfigEnum {
+    /// Convert this configuration into a sing-box outbound fragment
+    ///
+    /// `uuid` is the user credential; it is used as the password or UUID
+    /// depending on the protocol. The node config does not carry the server
+    /// address, so the `server` field is left for the caller to fill in.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use server_r_client::{parse_config, NodeType};
+    ///
+    /// let json = r#"{"id": 1, "server_port": 443, "server_name": "example.com"}"#;
+    /// let config = parse_config(NodeType::Trojan, json.as_bytes()).unwrap();
+    /// let outbound = config.to_singbox_outbound("trojan-1", "secret").unwrap();
+    /// assert_eq!(outbound["type"], "trojan");
+    /// assert_eq!(outbound["server_port"], 443);
+    /// ```
+    pub fn to_singbox_outbound(&self, tag: &str, uuid: &str) -> Result<Value> {
+        let mut outbound = Map::new();
+        outbound.insert("type".into(), json!(self.type_name()));
+        outbound.insert("tag".into(), json!(tag));
+
+        match self {
+            NodeConfigEnum::Trojan(config) => {
+                outbound.insert("server_port".into(), json!(config.server_port));
+                outbound.insert("password".into(), json!(uuid));
+                outbound.insert(
+                    "tls".into(),
+                    tls_fragment(
+                        config.primary_server_name(),
+                        config.allow_insecure,
+                        config.alpn.as_deref(),
+                    ),
+                );
+                if let Some(transport) = transport_fragment(
+                    config.network.as_ref().map(Network::as_str),
+                    config.websocket_config.as_ref(),
+                    config.grpc_config.as_ref(),
+                )? {
+                    outbound.insert("transport".into(), transport);
+                }
+            }
+            NodeConfigEnum::ShadowSocks(config) => {
+                let method = config.primary_method().ok_or_else(|| {
+                    ApiError::config_error("shadowsocks config has no method for sing-box")
+                })?;
+                outbound.insert("server_port".into(), json!(config.server_port));
+                outbound.insert("method".into(), json!(method));
+                outbound.insert("password".into(), json!(uuid));
+            }
+            NodeConfigEnum::Hysteria(config) => {
+                outbound.insert("server_port".into(), json!(config.server_port));
+                outbound.insert("auth_str".into(), json!(uuid));
+                if let Some(up) = config.up_mbps {
+                    outbound.insert("up_mbps".into(), json!(up));
+                }
+                if let Some(down) = config.down_mbps {
+                    outbound.insert("down_mbps".into(), json!(down));
+                }
+                match &config.obfs {
+                    Obfs::None => {}
+                    Obfs::Salamander { password } => {
+                        outbound.insert("obfs".into(), json!(password));
+                    }
+                    Obfs::Other(_) => {
+                        return Err(ApiError::config_error(
+                            "hysteria obfs type is not supported by sing-box",
+                        ))
+                    }
+                }
+                outbound.insert("tls".into(), tls_fragment(None, false, None));
+            }
+            NodeConfigEnum::Hysteria2(config) => {
+                outbound.insert("server_port".into(), json!(config.server_port));
+                outbound.insert("password".into(), json!(uuid));
+                if let Some(up) = config.up_mbps {
+                    outbound.insert("up_mbps".into(), json!(up));
+                }
+                if let Some(down) = config.down_mbps {
+                    outbound.insert("down_mbps".into(), json!(down));
+                }
+                match &config.obfs {
+                    Obfs::None => {}
+                    Obfs::Salamander { password } => {
+                        outbound.insert(
+                            "obfs".into(),
+                            json!({ "type": "salamander", "password": password }),
+                        );
+                    }
+                    Obfs::Other(_) => {
+                        return Err(ApiError::config_error(
+                            "hysteria2 obfs type is not supported by sing-box",
+                        ))
+                    }
+                }
+                outbound.insert(
+                    "tls".into(),
+                    tls_fragment(None, false, config.alpn.as_deref()),
+                );
+            }
+            NodeConfigEnum::VMess(config) => {
+                outbound.insert("server_port".into(), json!(config.server_port));
+                outbound.insert("uuid".into(), json!(uuid));
+                outbound.insert(
+                    "security".into(),
+                    json!(config.security.as_deref().unwrap_or("auto")),
+                );
+                outbound.insert("alter_id".into(), json!(config.alter_id.unwrap_or(0)));
+                if config.tls {
+                    let tls = config.tls_config.as_ref();
+                    outbound.insert(
+                        "tls".into(),
+                        tls_fragment(
+                            tls.and_then(|t| t.server_name.as_deref()),
+                            tls.is_some_and(|t| t.allow_insecure),
+                            tls.and_then(|t| t.alpn.as_deref()),
+                        ),
+                    );
+                }
+                let transport = match config.network.as_ref().map(Network::as_str) {
+                    Some("h2") | Some("http") => {
+                        let h2 = config.h2_config.clone().unwrap_or_default();
+                        let mut transport = Map::new();
+                        transport.insert("type".into(), json!("http"));
+                        if let Some(host) = h2.host {
+                            transport.insert("host".into(), json!(host));
+                        }
+                        if let Some(path) = h2.path {
+                            transport.insert("path".into(), json!(path));
+                        }
+                        Some(Value::Object(transport))
+                    }
+                    network => transport_fragment(
+                        network,
+                        config.websocket_config.as_ref(),
+                        config.grpc_config.as_ref(),
+                    )?,
+                };
+                if let Some(transport) = transport {
+                    outbound.insert("transport".into(), transport);
+                }
+            }
+            NodeConfigEnum::AnyTLS(config) => {
+                outbound.insert("server_port".into(), json!(config.server_port));
+                outbound.insert("password".into(), json!(uuid));
+                outbound.insert(
+                    "tls".into(),
+                    tls_fragment(
+                        config.primary_server_name(),
+                        config.allow_insecure,
+                        config.alpn.as_deref(),
+                    ),
+                );
+            }
+            NodeConfigEnum::Tuic(config) => {
+                outbound.insert("server_port".into(), json!(config.server_port));
+                outbound.insert("uuid".into(), json!(uuid));
+                outbound.insert("password".into(), json!(uuid));
+                outbound.insert(
+                    "zero_rtt_handshake".into(),
+                    json!(config.zero_rtt_handshake),
+                );
+                if let Some(congestion_control) = &config.congestion_control {
+                    outbound.insert("congestion_control".into(), json!(congestion_control));
+                }
+                if let Some(udp_relay_mode) = &config.udp_relay_mode {
+                    outbound.insert("udp_relay_mode".into(), json!(udp_relay_mode));
+                }
+                outbound.insert(
+                    "tls".into(),
+                    tls_fragment(
+                        config.primary_server_name(),
+                        config.allow_insecure,
+                        config.alpn.as_deref(),
+                    ),
+                );
+            }
+            NodeConfigEnum::Unknown { type_name, .. }
+            | NodeConfigEnum::Custom {
+                node_type: type_name,
+                ..
+            } => {
+                return Err(ApiError::config_error(format!(
+                    "node type '{}' is not supported by sing-box outbounds",
+                    type_name
+                )))
+            }
+        }
+
+        Ok(Value::Object(outbound))
+    }
+}
+
+/// Build a sing-box outbound TLS fragment
+fn tls_fragment(server_name: Option<&str>, insecure: bool, alpn: Option<&[String]>) -> Value {
+    let mut tls = Map::new();
+    tls.insert("enabled".into(), json!(true));
+    if let Some(server_name) = server_name {
+        tls.insert("server_name".into(), json!(server_name));
+    }
+    if insecure {
+        tls.insert("insecure".into(), json!(true));
+    }
+    if let Some(alpn) = alpn {
+        tls.insert("alpn".into(), json!(alpn));
+    }
+    Value::Object(tls)
+}
+
+/// Build a sing-box V2Ray transport fragment for ws/grpc networks
+///
+/// Returns `None` for plain TCP and an error for networks sing-box cannot express.
+fn transport_fragment(
+    network: Option<&str>,
+    websocket: Option<&WebSocketConfig>,
+    grpc: Option<&GrpcConfig>,
+) -> Result<Option<Value>> {
+    match network {
+        None | Some("") | Some("tcp") => Ok(None),
+        Some("ws") => {
+            let mut transport = Map::new();
+            transport.insert("type".into(), json!("ws"));
+            if let Some(ws) = websocket {
+                if let Some(path) = &ws.path {
+                    transport.insert("path".into(), json!(path));
+                }
+                if let Some(headers) = &ws.headers {
+                    transport.insert("headers".into(), json!(headers));
+                }
+            }
+            Ok(Some(Value::Object(transport)))
+        }
+        Some("grpc") => {
+            let mut transport = Map::new();
+            transport.insert("type".into(), json!("grpc"));
+            if let Some(service_name) = grpc.and_then(GrpcConfig::service_name) {
+                transport.insert("service_name".into(), json!(service_name));
+            }
+            Ok(Some(Value::Object(transport)))
+        }
+        Some(other) => Err(ApiError::config_error(format!(
+            "network '{}' is not supported by sing-box outbounds",
+            other
+        ))),
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
 
 use crate::error::{ApiError, Result};
 use crate::models::ApiResponse;
@@ -31,16 +32,115 @@ pub fn unmarshal_users(data: &[u8]) -> Result<Vec<User>> {
     Ok(response.data)
 }
 
+/// Iterator that decodes users one at a time from a raw JSON array
+///
+/// Only the array text is kept in memory; each `User` is parsed on demand.
+/// Iteration stops after the first malformed entry.
+pub(crate) struct UserArrayIter {
+    raw: Box<RawValue>,
+    pos: usize,
+    first: bool,
+    done: bool,
+    url: String,
+}
+
+impl UserArrayIter {
+    /// Start iterating over `raw`, which must hold a JSON array
+    pub(crate) fn new(raw: Box<RawValue>, url: impl Into<String>) -> Result<Self> {
+        let url = url.into();
+        let text = raw.get().as_bytes();
+        let pos = skip_whitespace(text, 0);
+
+        if text.get(pos) != Some(&b'[') {
+            return Err(ApiError::parse_error(
+                "users data is not an array",
+                url,
+                None,
+            ));
+        }
+
+        Ok(Self {
+            pos: pos + 1,
+            raw,
+            first: true,
+            done: false,
+            url,
+        })
+    }
+}
+
+impl Iterator for UserArrayIter {
+    type Item = Result<User>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let text = self.raw.get().as_bytes();
+        let mut pos = skip_whitespace(text, self.pos);
+
+        match text.get(pos) {
+            Some(b']') => {
+                self.done = true;
+                return None;
+            }
+            Some(b',') if !self.first => pos = skip_whitespace(text, pos + 1),
+            _ if self.first => {}
+            _ => {
+                self.done = true;
+                return Some(Err(ApiError::parse_error(
+                    "malformed users array",
+                    &self.url,
+                    None,
+                )));
+            }
+        }
+
+        let mut stream = serde_json::Deserializer::from_slice(&text[pos..]).into_iter::<User>();
+        match stream.next() {
+            Some(Ok(user)) => {
+                self.first = false;
+                self.pos = pos + stream.byte_offset();
+                Some(Ok(user))
+            }
+            Some(Err(e)) => {
+                self.done = true;
+                Some(Err(ApiError::parse_error(
+                    format!("failed to unmarshal user: {}", e),
+                    &self.url,
+                    Some(e),
+                )))
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+fn skip_whitespace(text: &[u8], mut pos: usize) -> usize {
+    while text.get(pos).is_some_and(u8::is_ascii_whitespace) {
+        pos += 1;
+    }
+    pos
+}
+
 /// User traffic data for submission
+///
+/// Deserialization also accepts the verbose `upload`/`download`/`count` names.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserTraffic {
     pub user_id: i64,
     /// Upload bytes
+    #[serde(alias = "upload")]
     pub u: u64,
     /// Download bytes
+    #[serde(alias = "download")]
     pub d: u64,
     /// Count/connections
-    #[serde(default)]
+    #[serde(default, alias = "count")]
     pub n: u64,
 }
 
@@ -64,6 +164,88 @@ impl UserTraffic {
             n: count,
         }
     }
+
+    /// Compute per-user deltas between two cumulative snapshots
+    ///
+    /// Entries are matched by `user_id` and returned in `curr` order. Users
+    /// missing from `prev` are included as-is; counters that went backwards
+    /// (e.g. after a reset) clamp at zero.
+    pub fn diff(prev: &[UserTraffic], curr: &[UserTraffic]) -> Vec<UserTraffic> {
+        let prev: std::collections::HashMap<i64, &UserTraffic> =
+            prev.iter().map(|t| (t.user_id, t)).collect();
+
+        curr.iter()
+            .map(|t| match prev.get(&t.user_id) {
+                Some(p) => UserTraffic::with_count(
+                    t.user_id,
+                    t.u.saturating_sub(p.u),
+                    t.d.saturating_sub(p.d),
+                    t.n.saturating_sub(p.n),
+                ),
+                None => t.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Sum upload bytes across a batch, saturating at `u64::MAX`
+pub fn total_upload(traffic: &[UserTraffic]) -> u64 {
+    traffic.iter().fold(0u64, |sum, t| sum.saturating_add(t.u))
+}
+
+/// Sum download bytes across a batch, saturating at `u64::MAX`
+pub fn total_download(traffic: &[UserTraffic]) -> u64 {
+    traffic.iter().fold(0u64, |sum, t| sum.saturating_add(t.d))
+}
+
+/// Sum upload and download bytes across a batch, saturating at `u64::MAX`
+pub fn total_bytes(traffic: &[UserTraffic]) -> u64 {
+    total_upload(traffic).saturating_add(total_download(traffic))
+}
+
+/// Field naming used when serializing traffic submissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrafficFieldStyle {
+    /// `u`/`d`/`n` (default)
+    #[default]
+    Short,
+    /// `upload`/`download`/`count`
+    Verbose,
+}
+
+/// User traffic data with verbose field names
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserTrafficVerbose {
+    pub user_id: i64,
+    /// Upload bytes
+    pub upload: u64,
+    /// Download bytes
+    pub download: u64,
+    /// Count/connections
+    #[serde(default)]
+    pub count: u64,
+}
+
+impl From<UserTraffic> for UserTrafficVerbose {
+    fn from(traffic: UserTraffic) -> Self {
+        Self {
+            user_id: traffic.user_id,
+            upload: traffic.u,
+            download: traffic.d,
+            count: traffic.n,
+        }
+    }
+}
+
+impl From<UserTrafficVerbose> for UserTraffic {
+    fn from(traffic: UserTrafficVerbose) -> Self {
+        Self::with_count(
+            traffic.user_id,
+            traffic.upload,
+            traffic.download,
+            traffic.count,
+        )
+    }
 }
 
 /// Aggregated traffic statistics
@@ -92,11 +274,30 @@ impl TrafficStats {
     }
 
     /// Add a user's request count
+    ///
+    /// Adding a `user_id` again merges into its existing entry instead of
+    /// counting the user twice. Totals saturate instead of overflowing.
     pub fn add_user(&mut self, user_id: i64, requests: i64) {
-        self.user_ids.push(user_id);
-        self.user_requests.insert(user_id, requests);
-        self.requests += requests;
-        self.count += 1;
+        match self.user_requests.get_mut(&user_id) {
+            Some(existing) => *existing = existing.saturating_add(requests),
+            None => {
+                self.user_ids.push(user_id);
+                self.user_requests.insert(user_id, requests);
+                self.count = self.count.saturating_add(1);
+            }
+        }
+        self.requests = self.requests.saturating_add(requests);
+    }
+
+    /// Derive statistics from a traffic batch, using each entry's count as requests
+    ///
+    /// Entries sharing a `user_id` are merged.
+    pub fn from_traffic(traffic: &[UserTraffic]) -> Self {
+        let mut stats = Self::new();
+        for t in traffic {
+            stats.add_user(t.user_id, i64::try_from(t.n).unwrap_or(i64::MAX));
+        }
+        stats
     }
 }
 
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Hook invoked around every HTTP request made by the client
+///
+/// Useful for metrics and audit logging. All methods have empty default
+/// implementations, so implementors only override what they need.
+///
+/// # Example
+///
+/// ```
+/// use server_r_client::{Config, RequestObserver};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct Counter(AtomicU64);
+///
+/// impl RequestObserver for Counter {
+///     fn on_response(&self, _status: u16, _elapsed: Duration, _bytes_len: Option<u64>) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let config = Config::new("https://api.example.com", "token")
+///     .with_observer(Arc::new(Counter::default()));
+/// ```
+pub trait RequestObserver: Send + Sync {
+    /// Called before a request is sent
+    ///
+    /// `url` has the `token` query parameter removed.
+    fn on_request(&self, method: &str, url: &str) {
+        let _ = (method, url);
+    }
+
+    /// Called when response headers are received
+    ///
+    /// `bytes_len` is the `Content-Length` of the response, if known.
+    fn on_response(&self, status: u16, elapsed: Duration, bytes_len: Option<u64>) {
+        let _ = (status, elapsed, bytes_len);
+    }
+
+    /// Called after the panel issued a new token via the `X-New-Token` header
+    ///
+    /// The client already uses the new token; override this to persist it.
+    fn on_token_rotated(&self, new_token: &str) {
+        let _ = new_token;
+    }
+}
+
+impl fmt::Debug for dyn RequestObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestObserver")
+    }
+}
+
+/// Callback receiving the `message` of successful responses
+///
+/// Panels use it for advisories such as upcoming token expiry. Set it with
+/// [`Config::with_warning_observer`](crate::Config::with_warning_observer).
+#[derive(Clone)]
+pub struct WarningObserver(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl WarningObserver {
+    /// Wrap a warning callback
+    pub fn new(callback: Arc<dyn Fn(&str) + Send + Sync>) -> Self {
+        Self(callback)
+    }
+
+    /// Invoke the callback for a non-empty message
+    pub(crate) fn notify(&self, message: Option<&str>) {
+        if let Some(message) = message.map(str::trim).filter(|m| !m.is_empty()) {
+            (self.0)(message);
+        }
+    }
+}
+
+impl fmt::Debug for WarningObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WarningObserver")
+    }
+}
+
+/// Remove the `token` query parameter from a URL
+pub(crate) fn redact_token(url: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+
+    let pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(k, _)| k != "token")
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    if pairs.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+
+    parsed.into()
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+
+use crate::error::Result;
+use crate::models::NodeConfigEnum;
+
+/// Custom parser for the `data` payload of a node configuration response
+///
+/// Register implementations with [`ApiClient::with_config_parser`](crate::ApiClient::with_config_parser)
+/// to support private protocols or replace the built-in parsing for a node
+/// type. Parsers for private protocols can return
+/// [`NodeConfigEnum::Custom`]. Closures with a matching signature implement
+/// this trait.
+///
+/// # Example
+///
+/// ```
+/// use server_r_client::{ApiClient, Config, ConfigParser, NodeConfigEnum, Result};
+///
+/// struct NaiveParser;
+///
+/// impl ConfigParser for NaiveParser {
+///     fn parse(&self, data: &[u8]) -> Result<NodeConfigEnum> {
+///         let data = serde_json::from_slice(data).map_err(|e| {
+///             server_r_client::ApiError::parse_error(e.to_string(), "", Some(e))
+///         })?;
+///         Ok(NodeConfigEnum::Custom {
+///             node_type: "naive".to_string(),
+///             data,
+///         })
+///     }
+/// }
+///
+/// let client = ApiClient::new(Config::new("https://api.example.com", "token"))
+///     .unwrap()
+///     .with_config_parser("naive", Box::new(NaiveParser));
+/// ```
+pub trait ConfigParser: Send + Sync {
+    /// Parse the unwrapped configuration JSON
+    fn parse(&self, data: &[u8]) -> Result<NodeConfigEnum>;
+}
+
+impl<F> ConfigParser for F
+where
+    F: Fn(&[u8]) -> Result<NodeConfigEnum> + Send + Sync,
+{
+    fn parse(&self, data: &[u8]) -> Result<NodeConfigEnum> {
+        self(data)
+    }
+}
+
+impl fmt::Debug for dyn ConfigParser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ConfigParser")
+    }
+}
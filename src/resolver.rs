@@ -0,0 +1,63 @@
+use std::io;
+use std::net::SocketAddr;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+use crate::client::IpFamily;
+
+/// Resolver sending connections for an SNI override to `api_host`'s address
+///
+/// `api_host` is looked up on every new connection rather than once when the
+/// client is built, so DNS changes are picked up. Other names resolve as usual.
+#[derive(Debug)]
+pub(crate) struct SniResolver {
+    sni: String,
+    host: String,
+    port: u16,
+    family: IpFamily,
+}
+
+impl SniResolver {
+    /// Map `sni` to `host:port`, keeping only addresses in `family`
+    pub(crate) fn new(sni: &str, host: String, port: u16, family: IpFamily) -> Self {
+        Self {
+            sni: sni.to_string(),
+            host,
+            port,
+            family,
+        }
+    }
+}
+
+impl Resolve for SniResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        if !name.as_str().eq_ignore_ascii_case(&self.sni) {
+            return Box::pin(async move {
+                let addrs: Vec<SocketAddr> =
+                    tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+                Ok(Box::new(addrs.into_iter()) as Addrs)
+            });
+        }
+
+        let host = self.host.clone();
+        let port = self.port;
+        let family = self.family;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+                .await?
+                .filter(|addr| family.allows(addr))
+                .collect();
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "api_host '{}' has no address in the {:?} family",
+                        host, family
+                    ),
+                )
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Period whose worth of `min_per_sec` credits can be saved up
+const RETRY_BUDGET_WINDOW: Duration = Duration::from_secs(10);
+
+/// Limits on retries shared by every clone of a client
+///
+/// Each request sent earns `ratio` retry credits, and `min_per_sec` credits
+/// accrue every second regardless of traffic; a retry spends one credit. With
+/// `ratio = 0.2`, retries stay below 20% of the request rate during an outage
+/// instead of multiplying it. Set it with
+/// [`Config::with_retry_budget`](crate::Config::with_retry_budget).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryBudget {
+    /// Retry credits earned per request
+    pub ratio: f64,
+    /// Retry credits accrued per second
+    pub min_per_sec: f64,
+}
+
+impl RetryBudget {
+    /// Create a retry budget
+    pub fn new(ratio: f64, min_per_sec: f64) -> Self {
+        Self { ratio, min_per_sec }
+    }
+
+    /// Largest number of credits that can be saved up
+    fn capacity(&self) -> f64 {
+        (self.min_per_sec * RETRY_BUDGET_WINDOW.as_secs_f64()).max(1.0)
+    }
+}
+
+/// Token bucket tracking the credits of a [`RetryBudget`]
+#[derive(Debug)]
+pub(crate) struct RetryCredits {
+    budget: RetryBudget,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RetryCredits {
+    /// Create a bucket holding one window of `min_per_sec` credits
+    pub(crate) fn new(budget: RetryBudget) -> Self {
+        let initial = budget.min_per_sec * RETRY_BUDGET_WINDOW.as_secs_f64();
+        Self {
+            budget,
+            state: Mutex::new((initial.min(budget.capacity()), Instant::now())),
+        }
+    }
+
+    /// Earn the credits of one sent request
+    pub(crate) fn deposit(&self) {
+        self.update(|balance| (balance + self.budget.ratio, ()));
+    }
+
+    /// Spend one credit, returning `false` if the budget is exhausted
+    pub(crate) fn try_withdraw(&self) -> bool {
+        self.update(|balance| {
+            if balance >= 1.0 {
+                (balance - 1.0, true)
+            } else {
+                (balance, false)
+            }
+        })
+    }
+
+    /// Apply time-based refill, then `change`, keeping the balance in bounds
+    fn update<T>(&self, change: impl FnOnce(f64) -> (f64, T)) -> T {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (balance, refilled_at) = &mut *state;
+
+        let now = Instant::now();
+        let accrued = now.duration_since(*refilled_at).as_secs_f64() * self.budget.min_per_sec;
+        *refilled_at = now;
+
+        let capacity = self.budget.capacity();
+        let (next, result) = change((*balance + accrued).min(capacity));
+        *balance = next.min(capacity);
+        result
+    }
+}
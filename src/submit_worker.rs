@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+
+use crate::client::{closed, ApiClient};
+use crate::error::ApiError;
+use crate::models::{NodeType, SubmitRequest, UserTraffic};
+
+/// Number of unread failures kept before newer ones are dropped
+const FAILURE_QUEUE_CAPACITY: usize = 64;
+
+/// Failed flush reported by a [`SubmitWorker`]
+///
+/// The batch is not retried; it is handed back so the caller can decide.
+#[derive(Debug)]
+pub struct SubmitFailure {
+    pub traffic: Vec<UserTraffic>,
+    pub error: ApiError,
+}
+
+/// Handle to a worker started with [`ApiClient::spawn_submit_worker`]
+///
+/// The worker submits a batch every `flush_interval`, or as soon as
+/// `max_batch` entries are buffered. It stops when the traffic sender is
+/// dropped, when [`SubmitWorker::stop`] is called or when the client is
+/// [closed](ApiClient::close), flushing what is left first. Dropping the handle
+/// also stops the worker, without waiting for it.
+///
+/// Up to 64 unread failures are kept; further ones are logged and counted in
+/// [`SubmitWorker::dropped_failures`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use server_r_client::{ApiClient, Config, NodeType, UserTraffic};
+/// use std::time::Duration;
+/// use tokio::sync::mpsc;
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = ApiClient::new(Config::new("https://api.example.com", "token"))?;
+/// let (tx, rx) = mpsc::channel(1024);
+/// let mut worker = client.spawn_submit_worker(
+///     NodeType::Trojan,
+///     "register-id",
+///     rx,
+///     Duration::from_secs(60),
+///     500,
+/// )?;
+///
+/// tx.send(UserTraffic::new(1, 1024, 2048)).await?;
+/// drop(tx);
+///
+/// while let Some(failure) = worker.next_failure().await {
+///     eprintln!("lost {} entries: {}", failure.traffic.len(), failure.error);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SubmitWorker {
+    task: JoinHandle<()>,
+    stop: Option<oneshot::Sender<()>>,
+    failures: mpsc::Receiver<SubmitFailure>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl SubmitWorker {
+    /// Start draining `traffic` on a new tokio task
+    pub(crate) fn spawn(
+        client: ApiClient,
+        node_type: NodeType,
+        register_id: String,
+        mut traffic: mpsc::Receiver<UserTraffic>,
+        flush_interval: Duration,
+        max_batch: usize,
+    ) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+        let (failures_tx, failures) = mpsc::channel(FAILURE_QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let dropped_count = dropped.clone();
+        let mut shutdown = client.shutdown_signal();
+
+        let task = tokio::spawn(async move {
+            let flush = |batch: Vec<UserTraffic>| {
+                let client = &client;
+                let register_id = &register_id;
+                let failures_tx = &failures_tx;
+                let dropped = &dropped_count;
+                async move {
+                    let request = SubmitRequest::new(register_id.as_str(), batch);
+                    let Err(error) = client.submit_request(node_type, &request).await else {
+                        return;
+                    };
+                    debug!(
+                        "submit of {} entries for {} node {} failed: {}",
+                        request.data.len(),
+                        node_type,
+                        register_id,
+                        error
+                    );
+                    let failure = SubmitFailure {
+                        traffic: request.data,
+                        error,
+                    };
+                    if let Err(mpsc::error::TrySendError::Full(failure)) =
+                        failures_tx.try_send(failure)
+                    {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                        warn!(
+                            "dropping failed batch of {} entries for {} node {}: failure queue is full",
+                            failure.traffic.len(),
+                            node_type,
+                            register_id
+                        );
+                    }
+                }
+            };
+
+            let mut ticker = tokio::time::interval(flush_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately
+            ticker.tick().await;
+
+            let mut batch = Vec::with_capacity(max_batch);
+            loop {
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    _ = closed(&mut shutdown) => break,
+                    item = traffic.recv() => match item {
+                        Some(item) => {
+                            batch.push(item);
+                            if batch.len() >= max_batch {
+                                flush(std::mem::take(&mut batch)).await;
+                                ticker.reset();
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => {
+                        if !batch.is_empty() {
+                            flush(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                }
+            }
+
+            // Entries already queued when stopping are still submitted
+            while let Ok(item) = traffic.try_recv() {
+                batch.push(item);
+                if batch.len() >= max_batch {
+                    flush(std::mem::take(&mut batch)).await;
+                }
+            }
+            if !batch.is_empty() {
+                flush(batch).await;
+            }
+        });
+
+        Self {
+            task,
+            stop: Some(stop),
+            failures,
+            dropped,
+        }
+    }
+
+    /// Wait for the next failed flush
+    ///
+    /// Returns `None` once the worker has finished and every failure was read.
+    pub async fn next_failure(&mut self) -> Option<SubmitFailure> {
+        self.failures.recv().await
+    }
+
+    /// Number of failures dropped because too many were left unread
+    pub fn dropped_failures(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Check whether the worker task has finished
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Flush buffered traffic, stop the worker and wait for it to finish
+    ///
+    /// Returns the failures that were not read yet.
+    pub async fn stop(mut self) -> Vec<SubmitFailure> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        let _ = (&mut self.task).await;
+
+        let mut failures = Vec::new();
+        while let Ok(failure) = self.failures.try_recv() {
+            failures.push(failure);
+        }
+        failures
+    }
+}
@@ -0,0 +1,88 @@
+//! Fake panel used by the mock server tests
+
+use server_r_client::{ApiClient, Config};
+use wiremock::matchers::{header, method, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Token accepted by the fake panel
+pub const TOKEN: &str = "test-token";
+
+/// Register ID handed out by the fake panel
+pub const REGISTER_ID: &str = "reg-1";
+
+/// ETag served with the user list
+pub const USERS_ETAG: &str = "\"users-v1\"";
+
+/// Mock panel implementing register/users/heartbeat/submit
+///
+/// Requests without the expected token get a 401; `users` answers 304 when
+/// `If-None-Match` carries the current ETag.
+pub struct FakePanel {
+    pub server: MockServer,
+}
+
+impl FakePanel {
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/api/v1/server/enhanced/\w+/register$"))
+            .and(query_param("token", TOKEN))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"{{"data": {{"register_id": "{}"}}}}"#,
+                REGISTER_ID
+            )))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/api/v1/server/enhanced/\w+/users$"))
+            .and(query_param("token", TOKEN))
+            .and(header("If-None-Match", USERS_ETAG))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/api/v1/server/enhanced/\w+/users$"))
+            .and(query_param("token", TOKEN))
+            .and(query_param("register_id", REGISTER_ID))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", USERS_ETAG)
+                    .set_body_string(
+                        r#"{"data": [{"id": 1, "uuid": "uuid-1"}, {"id": 2, "uuid": "uuid-2"}]}"#,
+                    ),
+            )
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(
+                r"^/api/v1/server/enhanced/\w+/(heartbeat|submit)$",
+            ))
+            .and(query_param("token", TOKEN))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+            .mount(&server)
+            .await;
+
+        Mock::given(path_regex(r"^/api/"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("invalid token"))
+            .with_priority(10)
+            .mount(&server)
+            .await;
+
+        Self { server }
+    }
+
+    /// Client configured with the accepted token
+    pub fn client(&self) -> ApiClient {
+        self.client_with_token(TOKEN)
+    }
+
+    /// Client configured with an arbitrary token
+    pub fn client_with_token(&self, token: &str) -> ApiClient {
+        ApiClient::new(Config::new(self.server.uri(), token)).unwrap()
+    }
+}
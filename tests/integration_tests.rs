@@ -1,10 +1,12 @@
 use server_r_client::{
-    AnyTLSConfig, ApiClient, ApiError, Config, Hysteria2Config, HysteriaConfig, NodeConfigEnum,
-    NodeType, RegisterRequest, ShadowsocksConfig, TrafficStats, TrojanConfig, TuicConfig,
-    UserTraffic, VMessConfig,
+    AnyTLSConfig, ApiClient, ApiError, Config, Hysteria2Config, HysteriaConfig, HysteriaProtocol,
+    Network, NodeConfigEnum, NodeType, RegisterRequest, ShadowsocksConfig, TrafficStats,
+    TrojanConfig, TuicConfig, UserTraffic, VMessConfig,
 };
 use std::time::Duration;
 
+mod common;
+
 // Unit tests that don't require network
 #[test]
 fn test_node_type_parsing() {
@@ -24,6 +26,42 @@ fn test_node_type_parsing() {
     assert!("invalid".parse::<NodeType>().is_err());
 }
 
+#[test]
+fn test_node_type_renderings_agree() {
+    for (index, node_type) in NodeType::all().iter().enumerate() {
+        // Adding a variant fails to compile here until it is listed in all()
+        let position = match node_type {
+            NodeType::Trojan => 0,
+            NodeType::ShadowSocks => 1,
+            NodeType::Hysteria => 2,
+            NodeType::Hysteria2 => 3,
+            NodeType::VMess => 4,
+            NodeType::AnyTLS => 5,
+            NodeType::Tuic => 6,
+        };
+        assert_eq!(index, position);
+
+        let path = node_type.as_str();
+        assert_eq!(node_type.to_string(), path);
+        assert_eq!(
+            serde_json::to_string(node_type).unwrap(),
+            format!("\"{}\"", path)
+        );
+        assert_eq!(path.parse::<NodeType>().unwrap(), *node_type);
+    }
+    assert_eq!(NodeType::all().len(), 7);
+}
+
+#[test]
+fn test_node_id_validation() {
+    use server_r_client::NodeId;
+
+    assert_eq!(NodeId::new(42).unwrap().get(), 42);
+    assert!(matches!(NodeId::new(0), Err(ApiError::ConfigError { .. })));
+    assert!(matches!(NodeId::new(-1), Err(ApiError::ConfigError { .. })));
+    assert_eq!(i64::from(NodeId::from(7)), 7);
+}
+
 #[test]
 fn test_node_type_display() {
     assert_eq!(format!("{}", NodeType::Trojan), "trojan");
@@ -47,6 +85,31 @@ fn test_traffic_stats() {
     assert_eq!(stats.user_requests.get(&2), Some(&200));
 }
 
+#[test]
+fn test_traffic_stats_duplicate_user_merges() {
+    let mut stats = TrafficStats::new();
+    stats.add_user(1, 100);
+    stats.add_user(1, 50);
+
+    assert_eq!(stats.count, 1);
+    assert_eq!(stats.requests, 150);
+    assert_eq!(stats.user_ids, vec![1]);
+    assert_eq!(stats.user_requests.get(&1), Some(&150));
+}
+
+#[test]
+fn test_traffic_stats_saturates_near_max() {
+    let mut stats = TrafficStats::new();
+    stats.add_user(1, i64::MAX - 1);
+    stats.add_user(2, 10);
+    stats.add_user(1, 10);
+
+    assert_eq!(stats.count, 2);
+    assert_eq!(stats.requests, i64::MAX);
+    assert_eq!(stats.user_requests.get(&1), Some(&i64::MAX));
+    assert_eq!(stats.user_requests.get(&2), Some(&10));
+}
+
 #[test]
 fn test_traffic_stats_default() {
     let stats = TrafficStats::default();
@@ -82,12 +145,72 @@ fn test_register_request_builder() {
     assert_eq!(request_with_ip.node_ip, Some("1.2.3.4".to_string()));
 }
 
+#[test]
+fn test_register_request_validate() {
+    assert!(RegisterRequest::new("node.example.com", 443)
+        .validate()
+        .is_ok());
+
+    let err = RegisterRequest::new("  ", 443).validate().unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+    assert!(err.to_string().contains("hostname"));
+
+    let err = RegisterRequest::new("node.example.com", 0)
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("port"));
+
+    // IP literals are only checked by validate_public
+    assert!(RegisterRequest::new("10.0.0.5", 443).validate().is_ok());
+    for hostname in [
+        "10.0.0.5",
+        "127.0.0.1",
+        "[::1]",
+        "fd00::1",
+        "::ffff:192.168.1.1",
+    ] {
+        assert!(
+            RegisterRequest::new(hostname, 443)
+                .validate_public()
+                .is_err(),
+            "{} should be rejected",
+            hostname
+        );
+    }
+    assert!(RegisterRequest::new("node.example.com", 443)
+        .with_node_ip("192.168.0.2")
+        .validate_public()
+        .is_err());
+    assert!(RegisterRequest::new("8.8.8.8", 443)
+        .with_node_ip("2001:4860::8888")
+        .validate_public()
+        .is_ok());
+}
+
+#[test]
+fn test_heartbeat_request_load_serialization() {
+    use server_r_client::{HeartbeatRequest, NodeLoad};
+
+    let plain = serde_json::to_value(HeartbeatRequest::new("reg-1")).unwrap();
+    assert_eq!(plain, serde_json::json!({"register_id": "reg-1"}));
+
+    let request = HeartbeatRequest::new("reg-1").with_load(NodeLoad::new(12.5, 40.0, 321));
+    assert_eq!(
+        serde_json::to_value(&request).unwrap(),
+        serde_json::json!({
+            "register_id": "reg-1",
+            "load": {"cpu": 12.5, "mem": 40.0, "connections": 321}
+        })
+    );
+}
+
 #[test]
 fn test_config_creation() {
     let config = Config::new("https://api.example.com", "test-token");
     assert_eq!(config.api_host, "https://api.example.com");
     assert_eq!(config.token, "test-token");
     assert_eq!(config.timeout, Duration::from_secs(5));
+    assert_eq!(config.connect_timeout, None);
     assert!(!config.debug);
 }
 
@@ -95,10 +218,43 @@ fn test_config_creation() {
 fn test_config_builder() {
     let config = Config::new("https://api.example.com", "test-token")
         .with_timeout(Duration::from_secs(30))
+        .with_connect_timeout(Duration::from_secs(2))
         .with_debug(true);
 
+    assert_eq!(config.timeout, Duration::from_secs(30));
+    assert_eq!(config.connect_timeout, Some(Duration::from_secs(2)));
+    assert!(config.debug);
+}
+
+#[test]
+fn test_config_from_env() {
+    // The only test touching these variables, so mutating them is safe
+    std::env::remove_var("SERVER_R_API_HOST");
+    std::env::set_var("SERVER_R_API_TOKEN", "env-token");
+    let err = Config::from_env().unwrap_err();
+    assert!(err.to_string().contains("SERVER_R_API_HOST"));
+
+    std::env::set_var("SERVER_R_API_HOST", "https://api.example.com");
+    std::env::set_var("SERVER_R_TIMEOUT_SECS", "30");
+    std::env::set_var("SERVER_R_DEBUG", "true");
+    let config = Config::from_env().unwrap();
+    assert_eq!(config.api_host, "https://api.example.com");
+    assert_eq!(config.token, "env-token");
     assert_eq!(config.timeout, Duration::from_secs(30));
     assert!(config.debug);
+
+    std::env::set_var("SERVER_R_TIMEOUT_SECS", "soon");
+    let err = Config::from_env().unwrap_err();
+    assert!(err.to_string().contains("SERVER_R_TIMEOUT_SECS"));
+
+    for name in [
+        "SERVER_R_API_HOST",
+        "SERVER_R_API_TOKEN",
+        "SERVER_R_TIMEOUT_SECS",
+        "SERVER_R_DEBUG",
+    ] {
+        std::env::remove_var(name);
+    }
 }
 
 #[test]
@@ -108,6 +264,32 @@ fn test_client_creation() {
     assert!(client.is_ok());
 }
 
+#[test]
+fn test_config_validate_empty_token() {
+    let config = Config::new("https://api.example.com", "");
+    let err = config.validate().unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+    assert!(err.to_string().contains("token"));
+
+    assert!(ApiClient::new(Config::new("https://api.example.com", "  ")).is_err());
+}
+
+#[test]
+fn test_config_validate_host_without_scheme() {
+    let config = Config::new("api.example.com", "test-token");
+    assert!(matches!(
+        config.validate(),
+        Err(ApiError::ConfigError { .. })
+    ));
+
+    let config = Config::new("ftp://api.example.com", "test-token");
+    assert!(config.validate().is_err());
+
+    assert!(Config::new("http://127.0.0.1:8080", "test-token")
+        .validate()
+        .is_ok());
+}
+
 #[test]
 fn test_error_types() {
     let server_err = ApiError::from_status_code(500, "Internal Server Error", "http://test.com");
@@ -129,6 +311,76 @@ fn test_error_types() {
     assert!(!not_modified.is_server_error());
 }
 
+#[tokio::test]
+async fn test_rate_limited_retry_after() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/vmess/heartbeat"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("Retry-After", "Fri, 31 Dec 2100 23:59:59 GMT"),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/tuic/heartbeat"))
+        .respond_with(ResponseTemplate::new(429))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let err = client
+        .heartbeat(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap_err();
+    assert!(err.is_rate_limited());
+    assert_eq!(err.error_type(), server_r_client::ErrorType::RateLimited);
+    assert_eq!(err.retry_after(), Some(Duration::from_secs(120)));
+
+    let err = client
+        .heartbeat(NodeType::VMess, "reg-1")
+        .await
+        .unwrap_err();
+    assert!(err.retry_after().unwrap() > Duration::from_secs(365 * 24 * 3600));
+
+    let err = client.heartbeat(NodeType::Tuic, "reg-1").await.unwrap_err();
+    assert!(matches!(
+        err,
+        ApiError::RateLimited {
+            retry_after: None,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_auth_error_from_status_code() {
+    use server_r_client::ErrorType;
+
+    for status in [401, 403] {
+        let err = ApiError::from_status_code(status, "Unauthorized", "http://test.com");
+        assert!(err.is_auth_error());
+        assert!(!err.is_server_error());
+        assert_eq!(err.error_type(), ErrorType::Auth);
+    }
+
+    let not_found = ApiError::from_status_code(404, "Not Found", "http://test.com");
+    assert!(not_found.is_server_error());
+    assert!(!not_found.is_auth_error());
+}
+
 #[test]
 fn test_trojan_config_deserialization() {
     let json = r#"{
@@ -142,7 +394,27 @@ fn test_trojan_config_deserialization() {
     assert_eq!(config.id, 1);
     assert_eq!(config.server_port, 443);
     assert!(!config.allow_insecure);
-    assert_eq!(config.server_name, Some("example.com".to_string()));
+    assert_eq!(config.server_name, vec!["example.com".to_string()]);
+    assert_eq!(config.primary_server_name(), Some("example.com"));
+}
+
+#[test]
+fn test_server_name_list() {
+    let config: TrojanConfig = serde_json::from_str(
+        r#"{"id": 1, "server_port": 443, "server_name": ["a.example.com", "b.example.com"]}"#,
+    )
+    .unwrap();
+    assert_eq!(config.server_name, vec!["a.example.com", "b.example.com"]);
+    assert_eq!(config.primary_server_name(), Some("a.example.com"));
+
+    let config: AnyTLSConfig =
+        serde_json::from_str(r#"{"id": 6, "server_port": 443, "server_name": "example.com"}"#)
+            .unwrap();
+    assert_eq!(config.server_name, vec!["example.com"]);
+
+    let config: TuicConfig = serde_json::from_str(r#"{"id": 7, "server_port": 443}"#).unwrap();
+    assert!(config.server_name.is_empty());
+    assert_eq!(config.primary_server_name(), None);
 }
 
 #[test]
@@ -160,6 +432,32 @@ fn test_shadowsocks_config_deserialization() {
     assert_eq!(config.method, Some("aes-256-gcm".to_string()));
 }
 
+#[test]
+fn test_shadowsocks_methods() {
+    let json = r#"{
+        "id": 2,
+        "server_port": 8388,
+        "method": "aes-256-gcm",
+        "methods": ["chacha20-ietf-poly1305", "aes-128-gcm"]
+    }"#;
+
+    let config: ShadowsocksConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.primary_method(), Some("aes-256-gcm"));
+    assert_eq!(
+        config.methods,
+        Some(vec![
+            "chacha20-ietf-poly1305".to_string(),
+            "aes-128-gcm".to_string()
+        ])
+    );
+
+    let config: ShadowsocksConfig = serde_json::from_str(
+        r#"{"id": 2, "server_port": 8388, "methods": ["2022-blake3-aes-128-gcm"]}"#,
+    )
+    .unwrap();
+    assert_eq!(config.primary_method(), Some("2022-blake3-aes-128-gcm"));
+}
+
 #[test]
 fn test_hysteria_config_deserialization() {
     let json = r#"{
@@ -176,6 +474,104 @@ fn test_hysteria_config_deserialization() {
     assert_eq!(config.server_port, 443);
     assert_eq!(config.up_mbps, Some(100));
     assert_eq!(config.down_mbps, Some(100));
+    assert_eq!(config.protocol, Some(HysteriaProtocol::Udp));
+}
+
+#[test]
+fn test_hysteria_protocol_values() {
+    let cases = [
+        ("udp", HysteriaProtocol::Udp),
+        ("wechat-video", HysteriaProtocol::WechatVideo),
+        ("faketcp", HysteriaProtocol::FakeTcp),
+        ("quic", HysteriaProtocol::Other("quic".to_string())),
+    ];
+
+    for (wire, expected) in cases {
+        let json = format!(r#"{{"id": 3, "server_port": 443, "protocol": "{}"}}"#, wire);
+        let config: HysteriaConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.protocol.as_ref(), Some(&expected));
+        assert_eq!(config.protocol_str(), Some(wire));
+        assert_eq!(expected.to_string(), wire);
+
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["protocol"], wire);
+    }
+
+    let json = r#"{"id": 3, "server_port": 443}"#;
+    let config: HysteriaConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.protocol, None);
+    assert_eq!(config.protocol_str(), None);
+}
+
+#[test]
+fn test_camel_case_config_keys() {
+    use server_r_client::parse_config_strict;
+
+    let json = r#"{
+        "id": 1,
+        "serverPort": 443,
+        "allowInsecure": true,
+        "serverName": "example.com",
+        "network": "ws",
+        "websocketConfig": {"path": "/ws"}
+    }"#;
+    let config: TrojanConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.server_port, 443);
+    assert!(config.allow_insecure);
+    assert_eq!(config.server_name, vec!["example.com".to_string()]);
+    assert_eq!(
+        config.websocket_config.unwrap().path.as_deref(),
+        Some("/ws")
+    );
+
+    let json = r#"{
+        "id": 3,
+        "serverPort": 443,
+        "upMbps": "100",
+        "downMbps": 200,
+        "disableMtuDiscovery": true,
+        "disableUdp": true
+    }"#;
+    let config: HysteriaConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.server_port, 443);
+    assert_eq!(config.up_mbps, Some(100));
+    assert_eq!(config.down_mbps, Some(200));
+    assert!(config.disable_mtu_discovery);
+    assert!(config.disable_udp);
+
+    // Strict parsing treats camelCase keys as known fields
+    assert!(parse_config_strict(NodeType::Hysteria, json.as_bytes()).is_ok());
+}
+
+#[test]
+fn test_hysteria_bandwidth_as_string() {
+    let json = r#"{
+        "id": 3,
+        "server_port": 443,
+        "up_mbps": "100",
+        "down_mbps": "200"
+    }"#;
+
+    let config: HysteriaConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.up_mbps, Some(100));
+    assert_eq!(config.down_mbps, Some(200));
+}
+
+#[test]
+fn test_hysteria2_bandwidth_empty_or_null() {
+    let json = r#"{
+        "id": 4,
+        "server_port": 443,
+        "up_mbps": "",
+        "down_mbps": null
+    }"#;
+
+    let config: Hysteria2Config = serde_json::from_str(json).unwrap();
+    assert_eq!(config.up_mbps, None);
+    assert_eq!(config.down_mbps, None);
+
+    let json = r#"{"id": 4, "server_port": 443, "up_mbps": 50, "down_mbps": "abc"}"#;
+    assert!(serde_json::from_str::<Hysteria2Config>(json).is_err());
 }
 
 #[test]
@@ -193,6 +589,40 @@ fn test_hysteria2_config_deserialization() {
     assert!(config.ignore_cli_bandwidth);
 }
 
+#[test]
+fn test_hysteria_obfs_encodings() {
+    use server_r_client::Obfs;
+
+    let legacy: HysteriaConfig =
+        serde_json::from_str(r#"{"id": 3, "server_port": 443, "obfs": "secret"}"#).unwrap();
+    assert_eq!(
+        legacy.obfs,
+        Obfs::Salamander {
+            password: "secret".to_string()
+        }
+    );
+
+    let object: Hysteria2Config = serde_json::from_str(
+        r#"{"id": 4, "server_port": 443, "obfs": {"type": "salamander", "password": "pw"}}"#,
+    )
+    .unwrap();
+    assert_eq!(object.obfs.password(), Some("pw"));
+
+    let none: Hysteria2Config =
+        serde_json::from_str(r#"{"id": 4, "server_port": 443, "obfs": null}"#).unwrap();
+    assert!(none.obfs.is_none());
+
+    let other: Hysteria2Config = serde_json::from_str(
+        r#"{"id": 4, "server_port": 443, "obfs": {"type": "xplus", "key": "k"}}"#,
+    )
+    .unwrap();
+    assert!(matches!(other.obfs, Obfs::Other(_)));
+
+    let json = serde_json::to_value(&object).unwrap();
+    assert_eq!(json["obfs"]["type"], "salamander");
+    assert_eq!(json["obfs"]["password"], "pw");
+}
+
 #[test]
 fn test_vmess_config_deserialization() {
     let json = r#"{
@@ -206,7 +636,156 @@ fn test_vmess_config_deserialization() {
     assert_eq!(config.id, 5);
     assert_eq!(config.server_port, 443);
     assert!(config.tls);
-    assert_eq!(config.network, Some("ws".to_string()));
+    assert_eq!(config.network, Some(Network::Ws));
+    assert_eq!(config.network.as_ref().unwrap().as_str(), "ws");
+}
+
+#[test]
+fn test_vmess_alter_id_and_security() {
+    let json = r#"{
+        "id": 5,
+        "server_port": 443,
+        "alter_id": 0,
+        "security": "auto"
+    }"#;
+
+    let config: VMessConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.alter_id, Some(0));
+    assert_eq!(config.security.as_deref(), Some("auto"));
+
+    let legacy: VMessConfig = serde_json::from_str(r#"{"id": 5, "server_port": 443}"#).unwrap();
+    assert!(legacy.alter_id.is_none());
+    assert!(legacy.security.is_none());
+}
+
+#[test]
+fn test_grpc_service_name_scalar_and_array() {
+    let config: TrojanConfig = serde_json::from_str(
+        r#"{"id": 1, "server_port": 443, "network": "grpc", "grpc_config": {"service_name": "svc"}}"#,
+    )
+    .unwrap();
+    let grpc = config.grpc_config.unwrap();
+    assert_eq!(grpc.service_name(), Some("svc"));
+    assert_eq!(grpc.service_names(), vec!["svc"]);
+    // A single name is written back as a string
+    assert_eq!(
+        serde_json::to_value(&grpc).unwrap(),
+        serde_json::json!({"service_name": "svc"})
+    );
+
+    let config: VMessConfig = serde_json::from_str(
+        r#"{"id": 2, "server_port": 443, "network": "grpc", "grpc_config": {"service_name": ["a", "b"]}}"#,
+    )
+    .unwrap();
+    let grpc = config.grpc_config.unwrap();
+    assert_eq!(grpc.service_name(), Some("a"));
+    assert_eq!(grpc.service_names(), vec!["a", "b"]);
+    assert_eq!(
+        serde_json::to_value(&grpc).unwrap(),
+        serde_json::json!({"service_name": ["a", "b"]})
+    );
+
+    let config: VMessConfig =
+        serde_json::from_str(r#"{"id": 3, "server_port": 443, "grpc_config": {}}"#).unwrap();
+    assert_eq!(config.grpc_config.unwrap().service_name(), None);
+}
+
+#[test]
+fn test_node_config_display_summary() {
+    use server_r_client::parse_config;
+
+    let trojan = parse_config(
+        NodeType::Trojan,
+        br#"{"id": 1, "server_port": 443, "server_name": "example.com", "network": "ws"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        trojan.to_string(),
+        "trojan[id=1 port=443 sni=example.com network=ws]"
+    );
+
+    let hysteria2 = parse_config(
+        NodeType::Hysteria2,
+        br#"{"id": 2, "server_port": 8443, "obfs": "salamander", "obfs_password": "secret", "up_mbps": 100}"#,
+    )
+    .unwrap();
+    let summary = hysteria2.to_string();
+    assert_eq!(summary, "hysteria2[id=2 port=8443 obfs=salamander up=100]");
+    assert!(!summary.contains("secret"));
+
+    let vmess = parse_config(NodeType::VMess, br#"{"id": 3, "server_port": 80}"#).unwrap();
+    assert_eq!(vmess.to_string(), "vmess[id=3 port=80 tls=false]");
+}
+
+#[test]
+fn test_network_parsing() {
+    let config: TrojanConfig =
+        serde_json::from_str(r#"{"id": 1, "server_port": 443, "network": "grpc"}"#).unwrap();
+    assert_eq!(config.network, Some(Network::Grpc));
+
+    let config: ShadowsocksConfig =
+        serde_json::from_str(r#"{"id": 2, "server_port": 8388, "network": "quic"}"#).unwrap();
+    assert_eq!(config.network, Some(Network::Other("quic".to_string())));
+
+    let json = serde_json::to_string(&config).unwrap();
+    assert!(json.contains("\"network\":\"quic\""));
+}
+
+#[test]
+fn test_dns_servers_mixed_variants() {
+    use server_r_client::{DnsConfig, DnsServer};
+
+    let json = r#"{
+        "servers": [
+            "8.8.8.8",
+            {"address": "1.1.1.1", "port": 53},
+            {"address": "9.9.9.9", "port": "5353", "domains": ["example.com"]},
+            {"address": "208.67.222.222", "port": null}
+        ]
+    }"#;
+
+    let dns: DnsConfig = serde_json::from_str(json).unwrap();
+    let servers = dns.servers.unwrap();
+    let addresses: Vec<&str> = servers.iter().map(DnsServer::address).collect();
+    assert_eq!(
+        addresses,
+        vec!["8.8.8.8", "1.1.1.1", "9.9.9.9", "208.67.222.222"]
+    );
+
+    let ports: Vec<Option<u16>> = servers
+        .iter()
+        .map(|server| match server {
+            DnsServer::Simple(_) => None,
+            DnsServer::Complex { port, .. } => *port,
+        })
+        .collect();
+    assert_eq!(ports, vec![None, Some(53), Some(5353), None]);
+}
+
+#[test]
+fn test_websocket_headers_preserve_order() {
+    use server_r_client::WebSocketConfig;
+
+    let json = r#"{"path": "/ws", "headers": {"User-Agent": "ua", "Host": "example.com", "Accept": "*/*"}}"#;
+
+    let config: WebSocketConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.header("host"), Some("example.com"));
+    assert_eq!(config.header("missing"), None);
+
+    let names: Vec<&str> = config
+        .headers
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(names, vec!["User-Agent", "Host", "Accept"]);
+
+    let serialized = serde_json::to_string(&config).unwrap();
+    assert_eq!(
+        serialized,
+        r#"{"path":"/ws","headers":{"User-Agent":"ua","Host":"example.com","Accept":"*/*"}}"#
+    );
 }
 
 #[test]
@@ -216,7 +795,8 @@ fn test_anytls_config_deserialization() {
         "server_port": 443,
         "allow_insecure": false,
         "server_name": "example.com",
-        "padding_rules": ["rule1", "rule2"]
+        "padding_rules": ["rule1", "rule2"],
+        "alpn": ["h2", "http/1.1"]
     }"#;
 
     let config: AnyTLSConfig = serde_json::from_str(json).unwrap();
@@ -227,6 +807,52 @@ fn test_anytls_config_deserialization() {
         config.padding_rules,
         Some(vec!["rule1".to_string(), "rule2".to_string()])
     );
+    assert_eq!(
+        config.alpn,
+        Some(vec!["h2".to_string(), "http/1.1".to_string()])
+    );
+}
+
+#[test]
+fn test_anytls_padding_rules() {
+    use server_r_client::{PaddingRule, PaddingSize};
+
+    let json = r#"{
+        "id": 6,
+        "server_port": 443,
+        "padding_rules": ["0-1:30", "2-4:100-400", "5:500"]
+    }"#;
+
+    let config: AnyTLSConfig = serde_json::from_str(json).unwrap();
+    let rules = config.parsed_padding_rules().unwrap();
+    assert_eq!(
+        rules,
+        vec![
+            PaddingRule {
+                from: 0,
+                to: 1,
+                size: PaddingSize::Fixed(30)
+            },
+            PaddingRule {
+                from: 2,
+                to: 4,
+                size: PaddingSize::Range(100, 400)
+            },
+            PaddingRule {
+                from: 5,
+                to: 5,
+                size: PaddingSize::Fixed(500)
+            },
+        ]
+    );
+    assert_eq!(rules[1].to_string(), "2-4:100-400");
+
+    let bad: AnyTLSConfig =
+        serde_json::from_str(r#"{"id": 6, "server_port": 443, "padding_rules": ["0-1:abc"]}"#)
+            .unwrap();
+    let err = bad.parsed_padding_rules().unwrap_err();
+    assert!(err.is_parse_error());
+    assert!(err.to_string().contains("0-1:abc"));
 }
 
 #[test]
@@ -235,10 +861,11 @@ fn test_node_config_enum_type_conversion() {
         id: 1,
         server_port: 443,
         allow_insecure: false,
-        server_name: None,
+        server_name: Vec::new(),
         network: None,
         websocket_config: None,
         grpc_config: None,
+        alpn: None,
     });
 
     assert!(trojan.as_trojan().is_ok());
@@ -247,6 +874,92 @@ fn test_node_config_enum_type_conversion() {
     assert_eq!(trojan.type_name(), "trojan");
 }
 
+#[test]
+fn test_to_singbox_outbound() {
+    let json = r#"{
+        "id": 5,
+        "server_port": 443,
+        "tls": true,
+        "network": "ws",
+        "tls_config": {"server_name": "example.com"},
+        "websocket_config": {"path": "/ws"}
+    }"#;
+    let vmess = NodeConfigEnum::VMess(serde_json::from_str(json).unwrap());
+
+    let outbound = vmess.to_singbox_outbound("vmess-5", "uuid-1").unwrap();
+    assert_eq!(outbound["type"], "vmess");
+    assert_eq!(outbound["tag"], "vmess-5");
+    assert_eq!(outbound["uuid"], "uuid-1");
+    assert_eq!(outbound["server_port"], 443);
+    assert_eq!(outbound["tls"]["server_name"], "example.com");
+    assert_eq!(outbound["transport"]["type"], "ws");
+    assert_eq!(outbound["transport"]["path"], "/ws");
+
+    let hy2: Hysteria2Config =
+        serde_json::from_str(r#"{"id": 4, "server_port": 443, "obfs": "secret"}"#).unwrap();
+    let outbound = NodeConfigEnum::Hysteria2(hy2)
+        .to_singbox_outbound("hy2", "pw")
+        .unwrap();
+    assert_eq!(outbound["obfs"]["type"], "salamander");
+    assert_eq!(outbound["obfs"]["password"], "secret");
+}
+
+#[test]
+fn test_vmess_config_builder() {
+    use server_r_client::{GrpcConfig, TlsConfig};
+
+    let built = VMessConfig::builder(5, 443)
+        .with_tls(true)
+        .with_network(Network::Grpc)
+        .with_alter_id(0)
+        .with_security("auto")
+        .with_tls_config(TlsConfig {
+            server_name: Some("example.com".to_string()),
+            ..Default::default()
+        })
+        .with_grpc_config(GrpcConfig::new(["svc"]))
+        .build();
+
+    let parsed: VMessConfig = serde_json::from_str(
+        r#"{
+            "id": 5,
+            "server_port": 443,
+            "tls": true,
+            "network": "grpc",
+            "alter_id": 0,
+            "security": "auto",
+            "tls_config": {"server_name": "example.com"},
+            "grpc_config": {"service_name": "svc"}
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        serde_json::to_value(&built).unwrap(),
+        serde_json::to_value(&parsed).unwrap()
+    );
+
+    let bare = VMessConfig::builder(6, 80).build();
+    assert!(!bare.tls);
+    assert!(bare.network.is_none());
+    assert!(bare.websocket_config.is_none());
+    assert!(bare.dns_settings.is_none());
+}
+
+#[test]
+fn test_to_singbox_outbound_unsupported() {
+    let ss: ShadowsocksConfig = serde_json::from_str(r#"{"id": 2, "server_port": 8388}"#).unwrap();
+    assert!(NodeConfigEnum::ShadowSocks(ss)
+        .to_singbox_outbound("ss", "pw")
+        .is_err());
+
+    let vmess: VMessConfig =
+        serde_json::from_str(r#"{"id": 5, "server_port": 443, "network": "kcp"}"#).unwrap();
+    let err = NodeConfigEnum::VMess(vmess)
+        .to_singbox_outbound("vmess", "uuid")
+        .unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+}
+
 #[test]
 fn test_user_serialization() {
     use server_r_client::User;
@@ -277,27 +990,324 @@ fn test_user_traffic_serialization() {
 }
 
 #[test]
-fn test_traffic_stats_serialization() {
-    let mut stats = TrafficStats::new();
-    stats.add_user(1, 100);
+fn test_submit_request_online_users_serialization() {
+    use server_r_client::SubmitRequest;
 
-    let json = serde_json::to_string(&stats).unwrap();
-    assert!(json.contains("\"count\":1"));
-    assert!(json.contains("\"requests\":100"));
+    let request = SubmitRequest::new("reg-1", vec![UserTraffic::new(1, 10, 20)]);
+    let json = serde_json::to_value(&request).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "register_id": "reg-1",
+            "data": [{"user_id": 1, "u": 10, "d": 20, "n": 0}],
+        })
+    );
+
+    let json = serde_json::to_value(request.with_online_users(vec![1, 7])).unwrap();
+    assert_eq!(json["online_users"], serde_json::json!([1, 7]));
 }
 
 #[test]
-fn test_tuic_config_deserialization() {
-    let json = r#"{
-        "id": 1,
-        "server_port": 44442,
-        "zero_rtt_handshake": true
-    }"#;
+fn test_traffic_stats_from_traffic() {
+    let batch = vec![
+        UserTraffic::with_count(1, 100, 200, 3),
+        UserTraffic::with_count(2, 50, 25, 4),
+        UserTraffic::with_count(1, 10, 10, 2),
+    ];
 
-    let config: TuicConfig = serde_json::from_str(json).unwrap();
-    assert_eq!(config.id, 1);
-    assert_eq!(config.server_port, 44442);
-    assert!(config.zero_rtt_handshake);
+    let stats = TrafficStats::from_traffic(&batch);
+    assert_eq!(stats.count, 2);
+    assert_eq!(stats.requests, 9);
+    assert_eq!(stats.user_ids, vec![1, 2]);
+    assert_eq!(stats.user_requests[&1], 5);
+    assert_eq!(stats.user_requests[&2], 4);
+}
+
+#[test]
+fn test_user_traffic_diff() {
+    let prev = vec![
+        UserTraffic::with_count(1, 1000, 2000, 10),
+        UserTraffic::with_count(2, 500, 500, 5),
+        UserTraffic::with_count(3, 100, 100, 1),
+    ];
+    let curr = vec![
+        UserTraffic::with_count(1, 1500, 2600, 12),
+        // Counters reset to a lower value
+        UserTraffic::with_count(2, 100, 50, 1),
+        // Newly appearing user
+        UserTraffic::with_count(4, 300, 400, 2),
+    ];
+
+    let diff = UserTraffic::diff(&prev, &curr);
+    let values: Vec<(i64, u64, u64, u64)> =
+        diff.iter().map(|t| (t.user_id, t.u, t.d, t.n)).collect();
+    assert_eq!(
+        values,
+        vec![(1, 500, 600, 2), (2, 0, 0, 0), (4, 300, 400, 2)]
+    );
+}
+
+#[test]
+fn test_user_traffic_totals() {
+    use server_r_client::{total_bytes, total_download, total_upload};
+
+    assert_eq!(total_upload(&[]), 0);
+    assert_eq!(total_download(&[]), 0);
+    assert_eq!(total_bytes(&[]), 0);
+
+    let batch = vec![UserTraffic::new(1, 100, 200), UserTraffic::new(2, 50, 25)];
+    assert_eq!(total_upload(&batch), 150);
+    assert_eq!(total_download(&batch), 225);
+    assert_eq!(total_bytes(&batch), 375);
+
+    let huge = vec![
+        UserTraffic::new(1, u64::MAX, u64::MAX - 1),
+        UserTraffic::new(2, 1, 1),
+    ];
+    assert_eq!(total_upload(&huge), u64::MAX);
+    assert_eq!(total_download(&huge), u64::MAX);
+    assert_eq!(total_bytes(&huge), u64::MAX);
+}
+
+#[test]
+fn test_user_traffic_field_styles_round_trip() {
+    use server_r_client::UserTrafficVerbose;
+
+    let traffic = UserTraffic::with_count(1, 1000, 2000, 5);
+
+    let short = serde_json::to_string(&traffic).unwrap();
+    let parsed: UserTraffic = serde_json::from_str(&short).unwrap();
+    assert_eq!((parsed.u, parsed.d, parsed.n), (1000, 2000, 5));
+
+    let verbose = serde_json::to_string(&UserTrafficVerbose::from(traffic)).unwrap();
+    assert_eq!(
+        verbose,
+        r#"{"user_id":1,"upload":1000,"download":2000,"count":5}"#
+    );
+    let parsed: UserTraffic = serde_json::from_str(&verbose).unwrap();
+    assert_eq!((parsed.u, parsed.d, parsed.n), (1000, 2000, 5));
+}
+
+#[test]
+fn test_traffic_stats_serialization() {
+    let mut stats = TrafficStats::new();
+    stats.add_user(1, 100);
+
+    let json = serde_json::to_string(&stats).unwrap();
+    assert!(json.contains("\"count\":1"));
+    assert!(json.contains("\"requests\":100"));
+}
+
+#[test]
+fn test_tuic_config_deserialization() {
+    let json = r#"{
+        "id": 1,
+        "server_port": 44442,
+        "zero_rtt_handshake": true
+    }"#;
+
+    let config: TuicConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.id, 1);
+    assert_eq!(config.server_port, 44442);
+    assert!(config.zero_rtt_handshake);
+}
+
+#[test]
+fn test_tuic_config_extra_fields() {
+    let json = r#"{
+        "id": 1,
+        "server_port": 44442,
+        "server_name": "example.com",
+        "congestion_control": "bbr",
+        "udp_relay_mode": "native",
+        "alpn": ["h3"]
+    }"#;
+
+    let config: TuicConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.congestion_control.as_deref(), Some("bbr"));
+    assert_eq!(config.udp_relay_mode.as_deref(), Some("native"));
+    assert_eq!(config.alpn, Some(vec!["h3".to_string()]));
+
+    let minimal: TuicConfig = serde_json::from_str(r#"{"id": 1, "server_port": 1}"#).unwrap();
+    assert!(minimal.congestion_control.is_none());
+    assert!(minimal.udp_relay_mode.is_none());
+    assert!(minimal.alpn.is_none());
+}
+
+#[test]
+fn test_alpn_array_and_comma_string() {
+    let h3_h2 = vec!["h3".to_string(), "h2".to_string()];
+
+    let trojan: TrojanConfig =
+        serde_json::from_str(r#"{"id": 1, "server_port": 443, "alpn": ["h3", "h2"]}"#).unwrap();
+    assert_eq!(trojan.alpn, Some(h3_h2.clone()));
+    assert_eq!(trojan.alpn_list(), h3_h2);
+
+    let trojan: TrojanConfig =
+        serde_json::from_str(r#"{"id": 1, "server_port": 443, "alpn": "h3, h2"}"#).unwrap();
+    assert_eq!(trojan.alpn_list(), h3_h2);
+
+    let hy2: Hysteria2Config =
+        serde_json::from_str(r#"{"id": 4, "server_port": 443, "alpn": "h3,h2"}"#).unwrap();
+    assert_eq!(hy2.alpn_list(), h3_h2);
+
+    let tuic: TuicConfig =
+        serde_json::from_str(r#"{"id": 1, "server_port": 443, "alpn": "h3"}"#).unwrap();
+    assert_eq!(tuic.alpn_list(), vec!["h3".to_string()]);
+
+    let vmess: VMessConfig = serde_json::from_str(
+        r#"{"id": 5, "server_port": 443, "tls": true, "tls_config": {"alpn": "h2,http/1.1"}}"#,
+    )
+    .unwrap();
+    let tls = vmess.tls_config.as_ref().unwrap();
+    assert_eq!(
+        tls.alpn_list(),
+        vec!["h2".to_string(), "http/1.1".to_string()]
+    );
+
+    let outbound = NodeConfigEnum::VMess(vmess)
+        .to_singbox_outbound("vmess", "uuid")
+        .unwrap();
+    assert_eq!(
+        outbound["tls"]["alpn"],
+        serde_json::json!(["h2", "http/1.1"])
+    );
+
+    let empty: TrojanConfig =
+        serde_json::from_str(r#"{"id": 1, "server_port": 443, "alpn": ""}"#).unwrap();
+    assert!(empty.alpn.is_none());
+    assert!(empty.alpn_list().is_empty());
+}
+
+#[test]
+fn test_config_api_response_round_trip() {
+    use server_r_client::{parse_config, parse_raw_config_response};
+
+    let cases = [
+        (
+            NodeType::Trojan,
+            r#"{"id": 1, "server_port": 443, "server_name": "a.com", "network": "ws",
+                "websocket_config": {"path": "/ws", "headers": {"Host": "a.com"}}}"#,
+        ),
+        (
+            NodeType::ShadowSocks,
+            r#"{"id": 2, "server_port": 8388, "method": "aes-256-gcm"}"#,
+        ),
+        (
+            NodeType::Hysteria,
+            r#"{"id": 3, "server_port": 443, "up_mbps": "100", "obfs": "secret"}"#,
+        ),
+        (
+            NodeType::Hysteria2,
+            r#"{"id": 4, "server_port": 443, "down_mbps": 200,
+                "obfs": {"type": "salamander", "password": "pw"}}"#,
+        ),
+        (
+            NodeType::VMess,
+            r#"{"id": 5, "server_port": 443, "tls": true, "alter_id": 0, "security": "auto"}"#,
+        ),
+        (
+            NodeType::AnyTLS,
+            r#"{"id": 6, "server_port": 443, "padding_rules": ["0-1:30"], "alpn": ["h2"]}"#,
+        ),
+        (
+            NodeType::Tuic,
+            r#"{"id": 7, "server_port": 443, "zero_rtt_handshake": 1, "congestion_control": "bbr"}"#,
+        ),
+    ];
+
+    for (node_type, json) in cases {
+        let config = parse_config(node_type, json.as_bytes()).unwrap();
+        let bytes = config.to_api_response_bytes().unwrap();
+        let parsed = parse_raw_config_response(node_type, &bytes).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&config).unwrap(),
+            "{} config did not round-trip",
+            node_type
+        );
+    }
+}
+
+#[test]
+fn test_config_try_from_bytes_round_trip() {
+    use server_r_client::{
+        AnyTLSConfig, Hysteria2Config, HysteriaConfig, ShadowsocksConfig, TuicConfig, VMessConfig,
+    };
+
+    fn round_trip<T>(json: &str)
+    where
+        T: for<'a> TryFrom<&'a [u8], Error = ApiError> + serde::Serialize,
+    {
+        let config = T::try_from(json.as_bytes()).unwrap();
+        let bytes = serde_json::to_vec(&config).unwrap();
+        let parsed = T::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+    }
+
+    round_trip::<TrojanConfig>(r#"{"id": 1, "server_port": 443, "alpn": "h2,http/1.1"}"#);
+    round_trip::<ShadowsocksConfig>(r#"{"id": 2, "server_port": 8388, "method": "aes-256-gcm"}"#);
+    round_trip::<HysteriaConfig>(r#"{"id": 3, "server_port": 443, "up_mbps": "100"}"#);
+    round_trip::<Hysteria2Config>(r#"{"id": 4, "server_port": 443, "down_mbps": 200}"#);
+    round_trip::<VMessConfig>(r#"{"id": 5, "server_port": 443, "tls": true}"#);
+    round_trip::<AnyTLSConfig>(r#"{"id": 6, "server_port": 443, "padding_rules": ["0-1:30"]}"#);
+    round_trip::<TuicConfig>(r#"{"id": 7, "server_port": 443, "congestion_control": "bbr"}"#);
+
+    let err = TrojanConfig::try_from(&b"{not json"[..]).unwrap_err();
+    assert!(matches!(err, ApiError::ParseError { .. }));
+}
+
+#[test]
+fn test_parse_config_strict() {
+    use server_r_client::{parse_config, parse_config_strict};
+
+    let known = br#"{
+        "id": 1,
+        "server_port": 443,
+        "network": "ws",
+        "websocket_config": {"path": "/ws", "headers": {"Host": "a.com"}}
+    }"#;
+    assert!(parse_config_strict(NodeType::Trojan, known).is_ok());
+
+    let drifted = br#"{
+        "id": 1,
+        "server_port": 443,
+        "flow": "xtls-rprx-vision",
+        "websocket_config": {"path": "/ws", "early_data": 2048}
+    }"#;
+    let err = parse_config_strict(NodeType::Trojan, drifted).unwrap_err();
+    assert!(err.is_parse_error());
+    let message = err.to_string();
+    assert!(message.contains("flow"));
+    assert!(message.contains("websocket_config.early_data"));
+
+    // The lenient parser still accepts it
+    assert!(parse_config(NodeType::Trojan, drifted).is_ok());
+}
+
+#[test]
+fn test_parse_config_by_name_unknown_type() {
+    use server_r_client::parse_config_by_name;
+
+    let json = br#"{"id": 9, "server_port": 8443, "flow": "xtls-rprx-vision"}"#;
+
+    let config = parse_config_by_name("vless", json).unwrap();
+    assert!(config.is_unknown());
+    assert_eq!(config.type_name(), "unknown");
+    assert_eq!(config.node_type_name(), "vless");
+    match &config {
+        NodeConfigEnum::Unknown { raw, .. } => assert_eq!(raw["flow"], "xtls-rprx-vision"),
+        other => panic!("expected unknown config, got {:?}", other),
+    }
+    assert!(config.as_trojan().is_err());
+
+    let known = parse_config_by_name("trojan", json).unwrap();
+    assert!(!known.is_unknown());
+    assert_eq!(known.type_name(), "trojan");
+    assert_eq!(known.node_type_name(), "trojan");
 }
 
 // Real integration tests - run with: cargo test --test integration_tests real_ -- --ignored --nocapture
@@ -360,3 +1370,3084 @@ async fn real_test_tuic_config() {
 
     println!("\n✓ All TUIC config tests passed!");
 }
+
+// Mock server tests
+
+#[tokio::test]
+async fn test_users_stream_skips_not_modified() {
+    use futures_util::StreamExt;
+    use wiremock::matchers::{header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(header_exists("If-None-Match"))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": [{"id": 1, "uuid": "abc"}]}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let stream = client.users_stream(NodeType::Trojan, "reg-1", Duration::from_millis(10));
+    tokio::pin!(stream);
+
+    let users = stream.next().await.unwrap().unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].uuid, "abc");
+
+    // Subsequent polls return 304 and must not yield anything
+    let next = tokio::time::timeout(Duration::from_millis(100), stream.next()).await;
+    assert!(next.is_err());
+}
+
+#[tokio::test]
+async fn test_submit_idempotent_sends_key() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .and(header("Idempotency-Key", "key-123"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let data = vec![UserTraffic::new(1, 100, 200)];
+
+    // Retrying with the same key sends the same header
+    for _ in 0..2 {
+        client
+            .submit_idempotent(NodeType::Trojan, "reg-1", data.clone(), "key-123")
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_submit_generates_idempotency_key_per_call() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(502).set_body_string("bad gateway"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let data = vec![UserTraffic::new(1, 100, 200)];
+
+    // Identical data after a failure may be a new period's traffic, not a retry
+    assert!(client
+        .submit(NodeType::Trojan, "reg-1", data.clone())
+        .await
+        .is_err());
+    client
+        .submit(NodeType::Trojan, "reg-1", data.clone())
+        .await
+        .unwrap();
+    client
+        .submit(NodeType::Trojan, "reg-1", data)
+        .await
+        .unwrap();
+
+    let keys: Vec<String> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| {
+            r.headers
+                .get("Idempotency-Key")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    assert_eq!(keys.len(), 3);
+    assert_ne!(keys[0], keys[1]);
+    assert_ne!(keys[1], keys[2]);
+    assert_eq!(keys[0].len(), 36);
+}
+
+#[tokio::test]
+async fn test_unregister_many_reports_each_outcome() {
+    use wiremock::matchers::{method, path, path_regex, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/unregister"))
+        .and(query_param("register_id", "bad"))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/api/v1/server/enhanced/\w+/unregister$"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let results = client
+        .unregister_many(&[
+            (NodeType::Trojan, "bad"),
+            (NodeType::Trojan, "good"),
+            (NodeType::VMess, "other"),
+        ])
+        .await;
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].0, NodeType::Trojan);
+    assert_eq!(results[0].1, "bad");
+    assert!(results[0].2.as_ref().unwrap_err().is_server_error());
+    assert!(results[1].2.is_ok());
+    assert_eq!(results[2].0, NodeType::VMess);
+    assert!(results[2].2.is_ok());
+}
+
+#[tokio::test]
+async fn test_etag_cache_inspection() {
+    use wiremock::matchers::{method, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/api/v1/server/enhanced/\w+/users$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": []}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    assert_eq!(client.etag_cache_len().await, 0);
+
+    client.users(NodeType::Trojan, "reg-1").await.unwrap();
+    client.users(NodeType::VMess, "reg-2").await.unwrap();
+    assert_eq!(client.etag_cache_len().await, 2);
+
+    let mut keys = client.etag_cache_keys().await;
+    keys.sort();
+    assert_eq!(keys, vec!["trojan:reg-1", "vmess:reg-2"]);
+
+    assert_eq!(
+        client.remove_etag(NodeType::Trojan, "reg-1").await,
+        Some("\"v1\"".to_string())
+    );
+    assert_eq!(client.etag_cache_len().await, 1);
+    assert!(client.get_etag(NodeType::Trojan, "reg-1").await.is_none());
+}
+
+#[tokio::test]
+async fn test_etag_cache_capacity_evicts_lru() {
+    use wiremock::matchers::{method, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/api/v1/server/enhanced/trojan/users$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": []}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let config = Config::new(server.uri(), "test-token").with_etag_cache_capacity(2);
+    let client = ApiClient::new(config).unwrap();
+
+    client.users(NodeType::Trojan, "a").await.unwrap();
+    client.users(NodeType::Trojan, "b").await.unwrap();
+    // Touch "a" so that "b" becomes least recently used
+    client.users(NodeType::Trojan, "a").await.unwrap();
+    client.users(NodeType::Trojan, "c").await.unwrap();
+
+    assert_eq!(client.etag_cache_len().await, 2);
+    assert!(client.get_etag(NodeType::Trojan, "a").await.is_some());
+    assert!(client.get_etag(NodeType::Trojan, "b").await.is_none());
+    assert!(client.get_etag(NodeType::Trojan, "c").await.is_some());
+}
+
+#[tokio::test]
+async fn test_sni_override_rewrites_host() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    let port = server.address().port();
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .and(header(
+            "Host",
+            format!("panel.example.test:{}", port).as_str(),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = Config::new(server.uri(), "test-token").with_sni_override("panel.example.test");
+    let client = ApiClient::new(config).unwrap();
+
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_sni_override_resolves_api_host_on_connect() {
+    use server_r_client::IpFamily;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    let port = server.address().port();
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .and(header(
+            "Host",
+            format!("panel.example.test:{}", port).as_str(),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // A domain api_host is looked up when connecting, not when building the client
+    let config = Config::new(format!("http://localhost:{}", port), "test-token")
+        .with_sni_override("panel.example.test")
+        .with_ip_family(IpFamily::V4Only);
+    let client = ApiClient::new(config).unwrap();
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    // Resolution failures surface as network errors of the request
+    let config = Config::new("http://unresolvable.invalid", "test-token")
+        .with_sni_override("panel.example.test");
+    let client = ApiClient::new(config).unwrap();
+    let err = client
+        .heartbeat(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap_err();
+    assert!(err.is_network_error());
+}
+
+#[tokio::test]
+async fn test_users_changed() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": [{"id": 1, "uuid": "abc"}]}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let first = client
+        .users_changed(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap();
+    assert_eq!(first.unwrap().len(), 1);
+
+    let second = client
+        .users_changed(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap();
+    assert!(second.is_none());
+}
+
+#[tokio::test]
+async fn test_register_full_returns_metadata() {
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/register"))
+        .and(query_param("node_id", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"data": {"register_id": "reg-1", "expires_at": 1700000000, "heartbeat_interval": 30}}"#,
+        ))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let request = RegisterRequest::new("node.example.com", 443);
+
+    let data = client
+        .register_full(NodeType::Trojan, 1, request.clone())
+        .await
+        .unwrap();
+    assert_eq!(data.register_id, "reg-1");
+    assert_eq!(data.expires_at, Some(1700000000));
+    assert_eq!(data.heartbeat_interval, Some(30));
+
+    let register_id = client.register(NodeType::Trojan, 1, request).await.unwrap();
+    assert_eq!(register_id, "reg-1");
+}
+
+#[tokio::test]
+async fn test_ensure_registered() {
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .and(body_json(serde_json::json!({"register_id": "valid"})))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": true}"#))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .and(body_json(serde_json::json!({"register_id": "stale"})))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": false}"#))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/register"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(r#"{"data": {"register_id": "fresh"}}"#),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let request = RegisterRequest::new("node.example.com", 443);
+
+    let id = client
+        .ensure_registered(NodeType::Trojan, 1, "valid", request.clone())
+        .await
+        .unwrap();
+    assert_eq!(id, "valid");
+
+    let id = client
+        .ensure_registered(NodeType::Trojan, 1, "stale", request)
+        .await
+        .unwrap();
+    assert_eq!(id, "fresh");
+}
+
+#[tokio::test]
+async fn test_non_zero_body_code_is_server_error() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(
+                r#"{"code": 1001, "message": "register_id expired", "data": null}"#,
+            ),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"code": 1, "message": "node disabled", "data": {"id": 1, "server_port": 443}}"#,
+        ))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let err = client.users(NodeType::Trojan, "reg-1").await.unwrap_err();
+    assert!(err.is_server_error());
+    assert!(err.to_string().contains("register_id expired"));
+
+    let err = client.config(NodeType::Trojan, 1).await.unwrap_err();
+    assert!(err.is_server_error());
+    assert!(err.to_string().contains("node disabled"));
+}
+
+#[test]
+fn test_api_response_code() {
+    use server_r_client::ApiResponse;
+
+    let ok: ApiResponse<bool> = serde_json::from_str(r#"{"data": true, "code": 0}"#).unwrap();
+    assert!(ok.is_ok());
+
+    let missing: ApiResponse<bool> = serde_json::from_str(r#"{"data": true}"#).unwrap();
+    assert!(missing.is_ok());
+
+    let failed: ApiResponse<bool> = serde_json::from_str(r#"{"data": false, "code": 2}"#).unwrap();
+    assert!(!failed.is_ok());
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn test_blocking_client() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start());
+
+    runtime.block_on(
+        Mock::given(method("GET"))
+            .and(path("/api/v1/server/enhanced/trojan/users"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(r#"{"data": [{"id": 1, "uuid": "abc"}]}"#),
+            )
+            .mount(&server),
+    );
+    runtime.block_on(
+        Mock::given(method("POST"))
+            .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+            .mount(&server),
+    );
+
+    let client =
+        server_r_client::blocking::ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let users = client.users(NodeType::Trojan, "reg-1").unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(
+        client.get_etag(NodeType::Trojan, "reg-1"),
+        Some("\"v1\"".to_string())
+    );
+
+    client.heartbeat(NodeType::Trojan, "reg-1").unwrap();
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn test_blocking_export_and_import_state() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start());
+
+    runtime.block_on(
+        Mock::given(method("POST"))
+            .and(path("/api/v1/server/enhanced/trojan/register"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"data": {"register_id": "reg-1"}}"#),
+            )
+            .mount(&server),
+    );
+    runtime.block_on(
+        Mock::given(method("GET"))
+            .and(path("/api/v1/server/enhanced/trojan/users"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(r#"{"data": []}"#),
+            )
+            .mount(&server),
+    );
+    runtime.block_on(
+        Mock::given(method("POST"))
+            .and(path("/api/v1/server/enhanced/trojan/unregister"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": null}"#))
+            .mount(&server),
+    );
+
+    let client =
+        server_r_client::blocking::ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let request = RegisterRequest::new("node.example.com", 443);
+    let id = client.register(NodeType::Trojan, 1, request).unwrap();
+    client.users(NodeType::Trojan, &id).unwrap();
+
+    let state = client.export_state().unwrap();
+    assert_eq!(
+        state,
+        serde_json::json!({
+            "registrations": {
+                "trojan": [{"register_id": "reg-1", "etag": "\"v1\""}]
+            }
+        })
+    );
+
+    // State exported by either client can be imported by the other
+    let restored = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    restored.import_state(state.clone()).unwrap();
+    assert_eq!(restored.export_state().unwrap(), state);
+
+    client.unregister(NodeType::Trojan, "reg-1").unwrap();
+    assert_eq!(
+        client.export_state().unwrap(),
+        serde_json::json!({"registrations": {}})
+    );
+}
+
+#[cfg(feature = "msgpack")]
+#[tokio::test]
+async fn test_submit_msgpack_encoding() {
+    use server_r_client::{Encoding, SubmitRequest};
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .and(header("Content-Type", "application/msgpack"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = Config::new(server.uri(), "test-token").with_content_encoding(Encoding::MsgPack);
+    let client = ApiClient::new(config).unwrap();
+
+    client
+        .submit(
+            NodeType::Trojan,
+            "reg-1",
+            vec![UserTraffic::new(1, 100, 200)],
+        )
+        .await
+        .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: SubmitRequest = rmp_serde::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body.register_id, "reg-1");
+    assert_eq!(body.data[0].u, 100);
+}
+
+#[tokio::test]
+async fn test_request_observer() {
+    use server_r_client::RequestObserver;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Default)]
+    struct Recorder {
+        requests: Mutex<Vec<(String, String)>>,
+        statuses: Mutex<Vec<u16>>,
+    }
+
+    impl RequestObserver for Recorder {
+        fn on_request(&self, method: &str, url: &str) {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((method.to_string(), url.to_string()));
+        }
+
+        fn on_response(&self, status: u16, _elapsed: Duration, _bytes_len: Option<u64>) {
+            self.statuses.lock().unwrap().push(status);
+        }
+    }
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/unregister"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let recorder = Arc::new(Recorder::default());
+    let config = Config::new(server.uri(), "secret-token").with_observer(recorder.clone());
+    let client = ApiClient::new(config).unwrap();
+
+    assert!(client.unregister(NodeType::Trojan, "reg-1").await.is_err());
+
+    let requests = recorder.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, "POST");
+    assert!(requests[0].1.contains("register_id=reg-1"));
+    assert!(!requests[0].1.contains("secret-token"));
+    assert_eq!(*recorder.statuses.lock().unwrap(), vec![500]);
+}
+
+#[tokio::test]
+async fn test_proxy_configuration() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // The mock server acts as an HTTP proxy for an unresolvable panel host
+    let proxy = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&proxy)
+        .await;
+
+    let config = Config::new("http://panel.invalid", "test-token").with_proxy(Some(proxy.uri()));
+    let client = ApiClient::new(config).unwrap();
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    let config = Config::new("http://panel.invalid", "test-token")
+        .with_proxy(Some("ftp://proxy.example.com".to_string()));
+    assert!(matches!(
+        ApiClient::new(config),
+        Err(ApiError::ConfigError { .. })
+    ));
+
+    let config = Config::new("http://panel.invalid", "test-token")
+        .with_proxy(Some("socks5://127.0.0.1:1080".to_string()));
+    assert!(ApiClient::new(config).is_ok());
+}
+
+#[tokio::test]
+async fn test_users_cancellable() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": []}"#)
+                .set_delay(Duration::from_secs(5)),
+        )
+        .mount(&server)
+        .await;
+
+    let config = Config::new(server.uri(), "test-token").with_timeout(Duration::from_secs(10));
+    let client = ApiClient::new(config).unwrap();
+
+    let cancel = tokio::time::sleep(Duration::from_millis(50));
+    let started = std::time::Instant::now();
+    let err = client
+        .users_cancellable(NodeType::Trojan, "reg-1", cancel)
+        .await
+        .unwrap_err();
+
+    assert!(err.is_cancelled());
+    assert_eq!(err.error_type(), server_r_client::ErrorType::Cancelled);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_parse_error_includes_body_snippet() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<html>maintenance</html>"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(2000)))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "secret-token")).unwrap();
+
+    let err = client.verify(NodeType::Trojan, "reg-1").await.unwrap_err();
+    assert!(err.is_parse_error());
+    assert!(err.to_string().contains("<html>maintenance</html>"));
+    assert!(!err.to_string().contains("secret-token"));
+
+    let err = client.users(NodeType::Trojan, "reg-1").await.unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains(&format!("{}...", "x".repeat(512))));
+    assert!(!message.contains(&"x".repeat(513)));
+}
+
+#[tokio::test]
+async fn test_detect_public_ip() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/v4"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("203.0.113.7\n"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v6"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("2001:db8::1"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/bad"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("not an ip"))
+        .mount(&server)
+        .await;
+
+    let detect = |endpoint: &str| {
+        let config = Config::new("https://api.example.com", "test-token")
+            .with_ip_echo_url(format!("{}{}", server.uri(), endpoint));
+        ApiClient::new(config).unwrap()
+    };
+
+    assert_eq!(
+        detect("/v4").detect_public_ip().await.unwrap(),
+        "203.0.113.7"
+    );
+    assert_eq!(
+        detect("/v6").detect_public_ip().await.unwrap(),
+        "2001:db8::1"
+    );
+    assert!(detect("/bad")
+        .detect_public_ip()
+        .await
+        .unwrap_err()
+        .is_parse_error());
+}
+
+#[tokio::test]
+async fn test_detect_public_ip_skips_panel_bookkeeping() {
+    use server_r_client::RequestObserver;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::SystemTime;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Default)]
+    struct Count(AtomicUsize);
+
+    impl RequestObserver for Count {
+        fn on_request(&self, _method: &str, _url: &str) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let server = MockServer::start().await;
+    let skewed = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(3600));
+    Mock::given(method("GET"))
+        .and(path("/ip"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Date", skewed.as_str())
+                .set_body_string("203.0.113.7"),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/down"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("maintenance"))
+        .mount(&server)
+        .await;
+
+    let count = Arc::new(Count::default());
+    let echo = |endpoint: &str| {
+        let config = Config::new(server.uri(), "test-token")
+            .with_observer(count.clone())
+            .with_ip_echo_url(format!("{}{}", server.uri(), endpoint));
+        ApiClient::new(config).unwrap()
+    };
+
+    let client = echo("/ip");
+    assert_eq!(client.detect_public_ip().await.unwrap(), "203.0.113.7");
+    assert_eq!(client.last_server_time_skew(), None);
+
+    let err = echo("/down").detect_public_ip().await.unwrap_err();
+    assert!(err.is_server_error());
+    assert_eq!(count.0.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_build_url_ipv6_host_and_existing_query() {
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // Route through the mock server as a proxy so the IPv6 literal never has to resolve
+    let proxy = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/panel/api/v1/server/enhanced/trojan/heartbeat"))
+        .and(query_param("region", "eu"))
+        .and(query_param("token", "a b&c"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&proxy)
+        .await;
+
+    let config =
+        Config::new("http://[::1]:8080/panel/?region=eu", "a b&c").with_proxy(Some(proxy.uri()));
+    let client = ApiClient::new(config).unwrap();
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    let requests = proxy.received_requests().await.unwrap();
+    assert_eq!(requests[0].url.host_str(), Some("[::1]"));
+}
+
+#[test]
+fn test_register_request_ipv6_hostname() {
+    let request = RegisterRequest::new("2001:db8::1", 443).with_node_ip("2001:db8::2");
+    let json = serde_json::to_string(&request).unwrap();
+    assert!(json.contains("\"hostname\":\"2001:db8::1\""));
+
+    let parsed: RegisterRequest = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.hostname, "2001:db8::1");
+    assert_eq!(parsed.node_ip.as_deref(), Some("2001:db8::2"));
+}
+
+#[tokio::test]
+async fn test_bootstrap() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/config"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": {"id": 1, "server_port": 443}}"#),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": [{"id": 1, "uuid": "abc"}]}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let (config, users) = client
+        .bootstrap(NodeType::Trojan, 1, "reg-1")
+        .await
+        .unwrap();
+
+    assert_eq!(config.as_trojan().unwrap().server_port, 443);
+    assert_eq!(users.unwrap().len(), 1);
+    assert_eq!(
+        client.get_etag(NodeType::Trojan, "reg-1").await,
+        Some("\"v1\"".to_string())
+    );
+
+    // An unchanged user list still yields the config
+    let (config, users) = client
+        .bootstrap(NodeType::Trojan, 1, "reg-1")
+        .await
+        .unwrap();
+    assert_eq!(config.as_trojan().unwrap().server_port, 443);
+    assert!(users.is_none());
+
+    #[cfg(feature = "blocking")]
+    {
+        let server_uri = server.uri();
+        let (config, users) = tokio::task::spawn_blocking(move || {
+            let client =
+                server_r_client::blocking::ApiClient::new(Config::new(server_uri, "test-token"))
+                    .unwrap();
+            client.bootstrap(NodeType::Trojan, 1, "reg-1").unwrap();
+            client.bootstrap(NodeType::Trojan, 1, "reg-1").unwrap()
+        })
+        .await
+        .unwrap();
+        assert_eq!(config.as_trojan().unwrap().server_port, 443);
+        assert!(users.is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_submit_verbose_traffic_fields() {
+    use server_r_client::TrafficFieldStyle;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submitWithAgent"))
+        .and(body_json(serde_json::json!({
+            "register_id": "reg-1",
+            "data": [{"user_id": 1, "upload": 100, "download": 200, "count": 0}]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = Config::new(server.uri(), "test-token")
+        .with_traffic_field_style(TrafficFieldStyle::Verbose);
+    let client = ApiClient::new(config).unwrap();
+
+    client
+        .submit_with_agent(
+            NodeType::Trojan,
+            "reg-1",
+            vec![UserTraffic::new(1, 100, 200)],
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_heartbeat_loop_yields_each_attempt() {
+    use futures_util::StreamExt;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("down"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let stream = client.heartbeat_loop(NodeType::Trojan, "reg-1", Duration::from_millis(10));
+    tokio::pin!(stream);
+
+    let first = stream.next().await.unwrap();
+    assert!(first.unwrap_err().is_server_error());
+    assert!(stream.next().await.unwrap().is_ok());
+    assert!(stream.next().await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_heartbeat_loop_zero_retry_after_waits_base_interval() {
+    use futures_util::StreamExt;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let stream = client.heartbeat_loop(NodeType::Trojan, "reg-1", Duration::from_millis(200));
+    tokio::pin!(stream);
+
+    let first = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(first.retry_after(), Some(Duration::ZERO));
+    let started = std::time::Instant::now();
+    assert!(stream.next().await.unwrap().is_err());
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn test_get_headers_returns_rate_limit_hints() {
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(query_param("register_id", "reg-1"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("Retry-After", "30")
+                .insert_header("X-RateLimit-Remaining", "0"),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let headers = client
+        .get_headers(
+            "/api/v1/server/enhanced/trojan/users",
+            &[("register_id", "reg-1")],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(headers.get("Retry-After").unwrap(), "30");
+    assert_eq!(headers.get("x-ratelimit-remaining").unwrap(), "0");
+}
+
+#[tokio::test]
+async fn test_endpoint_paths() {
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": true}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let node_type = NodeType::Hysteria2;
+
+    client.verify(node_type, "reg-1").await.unwrap();
+    client.unregister(node_type, "reg-1").await.unwrap();
+    client.submit(node_type, "reg-1", vec![]).await.unwrap();
+    client
+        .submit_with_agent(node_type, "reg-1", vec![])
+        .await
+        .unwrap();
+    client
+        .submit_stats_with_agent(node_type, "reg-1", TrafficStats::default())
+        .await
+        .unwrap();
+    client.heartbeat(node_type, "reg-1").await.unwrap();
+
+    let paths: Vec<String> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| r.url.path().to_string())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "/api/v1/server/enhanced/hysteria2/verify",
+            "/api/v1/server/enhanced/hysteria2/unregister",
+            "/api/v1/server/enhanced/hysteria2/submit",
+            "/api/v1/server/enhanced/hysteria2/submitWithAgent",
+            "/api/v1/server/enhanced/hysteria2/submitStatsWithAgent",
+            "/api/v1/server/enhanced/hysteria2/heartbeat",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_submit_traffic_and_stats() {
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submitWithAgent"))
+        .and(body_partial_json(serde_json::json!({
+            "data": [{"user_id": 1, "u": 100, "d": 200, "n": 3}]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submitStatsWithAgent"))
+        .and(body_partial_json(serde_json::json!({
+            "data": {"count": 1, "requests": 3, "user_ids": [1]}
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    client
+        .submit_traffic_and_stats(
+            NodeType::Trojan,
+            "reg-1",
+            vec![UserTraffic::with_count(1, 100, 200, 3)],
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_fake_panel_lifecycle() {
+    use common::{FakePanel, REGISTER_ID, USERS_ETAG};
+
+    let panel = FakePanel::start().await;
+    let client = panel.client();
+
+    let register_id = client
+        .register(
+            NodeType::Trojan,
+            1,
+            RegisterRequest::new("node.example.com", 443),
+        )
+        .await
+        .unwrap();
+    assert_eq!(register_id, REGISTER_ID);
+
+    let users = client.users(NodeType::Trojan, &register_id).await.unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(
+        client
+            .get_etag(NodeType::Trojan, &register_id)
+            .await
+            .as_deref(),
+        Some(USERS_ETAG)
+    );
+
+    // The cached ETag is sent back and the panel answers 304
+    let err = client
+        .users(NodeType::Trojan, &register_id)
+        .await
+        .unwrap_err();
+    assert!(err.is_not_modified());
+
+    client
+        .submit(
+            NodeType::Trojan,
+            &register_id,
+            vec![UserTraffic::new(1, 10, 20)],
+        )
+        .await
+        .unwrap();
+    client
+        .heartbeat(NodeType::Trojan, &register_id)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_fake_panel_rejects_bad_token() {
+    use common::{FakePanel, REGISTER_ID};
+
+    let panel = FakePanel::start().await;
+    let client = panel.client_with_token("wrong-token");
+
+    let err = client
+        .heartbeat(NodeType::Trojan, REGISTER_ID)
+        .await
+        .unwrap_err();
+    assert!(err.is_auth_error());
+
+    let err = client
+        .users(NodeType::Trojan, REGISTER_ID)
+        .await
+        .unwrap_err();
+    assert!(err.is_auth_error());
+}
+
+#[tokio::test]
+async fn test_concurrent_users_share_one_request() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": [{"id": 1, "uuid": "abc"}]}"#)
+                .set_delay(Duration::from_millis(100)),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let results =
+        futures_util::future::join_all((0..5).map(|_| client.users(NodeType::Trojan, "reg-1")))
+            .await;
+
+    for users in results {
+        let users = users.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].uuid, "abc");
+    }
+}
+
+#[tokio::test]
+async fn test_submit_detailed_reports_rejections() {
+    use server_r_client::SubmitResult;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(
+                r#"{"data": {"accepted": [1], "rejected": [[2, "user disabled"]]}}"#,
+            ),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let data = vec![UserTraffic::new(1, 100, 200), UserTraffic::new(2, 10, 20)];
+
+    let result = client
+        .submit_detailed(NodeType::Trojan, "reg-1", data.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        result,
+        SubmitResult {
+            accepted: vec![1],
+            rejected: vec![(2, "user disabled".to_string())],
+        }
+    );
+
+    // The plain variant ignores the detail
+    client
+        .submit(NodeType::Trojan, "reg-1", data.clone())
+        .await
+        .unwrap();
+
+    // Panels without per-user detail yield an empty result
+    for body in [r#"{"data": null}"#, r#"{"data": true}"#] {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/server/enhanced/trojan/submit"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+
+        let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+        let result = client
+            .submit_detailed(NodeType::Trojan, "reg-1", data.clone())
+            .await
+            .unwrap();
+        assert_eq!(result, SubmitResult::default());
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_node_id_rejected_before_request() {
+    use wiremock::MockServer;
+
+    let server = MockServer::start().await;
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let err = client.config(NodeType::Trojan, 0).await.unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+
+    let err = client
+        .register(
+            NodeType::Trojan,
+            -3,
+            RegisterRequest::new("node.example.com", 443),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_warm_cache_populates_etags_and_reports_failures() {
+    use common::{FakePanel, REGISTER_ID, USERS_ETAG};
+
+    let panel = FakePanel::start().await;
+    let client = panel.client();
+
+    assert!(client
+        .warm_cache(&[
+            (NodeType::Trojan, REGISTER_ID),
+            (NodeType::VMess, REGISTER_ID),
+        ])
+        .await
+        .is_empty());
+    assert_eq!(
+        client
+            .get_etag(NodeType::VMess, REGISTER_ID)
+            .await
+            .as_deref(),
+        Some(USERS_ETAG)
+    );
+
+    // Already-warm entries answer 304, which is not a failure
+    assert!(client
+        .warm_cache(&[(NodeType::Trojan, REGISTER_ID)])
+        .await
+        .is_empty());
+
+    let failures = client
+        .warm_cache(&[(NodeType::Trojan, REGISTER_ID), (NodeType::Tuic, "unknown")])
+        .await;
+    assert_eq!(failures.len(), 1);
+    let (node_type, register_id, error) = &failures[0];
+    assert_eq!(*node_type, NodeType::Tuic);
+    assert_eq!(register_id, "unknown");
+    assert!(error.is_auth_error());
+}
+
+#[tokio::test]
+async fn test_raw_config_stream() {
+    use futures_util::StreamExt;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    let body = r#"{"data": {"id": 1, "server_port": 443}}"#;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/config"))
+        .and(query_param("node_id", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let stream = client.raw_config_stream(NodeType::Trojan, 1).await.unwrap();
+    tokio::pin!(stream);
+
+    let mut received = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        received.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(received, body.as_bytes());
+
+    let err = client
+        .raw_config_stream(NodeType::Trojan, 2)
+        .await
+        .err()
+        .unwrap();
+    assert!(err.is_server_error());
+}
+
+#[tokio::test]
+async fn test_verify_many() {
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .and(body_partial_json(
+            serde_json::json!({"register_id": "stale"}),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": false}"#))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .and(body_partial_json(
+            serde_json::json!({"register_id": "broken"}),
+        ))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": true}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let results = client
+        .verify_many(NodeType::Trojan, &["live", "stale", "broken", "live-2"], 2)
+        .await;
+
+    let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(ids, vec!["live", "stale", "broken", "live-2"]);
+    assert!(results[0].1.as_ref().unwrap());
+    assert!(!results[1].1.as_ref().unwrap());
+    assert!(results[2].1.as_ref().unwrap_err().is_server_error());
+    assert!(results[3].1.as_ref().unwrap());
+}
+
+#[cfg(feature = "metrics")]
+#[tokio::test]
+async fn test_latency_snapshot() {
+    use common::{FakePanel, REGISTER_ID};
+
+    let panel = FakePanel::start().await;
+    let client = panel.client();
+
+    for _ in 0..3 {
+        client
+            .heartbeat(NodeType::Trojan, REGISTER_ID)
+            .await
+            .unwrap();
+    }
+    client.users(NodeType::Trojan, REGISTER_ID).await.unwrap();
+
+    let snapshot = client.latency_snapshot().await;
+    let heartbeat = snapshot["/api/v1/server/enhanced/trojan/heartbeat"];
+    assert_eq!(heartbeat.count, 3);
+    assert!(heartbeat.min <= heartbeat.p50);
+    assert!(heartbeat.p50 <= heartbeat.p99);
+    assert!(heartbeat.p99 <= heartbeat.max);
+    assert_eq!(snapshot["/api/v1/server/enhanced/trojan/users"].count, 1);
+}
+
+#[tokio::test]
+async fn test_token_rotation() {
+    use server_r_client::RequestObserver;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Default)]
+    struct Persist(Mutex<Vec<String>>);
+
+    impl RequestObserver for Persist {
+        fn on_token_rotated(&self, new_token: &str) {
+            self.0.lock().unwrap().push(new_token.to_string());
+        }
+    }
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .and(query_param("token", "old-token"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("X-New-Token", "new-token")
+                .set_body_string(r#"{"data": {}}"#),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .and(query_param("token", "new-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let persist = Arc::new(Persist::default());
+    let config = Config::new(server.uri(), "old-token").with_observer(persist.clone());
+    let client = ApiClient::new(config).unwrap();
+
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+    assert_eq!(client.token(), "new-token");
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    assert_eq!(*persist.0.lock().unwrap(), vec!["new-token".to_string()]);
+}
+
+#[tokio::test]
+async fn test_token_rotation_ignores_urls_outside_panel() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    // Shares the panel URL as a string prefix, but not as a path
+    Mock::given(method("GET"))
+        .and(path("/panel-echo"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("X-New-Token", "stolen-token")
+                .set_body_string("203.0.113.7"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = Config::new(format!("{}/panel", server.uri()), "test-token")
+        .with_ip_echo_url(format!("{}/panel-echo", server.uri()));
+    let client = ApiClient::new(config).unwrap();
+
+    assert_eq!(client.detect_public_ip().await.unwrap(), "203.0.113.7");
+    assert_eq!(client.token(), "test-token");
+}
+
+#[tokio::test]
+async fn test_path_prefix_slash_handling() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/panel/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(4)
+        .mount(&server)
+        .await;
+
+    for (host, prefix) in [
+        (server.uri(), "/panel"),
+        (server.uri(), "/panel/"),
+        (server.uri(), "panel"),
+        (format!("{}/", server.uri()), "/panel/"),
+    ] {
+        let config = Config::new(host, "test-token").with_path_prefix(prefix);
+        let client = ApiClient::new(config).unwrap();
+        client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_registration_guard_unregisters_on_drop() {
+    use common::{FakePanel, REGISTER_ID};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+
+    let panel = FakePanel::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/unregister"))
+        .and(query_param("register_id", REGISTER_ID))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&panel.server)
+        .await;
+
+    let client = panel.client();
+    let request = || RegisterRequest::new("node.example.com", 443);
+
+    // Defused guards keep the registration
+    let guard = client
+        .register_guarded(NodeType::Trojan, 1, request())
+        .await
+        .unwrap();
+    assert_eq!(guard.defuse(), REGISTER_ID);
+
+    let guard = client
+        .register_guarded(NodeType::Trojan, 1, request())
+        .await
+        .unwrap();
+    assert_eq!(guard.register_id(), REGISTER_ID);
+    drop(guard);
+
+    // The unregister runs on a spawned task
+    for _ in 0..50 {
+        let requests = panel.server.received_requests().await.unwrap();
+        if requests
+            .iter()
+            .any(|r| r.url.path().ends_with("/unregister"))
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("guard did not unregister on drop");
+}
+
+#[tokio::test]
+async fn test_with_http_client_uses_injected_client() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .and(header("x-injected", "yes"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-injected", "yes".parse().unwrap());
+    let http_client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+
+    let client =
+        ApiClient::with_http_client(Config::new(server.uri(), "test-token"), http_client).unwrap();
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    let config = Config::new(server.uri(), "test-token")
+        .with_proxy(Some("socks5://127.0.0.1:1080".to_string()));
+    let err = ApiClient::with_http_client(config, reqwest::Client::new()).unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+
+    let config =
+        Config::new(server.uri(), "test-token").with_connect_timeout(Duration::from_secs(1));
+    let err = ApiClient::with_http_client(config, reqwest::Client::new()).unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+    assert!(err.to_string().contains("connect_timeout"));
+}
+
+#[tokio::test]
+async fn test_users_iter_parses_incrementally() {
+    use common::{FakePanel, REGISTER_ID};
+
+    let panel = FakePanel::start().await;
+    let client = panel.client();
+
+    let ids: Vec<i64> = client
+        .users_iter(NodeType::Trojan, REGISTER_ID)
+        .await
+        .unwrap()
+        .map(|user| user.unwrap().id)
+        .collect();
+    assert_eq!(ids, vec![1, 2]);
+
+    // Same ETag cache as users()
+    let err = client
+        .users_iter(NodeType::Trojan, REGISTER_ID)
+        .await
+        .err()
+        .unwrap();
+    assert!(err.is_not_modified());
+}
+
+#[tokio::test]
+async fn test_users_iter_stops_at_malformed_entry() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"message": "ok", "data": [ {"id": 1, "uuid": "a"} , {"id": "x"}, {"id": 3, "uuid": "c"} ]}"#,
+        ))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let mut users = client.users_iter(NodeType::Trojan, "reg-1").await.unwrap();
+
+    assert_eq!(users.next().unwrap().unwrap().uuid, "a");
+    assert!(users.next().unwrap().unwrap_err().is_parse_error());
+    assert!(users.next().is_none());
+}
+
+#[tokio::test]
+async fn test_verify_detailed_reason() {
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .and(body_partial_json(
+            serde_json::json!({"register_id": "expired"}),
+        ))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": {"valid": false, "reason": "expired"}}"#),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": true}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let detailed = client
+        .verify_detailed(NodeType::Trojan, "expired")
+        .await
+        .unwrap();
+    assert!(!detailed.valid);
+    assert_eq!(detailed.reason.as_deref(), Some("expired"));
+    assert!(!client.verify(NodeType::Trojan, "expired").await.unwrap());
+
+    let detailed = client
+        .verify_detailed(NodeType::Trojan, "ok")
+        .await
+        .unwrap();
+    assert!(detailed.valid);
+    assert_eq!(detailed.reason, None);
+}
+
+#[tokio::test]
+async fn test_query_encoding_form_vs_percent() {
+    use server_r_client::QueryEncoding;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .mount(&server)
+        .await;
+
+    let token = "a b/c";
+    let form = ApiClient::new(Config::new(server.uri(), token)).unwrap();
+    let percent = ApiClient::new(
+        Config::new(server.uri(), token).with_query_encoding(QueryEncoding::Percent),
+    )
+    .unwrap();
+
+    form.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+    percent.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests[0].url.query(), Some("token=a+b%2Fc"));
+    assert_eq!(requests[1].url.query(), Some("token=a%20b%2Fc"));
+
+    // Both decode to the same token
+    for request in &requests {
+        let (_, value) = request.url.query_pairs().next().unwrap();
+        assert_eq!(value, token);
+    }
+}
+
+#[tokio::test]
+async fn test_custom_config_parser() {
+    use wiremock::matchers::{method, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/api/v1/server/enhanced/\w+/config$"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(
+                r#"{"data": {"id": 1, "server_port": 443, "private_option": "x"}}"#,
+            ),
+        )
+        .mount(&server)
+        .await;
+
+    let parser = |data: &[u8]| -> server_r_client::Result<NodeConfigEnum> {
+        let raw: serde_json::Value = serde_json::from_slice(data).unwrap();
+        Ok(NodeConfigEnum::Unknown {
+            type_name: "private-trojan".to_string(),
+            raw,
+        })
+    };
+    let client = ApiClient::new(Config::new(server.uri(), "test-token"))
+        .unwrap()
+        .with_config_parser("Trojan", Box::new(parser));
+
+    let config = client.config(NodeType::Trojan, 1).await.unwrap();
+    assert_eq!(config.node_type_name(), "private-trojan");
+    match config {
+        NodeConfigEnum::Unknown { raw, .. } => assert_eq!(raw["private_option"], "x"),
+        other => panic!("expected custom config, got {:?}", other),
+    }
+
+    // Other node types keep the built-in parser
+    let config = client.config(NodeType::VMess, 1).await.unwrap();
+    assert!(config.as_vmess().is_ok());
+}
+
+#[tokio::test]
+async fn test_config_by_name_custom_parser() {
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/naive/config"))
+        .and(query_param("node_id", "3"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": {"id": 3, "server_port": 8443, "padding": true}}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let parser = |data: &[u8]| -> server_r_client::Result<NodeConfigEnum> {
+        Ok(NodeConfigEnum::Custom {
+            node_type: "naive".to_string(),
+            data: serde_json::from_slice(data).unwrap(),
+        })
+    };
+    let client = ApiClient::new(Config::new(server.uri(), "test-token"))
+        .unwrap()
+        .with_config_parser("Naive", Box::new(parser));
+
+    let config = client.config_by_name("naive", 3).await.unwrap();
+    assert_eq!(config.type_name(), "custom");
+    assert_eq!(config.node_type_name(), "naive");
+    assert_eq!(config.to_string(), "naive[id=3 port=8443]");
+    match config {
+        NodeConfigEnum::Custom { data, .. } => assert_eq!(data["padding"], true),
+        other => panic!("expected custom config, got {:?}", other),
+    }
+
+    // Names that cannot form a path segment are rejected before any request
+    let err = client.config_by_name("naive/../x", 3).await.unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+}
+
+#[tokio::test]
+async fn test_config_base64_wrapped_data() {
+    use base64::Engine;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    let config_json = r#"{"id": 7, "server_port": 443, "server_name": "sni.example.com"}"#;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(config_json);
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/config"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "data": encoded })),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/vmess/config"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "data": "not base64!" })),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let config = client.config(NodeType::Trojan, 7).await.unwrap();
+    let trojan = config.as_trojan().unwrap();
+    assert_eq!(trojan.id, 7);
+    assert_eq!(trojan.primary_server_name(), Some("sni.example.com"));
+
+    let err = client.config(NodeType::VMess, 7).await.unwrap_err();
+    assert!(err.is_parse_error());
+}
+
+#[tokio::test]
+async fn test_max_response_bytes() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    let body = r#"{"data": [{"id": 1, "uuid": "uuid-1"}]}"#;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&server)
+        .await;
+
+    let limited =
+        ApiClient::new(Config::new(server.uri(), "test-token").with_max_response_bytes(16))
+            .unwrap();
+    let err = limited.users(NodeType::Trojan, "reg-1").await.unwrap_err();
+    assert!(err.is_response_too_large());
+    match err {
+        ApiError::ResponseTooLarge { limit, actual, .. } => {
+            assert_eq!(limit, 16);
+            assert_eq!(actual, body.len() as u64);
+        }
+        other => panic!("expected ResponseTooLarge, got {:?}", other),
+    }
+
+    let roomy =
+        ApiClient::new(Config::new(server.uri(), "test-token").with_max_response_bytes(body.len()))
+            .unwrap();
+    assert_eq!(
+        roomy.users(NodeType::Trojan, "reg-1").await.unwrap().len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_submit_json_lines() {
+    use server_r_client::SubmitFormat;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .and(query_param("register_id", "reg-1"))
+        .and(header("Content-Type", "application/x-ndjson"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(
+        Config::new(server.uri(), "test-token").with_submit_format(SubmitFormat::JsonLines),
+    )
+    .unwrap();
+
+    let traffic = vec![
+        UserTraffic::with_count(1, 100, 200, 3),
+        UserTraffic::new(2, 50, 25),
+        UserTraffic::with_count(3, 0, 7, 1),
+    ];
+    client
+        .submit(NodeType::Trojan, "reg-1", traffic.clone())
+        .await
+        .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body = String::from_utf8(requests[0].body.clone()).unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines.len(), traffic.len());
+
+    for (line, expected) in lines.iter().zip(&traffic) {
+        let parsed: UserTraffic = serde_json::from_str(line).unwrap();
+        assert_eq!(parsed.user_id, expected.user_id);
+        assert_eq!(
+            (parsed.u, parsed.d, parsed.n),
+            (expected.u, expected.d, expected.n)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_reregister_starts_with_cold_etag() {
+    use common::{FakePanel, REGISTER_ID};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+
+    let panel = FakePanel::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/verify"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": false}"#))
+        .mount(&panel.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(query_param("register_id", "old-id"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"old\"")
+                .set_body_string(r#"{"data": []}"#),
+        )
+        .mount(&panel.server)
+        .await;
+
+    let client = panel.client();
+
+    // Populate the ETag for the id that will go stale, and for the id the
+    // panel hands out next (as if it had been used before)
+    client.users(NodeType::Trojan, "old-id").await.unwrap();
+    client.users(NodeType::Trojan, REGISTER_ID).await.unwrap();
+    assert!(client.get_etag(NodeType::Trojan, "old-id").await.is_some());
+
+    let register_id = client
+        .ensure_registered(
+            NodeType::Trojan,
+            1,
+            "old-id",
+            RegisterRequest::new("node.example.com", 443),
+        )
+        .await
+        .unwrap();
+    assert_eq!(register_id, REGISTER_ID);
+    assert!(client.get_etag(NodeType::Trojan, "old-id").await.is_none());
+
+    // No 304 from a previously cached ETag
+    let users = client.users(NodeType::Trojan, &register_id).await.unwrap();
+    assert_eq!(users.len(), 2);
+}
+
+#[tokio::test]
+async fn test_users_sync_sends_stored_token() {
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(query_param_is_missing("sync_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"data": {"users": [{"id": 1, "uuid": "a"}, {"id": 2, "uuid": "b"}], "next_token": "t1"}}"#,
+        ))
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(query_param("sync_token", "t1"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"data": {"users": [{"id": 3, "uuid": "c"}], "next_token": "t2"}}"#,
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let full = client.users_sync(NodeType::Trojan, "reg-1").await.unwrap();
+    assert_eq!(full.users.len(), 2);
+    assert_eq!(full.next_token, "t1");
+
+    let delta = client.users_sync(NodeType::Trojan, "reg-1").await.unwrap();
+    assert_eq!(delta.users[0].id, 3);
+    assert_eq!(delta.next_token, "t2");
+
+    // Unregistering forgets the token, so a recycled id starts over
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/unregister"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": null}"#))
+        .mount(&server)
+        .await;
+    client.unregister(NodeType::Trojan, "reg-1").await.unwrap();
+    let full = client.users_sync(NodeType::Trojan, "reg-1").await.unwrap();
+    assert_eq!(full.users.len(), 2);
+}
+
+#[tokio::test]
+async fn test_users_many_deadline() {
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(query_param("register_id", "fast"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(r#"{"data": [{"id": 1, "uuid": "a"}]}"#),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(query_param("register_id", "slow"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": []}"#)
+                .set_delay(Duration::from_secs(2)),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let started = std::time::Instant::now();
+    let results = client
+        .users_many_deadline(
+            &[(NodeType::Trojan, "fast"), (NodeType::Trojan, "slow")],
+            started + Duration::from_millis(300),
+        )
+        .await;
+
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(results[0].1, "fast");
+    assert_eq!(results[0].2.as_ref().unwrap().len(), 1);
+    assert_eq!(results[1].1, "slow");
+    assert!(results[1].2.as_ref().unwrap_err().is_timeout());
+}
+
+#[tokio::test]
+async fn test_warning_observer_receives_messages() {
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": [], "message": "token expires in 3 days"}"#),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": {}, "message": "quota at 90%"}"#),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}, "message": ""}"#))
+        .mount(&server)
+        .await;
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let sink = warnings.clone();
+    let config = Config::new(server.uri(), "test-token").with_warning_observer(Arc::new(
+        move |message: &str| sink.lock().unwrap().push(message.to_string()),
+    ));
+    let client = ApiClient::new(config).unwrap();
+
+    client.users(NodeType::Trojan, "reg-1").await.unwrap();
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+    client
+        .submit(NodeType::Trojan, "reg-1", vec![])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *warnings.lock().unwrap(),
+        vec!["token expires in 3 days", "quota at 90%"]
+    );
+}
+
+#[tokio::test]
+async fn test_export_and_import_state() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/register"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(r#"{"data": {"register_id": "reg-1"}}"#),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": []}"#),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/unregister"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": null}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let request = RegisterRequest::new("node.example.com", 443);
+    let id = client.register(NodeType::Trojan, 1, request).await.unwrap();
+    client.users(NodeType::Trojan, &id).await.unwrap();
+
+    let state = client.export_state().unwrap();
+    assert_eq!(
+        state,
+        serde_json::json!({
+            "registrations": {
+                "trojan": [{"register_id": "reg-1", "etag": "\"v1\""}]
+            }
+        })
+    );
+
+    let restored = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    restored.import_state(state.clone()).unwrap();
+    assert_eq!(
+        restored
+            .get_etag(NodeType::Trojan, "reg-1")
+            .await
+            .as_deref(),
+        Some("\"v1\"")
+    );
+    assert_eq!(restored.export_state().unwrap(), state);
+
+    restored
+        .unregister(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap();
+    assert_eq!(
+        restored.export_state().unwrap(),
+        serde_json::json!({"registrations": {}})
+    );
+
+    let err = restored
+        .import_state(serde_json::json!({"registrations": {"unknown": []}}))
+        .unwrap_err();
+    assert!(matches!(err, ApiError::ParseError { .. }));
+}
+
+#[tokio::test]
+async fn test_warmup_primes_connection() {
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    client.warmup().await.unwrap();
+
+    let unreachable = ApiClient::new(Config::new("http://127.0.0.1:1", "test-token")).unwrap();
+    let err = unreachable.warmup().await.unwrap_err();
+    assert!(matches!(err, ApiError::NetworkError { .. }));
+}
+
+#[tokio::test]
+async fn test_timeout_override_applies_to_node_type_only() {
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"data": null}"#)
+                .set_delay(Duration::from_millis(300)),
+        )
+        .mount(&server)
+        .await;
+
+    let config = Config::new(server.uri(), "test-token")
+        .with_timeout(Duration::from_millis(100))
+        .with_timeout_for(NodeType::VMess, Duration::from_secs(5));
+    assert_eq!(config.timeout_for(NodeType::VMess), Duration::from_secs(5));
+    assert_eq!(
+        config.timeout_for(NodeType::Trojan),
+        Duration::from_millis(100)
+    );
+
+    let client = ApiClient::new(config).unwrap();
+    client.heartbeat(NodeType::VMess, "reg-1").await.unwrap();
+
+    let err = client
+        .heartbeat(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ApiError::NetworkError { .. }));
+}
+
+#[tokio::test]
+async fn test_submit_ack_variants() {
+    use server_r_client::SubmitAck;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"data": {"accepted_count": 2, "server_time": 1700000000, "remaining": 1024}}"#,
+        ))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submitWithAgent"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(r#"{"data": {"accepted_count": 1}}"#),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submitStatsWithAgent"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": null}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let data = vec![UserTraffic::new(1, 100, 200), UserTraffic::new(2, 10, 20)];
+
+    let ack = client
+        .submit_ack(NodeType::Trojan, "reg-1", data.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        ack,
+        SubmitAck {
+            accepted_count: 2,
+            server_time: Some(1700000000),
+            remaining: Some(1024),
+        }
+    );
+
+    let ack = client
+        .submit_with_agent_ack(NodeType::Trojan, "reg-1", data.clone())
+        .await
+        .unwrap();
+    assert_eq!(ack.accepted_count, 1);
+    assert_eq!(ack.remaining, None);
+
+    let ack = client
+        .submit_stats_with_agent_ack(NodeType::Trojan, "reg-1", TrafficStats::from_traffic(&data))
+        .await
+        .unwrap();
+    assert_eq!(ack, SubmitAck::default());
+}
+
+#[tokio::test]
+async fn test_ip_family_restricts_connections() {
+    use server_r_client::IpFamily;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": null}"#))
+        .mount(&server)
+        .await;
+
+    assert_eq!(Config::new(server.uri(), "t").ip_family, IpFamily::Both);
+
+    let v4 =
+        ApiClient::new(Config::new(server.uri(), "t").with_ip_family(IpFamily::V4Only)).unwrap();
+    v4.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    // The mock server only listens on 127.0.0.1
+    let v6 =
+        ApiClient::new(Config::new(server.uri(), "t").with_ip_family(IpFamily::V6Only)).unwrap();
+    let err = v6.heartbeat(NodeType::Trojan, "reg-1").await.unwrap_err();
+    assert!(matches!(err, ApiError::NetworkError { .. }));
+
+    let err = ApiClient::with_http_client(
+        Config::new(server.uri(), "t").with_ip_family(IpFamily::V4Only),
+        reqwest::Client::new(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+}
+
+#[test]
+fn test_preview_url_encodes_params_and_redacts_token() {
+    use server_r_client::QueryEncoding;
+
+    let client = ApiClient::new(
+        Config::new("https://panel.example.com/", "secret-token").with_path_prefix("/panel"),
+    )
+    .unwrap();
+    assert_eq!(
+        client
+            .preview_url(
+                "/api/v1/server/enhanced/trojan/users",
+                &[("register_id", "a b&c")]
+            )
+            .unwrap(),
+        "https://panel.example.com/panel/api/v1/server/enhanced/trojan/users\
+         ?token=REDACTED&register_id=a+b%26c"
+    );
+
+    let client = ApiClient::new(
+        Config::new("https://panel.example.com", "secret-token")
+            .with_query_encoding(QueryEncoding::Percent),
+    )
+    .unwrap();
+    let url = client
+        .preview_url("/api/v1/server/enhanced/trojan/users", &[("q", "a b")])
+        .unwrap();
+    assert_eq!(
+        url,
+        "https://panel.example.com/api/v1/server/enhanced/trojan/users?token=REDACTED&q=a%20b"
+    );
+    assert!(!url.contains("secret-token"));
+}
+
+#[tokio::test]
+async fn test_heartbeat_manager_reports_failures() {
+    use server_r_client::HeartbeatManager;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .and(body_partial_json(serde_json::json!({"register_id": "bad"})))
+        .respond_with(ResponseTemplate::new(500).set_body_string("down"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": null}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let (manager, mut failures) = HeartbeatManager::new(client);
+
+    manager.add(NodeType::Trojan, "good", Duration::from_millis(50));
+    manager.add(NodeType::Trojan, "bad", Duration::from_millis(50));
+    assert_eq!(manager.len(), 2);
+    assert!(manager.contains(NodeType::Trojan, "bad"));
+
+    let failure = tokio::time::timeout(Duration::from_secs(5), failures.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(failure.node_type, NodeType::Trojan);
+    assert_eq!(failure.register_id, "bad");
+    assert!(failure.error.is_server_error());
+
+    assert!(manager.remove(NodeType::Trojan, "bad"));
+    assert!(!manager.remove(NodeType::Trojan, "bad"));
+    assert_eq!(manager.len(), 1);
+
+    manager.shutdown().await;
+    assert!(manager.is_empty());
+
+    let sent = server.received_requests().await.unwrap().len();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), sent);
+    assert_eq!(manager.dropped_failures(), 0);
+}
+
+#[tokio::test]
+async fn test_heartbeat_manager_drops_unread_failures() {
+    use server_r_client::HeartbeatManager;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let (manager, mut failures) = HeartbeatManager::new(client);
+    for i in 0..70 {
+        manager.add(
+            NodeType::Trojan,
+            &format!("reg-{}", i),
+            Duration::from_secs(60),
+        );
+    }
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while manager.dropped_failures() < 6 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    manager.shutdown().await;
+
+    assert_eq!(manager.dropped_failures(), 6);
+    let mut kept = 0;
+    while failures.try_recv().is_ok() {
+        kept += 1;
+    }
+    assert_eq!(kept, 64);
+}
+
+#[tokio::test]
+async fn test_config_or_cached_falls_back_to_cache_file() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/config"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(
+                r#"{"data": {"id": 1, "server_port": 443, "server_name": "a.com"}}"#,
+            ),
+        )
+        .mount(&server)
+        .await;
+
+    let cache_path = std::env::temp_dir().join(format!(
+        "server-r-client-config-cache-{}.json",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&cache_path);
+
+    let offline = ApiClient::new(
+        Config::new("http://127.0.0.1:1", "test-token").with_timeout(Duration::from_secs(1)),
+    )
+    .unwrap();
+    let err = offline
+        .config_or_cached(NodeType::Trojan, 1, &cache_path)
+        .await
+        .unwrap_err();
+    assert!(err.is_network_error());
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let fresh = client
+        .config_or_cached(NodeType::Trojan, 1, &cache_path)
+        .await
+        .unwrap();
+    assert!(cache_path.exists());
+
+    let cached = offline
+        .config_or_cached(NodeType::Trojan, 1, &cache_path)
+        .await
+        .unwrap();
+    assert_eq!(
+        cached.as_trojan().unwrap().server_name,
+        fresh.as_trojan().unwrap().server_name
+    );
+
+    std::fs::remove_file(&cache_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_etag_change_receiver() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v2\"")
+                .set_body_string(r#"{"data": []}"#),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": []}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    // Subscribing through a clone sees updates made by the original
+    let mut changes = client.clone().etag_change_receiver();
+
+    client.users(NodeType::Trojan, "reg-1").await.unwrap();
+    client.users(NodeType::Trojan, "reg-1").await.unwrap();
+
+    assert_eq!(
+        changes.try_recv().unwrap(),
+        ("trojan:reg-1".to_string(), "\"v1\"".to_string())
+    );
+    assert_eq!(
+        changes.try_recv().unwrap(),
+        ("trojan:reg-1".to_string(), "\"v2\"".to_string())
+    );
+    assert!(changes.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_etag_change_receiver_ignores_failed_responses() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(500)
+                .insert_header("ETag", "\"broken\"")
+                .set_body_string("internal error"),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let mut changes = client.etag_change_receiver();
+
+    let err = client.users(NodeType::Trojan, "reg-1").await.unwrap_err();
+    assert!(err.is_server_error());
+    assert!(changes.try_recv().is_err());
+    assert_eq!(client.get_etag(NodeType::Trojan, "reg-1").await, None);
+}
+
+#[tokio::test]
+async fn test_users_force_skips_if_none_match() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v2\"")
+                .set_body_string(r#"{"data": [{"id": 1, "uuid": "abc"}]}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    client
+        .import_state(serde_json::json!({
+            "registrations": {"trojan": [{"register_id": "reg-1", "etag": "\"v1\""}]}
+        }))
+        .unwrap();
+
+    let users = client.users_force(NodeType::Trojan, "reg-1").await.unwrap();
+    assert_eq!(users.len(), 1);
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(!requests[0].headers.contains_key("if-none-match"));
+    assert_eq!(
+        client.get_etag(NodeType::Trojan, "reg-1").await.as_deref(),
+        Some("\"v2\"")
+    );
+}
+
+#[test]
+fn test_malformed_api_host_is_config_error() {
+    let err = ApiClient::new(Config::new("not a url", "test-token")).unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+    assert!(err.to_string().contains("not a url"));
+
+    let client = ApiClient::new(Config::new("https://panel.example.com", "test-token")).unwrap();
+    for path in ["users", "/users?token=x", "/users#top"] {
+        let err = client.preview_url(path, &[]).unwrap_err();
+        assert!(matches!(err, ApiError::ConfigError { .. }));
+        assert!(err
+            .to_string()
+            .contains("Invalid URL 'https://panel.example.com"));
+        assert!(err.to_string().contains(path));
+    }
+}
+
+#[tokio::test]
+async fn test_register_multi() {
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/register"))
+        .and(body_json(serde_json::json!({
+            "node_ids": [1, 2],
+            "hostname": "node.example.com",
+            "port": 443
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"data": [{"node_id": 1, "register_id": "r1"}, {"node_id": 2, "register_id": "r2"}]}"#,
+        ))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/register"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("node 3 is disabled"))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let request = RegisterRequest::new("node.example.com", 443);
+
+    let ids = client
+        .register_multi(NodeType::Trojan, &[1, 2], request.clone())
+        .await
+        .unwrap();
+    assert_eq!(ids, vec![(1, "r1".to_string()), (2, "r2".to_string())]);
+
+    let err = client
+        .register_multi(NodeType::Trojan, &[3, 4], request.clone())
+        .await
+        .unwrap_err();
+    assert!(err.is_server_error());
+    assert!(err.to_string().contains("[3, 4]"));
+
+    let err = client
+        .register_multi(NodeType::Trojan, &[], request)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+}
+
+#[tokio::test]
+async fn test_clock_skew_from_date_header() {
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    let skewed = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(3600));
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Date", skewed.as_str())
+                .set_body_string(r#"{"data": {}}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let sink = warnings.clone();
+    let config = Config::new(server.uri(), "test-token")
+        .with_clock_skew_threshold(Duration::from_secs(60))
+        .with_warning_observer(Arc::new(move |message: &str| {
+            sink.lock().unwrap().push(message.to_string())
+        }));
+    let client = ApiClient::new(config).unwrap();
+    assert_eq!(client.last_server_time_skew(), None);
+
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+    client.heartbeat(NodeType::Trojan, "reg-1").await.unwrap();
+
+    let skew = client.last_server_time_skew().unwrap();
+    assert!(skew >= Duration::from_secs(3595) && skew <= Duration::from_secs(3605));
+
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("off from the panel"));
+}
+
+#[tokio::test]
+async fn test_submit_worker_batches_and_flushes_on_stop() {
+    use tokio::sync::mpsc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let (tx, rx) = mpsc::channel(16);
+    let worker = client
+        .spawn_submit_worker(NodeType::Trojan, "reg-1", rx, Duration::from_secs(60), 2)
+        .unwrap();
+
+    for user_id in 1..=3 {
+        tx.send(UserTraffic::new(user_id, 10, 20)).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+    assert!(worker.stop().await.is_empty());
+
+    let sizes: Vec<usize> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["data"].as_array().unwrap().len()
+        })
+        .collect();
+    assert_eq!(sizes, vec![2, 1]);
+
+    let (_tx, rx) = mpsc::channel(1);
+    let err = client
+        .spawn_submit_worker(NodeType::Trojan, "reg-1", rx, Duration::ZERO, 2)
+        .unwrap_err();
+    assert!(matches!(err, ApiError::ConfigError { .. }));
+}
+
+#[tokio::test]
+async fn test_submit_worker_reports_failures() {
+    use tokio::sync::mpsc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("database unavailable"))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let (tx, rx) = mpsc::channel(16);
+    let mut worker = client
+        .spawn_submit_worker(
+            NodeType::Trojan,
+            "reg-1",
+            rx,
+            Duration::from_millis(50),
+            100,
+        )
+        .unwrap();
+
+    tx.send(UserTraffic::new(1, 10, 20)).await.unwrap();
+    let failure = worker.next_failure().await.unwrap();
+    assert_eq!(failure.traffic.len(), 1);
+    assert!(failure.error.is_server_error());
+
+    drop(tx);
+    assert!(worker.next_failure().await.is_none());
+    assert_eq!(worker.dropped_failures(), 0);
+}
+
+#[tokio::test]
+async fn test_submit_worker_drops_unread_failures() {
+    use tokio::sync::mpsc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let (tx, rx) = mpsc::channel(128);
+    let worker = client
+        .spawn_submit_worker(NodeType::Trojan, "reg-1", rx, Duration::from_secs(60), 1)
+        .unwrap();
+
+    for user_id in 0..70 {
+        tx.send(UserTraffic::new(user_id, 10, 20)).await.unwrap();
+    }
+    drop(tx);
+    while !worker.is_finished() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Only the oldest failures are kept while nobody reads them
+    assert_eq!(worker.dropped_failures(), 6);
+    let failures = worker.stop().await;
+    assert_eq!(failures.len(), 64);
+    assert_eq!(failures[0].traffic[0].user_id, 0);
+}
+
+#[tokio::test]
+async fn test_request_compression_above_threshold() {
+    use std::io::Read;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/submit"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .mount(&server)
+        .await;
+
+    let config = Config::new(server.uri(), "test-token").with_request_compression(true);
+    let client = ApiClient::new(config).unwrap();
+
+    let small = vec![UserTraffic::new(1, 10, 20)];
+    let large: Vec<UserTraffic> = (1..=100).map(|id| UserTraffic::new(id, 10, 20)).collect();
+    client
+        .submit(NodeType::Trojan, "reg-1", small)
+        .await
+        .unwrap();
+    client
+        .submit(NodeType::Trojan, "reg-1", large)
+        .await
+        .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests[0].headers.get("content-encoding").is_none());
+    serde_json::from_slice::<serde_json::Value>(&requests[0].body).unwrap();
+
+    assert_eq!(requests[1].headers.get("content-encoding").unwrap(), "gzip");
+    let mut json = String::new();
+    flate2::read::GzDecoder::new(requests[1].body.as_slice())
+        .read_to_string(&mut json)
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(body["data"].as_array().unwrap().len(), 100);
+}
+
+#[tokio::test]
+async fn test_users_with_status_distinguishes_empty_and_unchanged() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/server/enhanced/trojan/users"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .set_body_string(r#"{"data": []}"#),
+        )
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+
+    let fresh = client
+        .users_with_status(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap();
+    assert!(fresh.is_empty());
+    assert!(!fresh.is_not_modified());
+
+    let unchanged = client
+        .users_with_status(NodeType::Trojan, "reg-1")
+        .await
+        .unwrap();
+    assert!(unchanged.is_not_modified());
+    assert!(unchanged.data.is_empty());
+    assert_eq!(unchanged.etag.as_deref(), Some("\"v1\""));
+}
+
+#[tokio::test]
+async fn test_retry_budget_shared_across_clones() {
+    use futures_util::StreamExt;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/server/enhanced/trojan/heartbeat"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("degraded"))
+        .mount(&server)
+        .await;
+
+    let unlimited = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    assert!(unlimited.try_acquire_retry());
+
+    let config = Config::new(server.uri(), "test-token").with_retry_budget(0.5, 0.0);
+    let client = ApiClient::new(config).unwrap();
+    let clone = client.clone();
+    assert!(!client.try_acquire_retry());
+
+    // Two failed requests earn one retry, shared by every clone
+    for _ in 0..2 {
+        assert!(client.heartbeat(NodeType::Trojan, "reg-1").await.is_err());
+    }
+    assert!(clone.try_acquire_retry());
+    assert!(!client.try_acquire_retry());
+
+    // Without credits, heartbeat_loop falls back to the capped backoff
+    let stream = client.heartbeat_loop(NodeType::Trojan, "reg-1", Duration::from_millis(10));
+    tokio::pin!(stream);
+    assert!(stream.next().await.unwrap().is_err());
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), stream.next())
+            .await
+            .is_err()
+    );
+
+    let invalid = Config::new(server.uri(), "test-token").with_retry_budget(-1.0, 1.0);
+    assert!(matches!(
+        ApiClient::new(invalid).unwrap_err(),
+        ApiError::ConfigError { .. }
+    ));
+}
+
+#[tokio::test]
+async fn test_close_stops_background_workers() {
+    use server_r_client::HeartbeatManager;
+    use tokio::sync::mpsc;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {}}"#))
+        .mount(&server)
+        .await;
+
+    let client = ApiClient::new(Config::new(server.uri(), "test-token")).unwrap();
+    let (manager, _failures) = HeartbeatManager::new(client.clone());
+    manager.add(NodeType::Trojan, "reg-1", Duration::from_millis(50));
+
+    let (tx, rx) = mpsc::channel(16);
+    let worker = client
+        .spawn_submit_worker(NodeType::Trojan, "reg-1", rx, Duration::from_secs(60), 100)
+        .unwrap();
+    tx.send(UserTraffic::new(1, 10, 20)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    tokio::time::timeout(Duration::from_secs(5), client.close())
+        .await
+        .unwrap();
+    assert!(worker.stop().await.is_empty());
+
+    let count = |requests: &[wiremock::Request], name: &str| {
+        requests
+            .iter()
+            .filter(|r| r.url.path().ends_with(name))
+            .count()
+    };
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(count(&requests, "/submit"), 1);
+    let heartbeats = count(&requests, "/heartbeat");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(count(&requests, "/heartbeat"), heartbeats);
+}
+
+#[tokio::test]
+async fn test_register_rejects_invalid_request_before_sending() {
+    use wiremock::MockServer;
+
+    let server = MockServer::start().await;
+    let config = Config::new(server.uri(), "test-token").with_public_hosts_only(true);
+    let client = ApiClient::new(config).unwrap();
+
+    for request in [
+        RegisterRequest::new("", 443),
+        RegisterRequest::new("node.example.com", 0),
+        RegisterRequest::new("192.168.1.10", 443),
+    ] {
+        let err = client
+            .register(NodeType::Trojan, 1, request)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::ConfigError { .. }));
+    }
+    assert!(server.received_requests().await.unwrap().is_empty());
+}